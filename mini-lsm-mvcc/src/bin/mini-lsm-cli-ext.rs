@@ -0,0 +1,391 @@
+//! A CLI for the features that only mini-lsm-mvcc has, e.g. column families, checkpoints and the
+//! storage options beyond the ones of mini-lsm-cli, which is shared with the other crates.
+
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use mini_lsm_mvcc::checkpoint::BackupMode;
+use mini_lsm_mvcc::column_family::{ColumnFamily, ColumnFamilyOptions, DEFAULT_COLUMN_FAMILY_NAME};
+use mini_lsm_mvcc::compact::{
+    CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions,
+    TieredCompactionOptions,
+};
+use mini_lsm_mvcc::compression::{CompressionOptions, CompressionType};
+use mini_lsm_mvcc::filter::{FilterOptions, FilterType};
+use mini_lsm_mvcc::iterators::StorageIterator;
+use mini_lsm_mvcc::lsm_storage::{LsmStorageOptions, MiniLsm};
+use mini_lsm_mvcc::mvcc::HistoryRetention;
+use mini_lsm_mvcc::prefix_extractor::{FixedPrefixExtractor, PrefixExtractor};
+use mini_lsm_mvcc::rate_limiter::RateLimiter;
+use rustyline::DefaultEditor;
+
+#[derive(Debug, Clone, ValueEnum)]
+enum CompactionStrategy {
+    Simple,
+    Leveled,
+    Tiered,
+    None,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Compression {
+    None,
+    Lz4,
+    Snappy,
+    Zstd,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum Filter {
+    Bloom,
+    BlockedBloom,
+    Xor,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "lsm.db")]
+    path: PathBuf,
+    #[arg(long, default_value = "leveled")]
+    compaction: CompactionStrategy,
+    #[arg(long)]
+    enable_wal: bool,
+    #[arg(long)]
+    serializable: bool,
+    #[arg(long, default_value = "none")]
+    compression: Compression,
+    #[arg(long, default_value = "bloom")]
+    filter: Filter,
+    #[arg(long, default_value = "10")]
+    filter_bits_per_key: usize,
+    #[arg(long)]
+    value_log_threshold: Option<usize>,
+    #[arg(long, default_value = "1048576")]
+    max_manifest_size: usize,
+    #[arg(long, default_value = "1")]
+    max_subcompactions: usize,
+    #[arg(long)]
+    rate_limit_bytes_per_sec: Option<u64>,
+    #[arg(long, default_value = "4294967296")]
+    block_cache_capacity_bytes: u64,
+    #[arg(long)]
+    index_partition_size: Option<usize>,
+    #[arg(long)]
+    prefix_length: Option<usize>,
+    #[arg(long)]
+    history_retention_secs: Option<u64>,
+}
+
+impl Args {
+    fn storage_options(&self) -> LsmStorageOptions {
+        let compaction_options = match self.compaction {
+            CompactionStrategy::None => CompactionOptions::NoCompaction,
+            CompactionStrategy::Simple => {
+                CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                    size_ratio_percent: 200,
+                    level0_file_num_compaction_trigger: 2,
+                    max_levels: 4,
+                })
+            }
+            CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                num_tiers: 3,
+                max_size_amplification_percent: 200,
+                size_ratio: 1,
+                min_merge_width: 2,
+            }),
+            CompactionStrategy::Leveled => CompactionOptions::Leveled(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 128,
+                level_size_multiplier: 2,
+            }),
+        };
+        let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+        options.block_size = 4096;
+        options.target_sst_size = 2 << 20; // 2MB
+        options.num_memtable_limit = 3;
+        options.enable_wal = self.enable_wal;
+        options.serializable = self.serializable;
+        options.compression = CompressionOptions::all_levels(match self.compression {
+            Compression::None => CompressionType::None,
+            Compression::Lz4 => CompressionType::Lz4,
+            Compression::Snappy => CompressionType::Snappy,
+            Compression::Zstd => CompressionType::Zstd(3),
+        });
+        options.filter = FilterOptions::all_levels(match self.filter {
+            Filter::Bloom => FilterType::Bloom(self.filter_bits_per_key),
+            Filter::BlockedBloom => FilterType::BlockedBloom(self.filter_bits_per_key),
+            Filter::Xor => FilterType::Xor8,
+        });
+        options.value_log_threshold = self.value_log_threshold;
+        options.max_manifest_size = self.max_manifest_size;
+        options.max_subcompactions = self.max_subcompactions;
+        options.rate_limiter = self
+            .rate_limit_bytes_per_sec
+            .map(|bytes_per_sec| Arc::new(RateLimiter::new(bytes_per_sec)));
+        options.block_cache_capacity_bytes = self.block_cache_capacity_bytes;
+        options.index_partition_size = self.index_partition_size;
+        options.prefix_extractor = self
+            .prefix_length
+            .map(|len| Arc::new(FixedPrefixExtractor::new(len)) as Arc<dyn PrefixExtractor>);
+        options.history_retention = self
+            .history_retention_secs
+            .map(|secs| HistoryRetention::Duration(Duration::from_secs(secs)));
+        options
+    }
+}
+
+struct ReplHandler {
+    lsm: Arc<MiniLsm>,
+    /// The column family that the commands read and write.
+    cf: Arc<ColumnFamily>,
+    /// The options of the column families created by the `cf` command.
+    cf_options: ColumnFamilyOptions,
+}
+
+impl ReplHandler {
+    fn handle(&mut self, command: &Command) -> Result<()> {
+        match command {
+            Command::Put { key, value } => {
+                self.lsm
+                    .put_cf(&self.cf, key.as_bytes(), value.as_bytes())?;
+                println!("{}={:?} put", key, value);
+            }
+            Command::Del { key } => {
+                self.lsm.delete_cf(&self.cf, key.as_bytes())?;
+                println!("{} deleted", key);
+            }
+            Command::Get { key } => {
+                if let Some(value) = self.lsm.get_cf(&self.cf, key.as_bytes())? {
+                    println!("{}={:?}", key, value);
+                } else {
+                    println!("{} not exist", key);
+                }
+            }
+            Command::Scan { begin, end } => {
+                let (lower, upper) = match (begin, end) {
+                    (Some(begin), Some(end)) => (
+                        Bound::Included(begin.as_bytes()),
+                        Bound::Included(end.as_bytes()),
+                    ),
+                    _ => (Bound::Unbounded, Bound::Unbounded),
+                };
+                let mut iter = self.lsm.scan_cf(&self.cf, lower, upper)?;
+                let mut cnt = 0;
+                while iter.is_valid() {
+                    println!(
+                        "{:?}={:?}",
+                        Bytes::copy_from_slice(iter.key()),
+                        Bytes::copy_from_slice(iter.value()),
+                    );
+                    iter.next()?;
+                    cnt += 1;
+                }
+                println!();
+                println!("{} keys scanned", cnt);
+            }
+            Command::Cf { name: None } => {
+                println!("column family {}", self.cf.name());
+            }
+            Command::Cf { name: Some(name) } => {
+                self.cf = match self.lsm.column_family(name) {
+                    Some(cf) => cf,
+                    None => {
+                        let cf = self
+                            .lsm
+                            .create_column_family(name, self.cf_options.clone())?;
+                        println!("column family {} created", name);
+                        cf
+                    }
+                };
+                println!("using column family {}", name);
+            }
+            Command::Checkpoint { dir } => {
+                let stats = self.lsm.create_checkpoint(dir)?;
+                println!("checkpoint at ts {} created: {:?}", stats.commit_ts, stats);
+            }
+            Command::Backup { dir } => {
+                let stats = self.lsm.create_backup(dir, BackupMode::Incremental)?;
+                println!("backup at ts {} created: {:?}", stats.commit_ts, stats);
+            }
+            Command::Dump => {
+                self.lsm.dump_column_families();
+                println!("dump success");
+            }
+            Command::Flush => {
+                self.lsm.force_flush()?;
+                println!("flush success");
+            }
+            Command::Quit | Command::Close => {
+                self.lsm.close()?;
+                std::process::exit(0);
+            }
+        };
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Put {
+        key: String,
+        value: String,
+    },
+    Del {
+        key: String,
+    },
+    Get {
+        key: String,
+    },
+    Scan {
+        begin: Option<String>,
+        end: Option<String>,
+    },
+    /// Show the current column family, or switch to a column family and create it if it does not
+    /// exist.
+    Cf {
+        name: Option<String>,
+    },
+    /// Create a checkpoint in an empty directory.
+    Checkpoint {
+        dir: String,
+    },
+    /// Back up to a directory, only copying the files that it does not have.
+    Backup {
+        dir: String,
+    },
+
+    Dump,
+    Flush,
+    Quit,
+    Close,
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Self> {
+        use nom::bytes::complete::*;
+        use nom::character::complete::*;
+
+        use nom::branch::*;
+        use nom::combinator::*;
+        use nom::sequence::*;
+
+        let string = |i| {
+            map(
+                take_till1::<_, &str, nom::error::Error<_>>(|c: char| c.is_whitespace()),
+                |s: &str| s.to_string(),
+            )(i)
+        };
+
+        let put = |i| {
+            map(
+                tuple((tag_no_case("put"), space1, string, space1, string)),
+                |(_, _, key, _, value)| Command::Put { key, value },
+            )(i)
+        };
+
+        let del = |i| {
+            map(
+                tuple((tag_no_case("del"), space1, string)),
+                |(_, _, key)| Command::Del { key },
+            )(i)
+        };
+
+        let get = |i| {
+            map(
+                tuple((tag_no_case("get"), space1, string)),
+                |(_, _, key)| Command::Get { key },
+            )(i)
+        };
+
+        let scan = |i| {
+            map(
+                tuple((
+                    tag_no_case("scan"),
+                    opt(tuple((space1, string, space1, string))),
+                )),
+                |(_, opt_args)| {
+                    let (begin, end) = opt_args
+                        .map_or((None, None), |(_, begin, _, end)| (Some(begin), Some(end)));
+                    Command::Scan { begin, end }
+                },
+            )(i)
+        };
+
+        let cf = |i| {
+            map(
+                tuple((tag_no_case("cf"), opt(tuple((space1, string))))),
+                |(_, name)| Command::Cf {
+                    name: name.map(|(_, name)| name),
+                },
+            )(i)
+        };
+
+        let checkpoint = |i| {
+            map(
+                tuple((tag_no_case("checkpoint"), space1, string)),
+                |(_, _, dir)| Command::Checkpoint { dir },
+            )(i)
+        };
+
+        let backup = |i| {
+            map(
+                tuple((tag_no_case("backup"), space1, string)),
+                |(_, _, dir)| Command::Backup { dir },
+            )(i)
+        };
+
+        let command = |i| {
+            alt((
+                put,
+                del,
+                get,
+                scan,
+                checkpoint,
+                cf,
+                backup,
+                map(tag_no_case("dump"), |_| Command::Dump),
+                map(tag_no_case("flush"), |_| Command::Flush),
+                map(tag_no_case("quit"), |_| Command::Quit),
+                map(tag_no_case("close"), |_| Command::Close),
+            ))(i)
+        };
+
+        command(input)
+            .map(|(_, c)| c)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let options = args.storage_options();
+    let cf_options = options.column_family_options();
+    let lsm = MiniLsm::open(&args.path, options)?;
+    let mut handler = ReplHandler {
+        cf: lsm.column_family(DEFAULT_COLUMN_FAMILY_NAME).unwrap(),
+        cf_options,
+        lsm,
+    };
+
+    println!("Welcome to mini-lsm-cli-ext!");
+    println!("A CLI for the column families, checkpoints and backups of mini-lsm-mvcc");
+    println!();
+    let mut editor = DefaultEditor::new()?;
+    loop {
+        let readline = editor.readline("mini-lsm-cli-ext> ")?;
+        if readline.trim().is_empty() {
+            // Skip noop
+            continue;
+        }
+        let command = Command::parse(&readline)?;
+        handler.handle(&command)?;
+        editor.add_history_entry(readline)?;
+    }
+}
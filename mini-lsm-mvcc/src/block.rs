@@ -1,43 +1,154 @@
 mod builder;
 mod iterator;
 
+use anyhow::{bail, Context, Result};
 pub use builder::BlockBuilder;
 use bytes::{Buf, BufMut, Bytes};
 pub use iterator::BlockIterator;
 
+use crate::value_type::ValueType;
+use crate::varint::LengthEncoding;
+
 pub(crate) const SIZEOF_U16: usize = std::mem::size_of::<u16>();
+pub(crate) const SIZEOF_U32: usize = std::mem::size_of::<u32>();
+
+/// How the entries of a block are encoded, which depends on the format version of its SST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// `u16` lengths and offsets, without value types. An empty value is a delete tombstone.
+    Untyped,
+    /// Varint lengths and `u32` offsets, with value types.
+    Varint,
+}
+
+impl BlockFormat {
+    pub(crate) fn has_value_types(self) -> bool {
+        self != Self::Untyped
+    }
+
+    pub(crate) fn lengths(self) -> LengthEncoding {
+        match self {
+            Self::Untyped => LengthEncoding::U16,
+            Self::Varint => LengthEncoding::Varint,
+        }
+    }
+
+    /// The size of an entry offset, and of the number of entries at the end of the block.
+    pub(crate) fn offset_len(self) -> usize {
+        match self {
+            Self::Untyped => SIZEOF_U16,
+            Self::Varint => SIZEOF_U32,
+        }
+    }
+}
 
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
 pub struct Block {
     pub(crate) data: Vec<u8>,
-    pub(crate) offsets: Vec<u16>,
+    pub(crate) offsets: Vec<u32>,
+    pub(crate) format: BlockFormat,
 }
 
 impl Block {
     pub fn encode(&self) -> Bytes {
         let mut buf = self.data.clone();
         let offsets_len = self.offsets.len();
+        let put_offset = |buf: &mut Vec<u8>, offset: usize| match self.format {
+            BlockFormat::Untyped => buf.put_u16(offset as u16),
+            BlockFormat::Varint => buf.put_u32(offset as u32),
+        };
         for offset in &self.offsets {
-            buf.put_u16(*offset);
+            put_offset(&mut buf, *offset as usize);
         }
         // Adds number of elements at the end of the block
-        buf.put_u16(offsets_len as u16);
+        put_offset(&mut buf, offsets_len);
         buf.into()
     }
 
     pub fn decode(data: &[u8]) -> Self {
+        Self::decode_with_format(data, BlockFormat::Varint).expect("corrupted block")
+    }
+
+    /// Decode a block in the given format, which is an older one for the SSTs in older formats.
+    /// The entries are checked, so that a corrupted block is an error here instead of a panic in
+    /// `BlockIterator`.
+    pub fn decode_with_format(data: &[u8], format: BlockFormat) -> Result<Self> {
+        let offset_len = format.offset_len();
+        let get_offset = |mut buf: &[u8]| match format {
+            BlockFormat::Untyped => buf.get_u16() as u32,
+            BlockFormat::Varint => buf.get_u32(),
+        };
+        if data.len() < offset_len {
+            bail!("corrupted block: too short");
+        }
         // get number of elements in the block
-        let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
-        let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
-        let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
+        let entry_offsets_len = get_offset(&data[data.len() - offset_len..]) as usize;
+        let data_end = (data.len() - offset_len)
+            .checked_sub(entry_offsets_len * offset_len)
+            .context("corrupted block: too many entries")?;
+        let offsets_raw = &data[data_end..data.len() - offset_len];
         // get offset array
-        let offsets = offsets_raw
-            .chunks(SIZEOF_U16)
-            .map(|mut x| x.get_u16())
-            .collect();
+        let offsets = offsets_raw.chunks(offset_len).map(get_offset).collect();
         // retrieve data
         let data = data[0..data_end].to_vec();
-        Self { data, offsets }
+        let block = Self {
+            data,
+            offsets,
+            format,
+        };
+        block.check_entries()?;
+        Ok(block)
+    }
+
+    /// Check that every entry can be read: it is within the block, its key overlap is within the
+    /// first key, and its value type is known and matches the value.
+    fn check_entries(&self) -> Result<()> {
+        if self.offsets.is_empty() {
+            bail!("corrupted block: no entries");
+        }
+        let lengths = self.format.lengths();
+        let mut first_key_len = 0;
+        for (idx, offset) in self.offsets.iter().enumerate() {
+            let mut entry = self
+                .data
+                .get(*offset as usize..)
+                .context("corrupted block: entry offset out of range")?;
+            let overlap = lengths.get(&mut entry)?;
+            let key_len = lengths.get(&mut entry)?;
+            if idx == 0 {
+                first_key_len = key_len;
+            }
+            if (idx == 0 && overlap != 0) || overlap > first_key_len {
+                bail!("corrupted block: key overlap out of range");
+            }
+            take(&mut entry, key_len + std::mem::size_of::<u64>())?;
+            let value_type = if self.format.has_value_types() {
+                Some(ValueType::from_u8(take(&mut entry, 1)?[0])?)
+            } else {
+                None
+            };
+            let value_len = lengths.get(&mut entry)?;
+            let value = take(&mut entry, value_len)?;
+            if let Some(value_type) = value_type {
+                value_type.check_value(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The size of the block in memory.
+    pub fn size(&self) -> usize {
+        self.data.len() + self.offsets.len() * SIZEOF_U32
+    }
+}
+
+/// Take `len` bytes from the front of an entry.
+fn take<'a>(entry: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if entry.len() < len {
+        bail!("corrupted block: entry out of range");
     }
+    let (taken, rest) = entry.split_at(len);
+    *entry = rest;
+    Ok(taken)
 }
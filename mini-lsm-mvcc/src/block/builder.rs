@@ -1,19 +1,22 @@
 use bytes::BufMut;
 
 use crate::key::{KeySlice, KeyVec};
+use crate::value_type::ValueType;
 
-use super::{Block, SIZEOF_U16};
+use super::{Block, BlockFormat};
 
 /// Builds a block.
 pub struct BlockBuilder {
     /// Offsets of each key-value entries.
-    offsets: Vec<u16>,
+    offsets: Vec<u32>,
     /// All serialized key-value pairs in the block.
     data: Vec<u8>,
     /// The expected block size.
     block_size: usize,
     /// The first key in the block
     first_key: KeyVec,
+    /// The format of the entries, an older one only for the SSTs written in an older format.
+    format: BlockFormat,
 }
 
 fn compute_overlap(first_key: KeySlice, key: KeySlice) -> usize {
@@ -33,41 +36,67 @@ fn compute_overlap(first_key: KeySlice, key: KeySlice) -> usize {
 impl BlockBuilder {
     /// Creates a new block builder.
     pub fn new(block_size: usize) -> Self {
+        Self::new_with_format(block_size, BlockFormat::Varint)
+    }
+
+    /// Creates a new block builder, which writes the block in the given format.
+    pub(crate) fn new_with_format(block_size: usize, format: BlockFormat) -> Self {
         Self {
             offsets: Vec::new(),
             data: Vec::new(),
             block_size,
             first_key: KeyVec::new(),
+            format,
         }
     }
 
     fn estimated_size(&self) -> usize {
-        SIZEOF_U16 /* number of key-value pairs in the block */ +  self.offsets.len() * SIZEOF_U16 /* offsets */ + self.data.len()
+        let offset_len = self.format.offset_len();
+        offset_len /* number of key-value pairs in the block */ +  self.offsets.len() * offset_len /* offsets */ + self.data.len()
         // key-value pairs
     }
 
-    /// Adds a key-value pair to the block. Returns false when the block is full.
+    /// Adds a key-value pair to the block. Returns false when the block is full. An empty value
+    /// is added as a delete tombstone.
     #[must_use]
     pub fn add(&mut self, key: KeySlice, value: &[u8]) -> bool {
+        self.add_with_type(key, ValueType::of_untyped(value), value)
+    }
+
+    /// Adds a record of a key to the block. Returns false when the block is full.
+    #[must_use]
+    pub fn add_with_type(&mut self, key: KeySlice, value_type: ValueType, value: &[u8]) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
-        if self.estimated_size() + key.raw_len() + value.len() + SIZEOF_U16 * 3 /* key_len, value_len and offset */ > self.block_size
-            && !self.is_empty()
-        {
+        let overlap = compute_overlap(self.first_key.as_key_slice(), key);
+        let lengths = self.format.lengths();
+        let rest_len = key.key_len() - overlap;
+        let value_type_len = if self.format.has_value_types() { 1 } else { 0 };
+        // The key is stored without its overlap with the first key.
+        let entry_size =
+            lengths.encoded_len(overlap) + lengths.encoded_len(rest_len) + key.raw_len() - overlap
+                + value_type_len
+                + lengths.encoded_len(value.len())
+                + value.len()
+                + self.format.offset_len();
+        if self.estimated_size() + entry_size > self.block_size && !self.is_empty() {
             return false;
         }
         // Add the offset of the data into the offset array.
-        self.offsets.push(self.data.len() as u16);
-        let overlap = compute_overlap(self.first_key.as_key_slice(), key);
+        self.offsets.push(self.data.len() as u32);
         // Encode key overlap.
-        self.data.put_u16(overlap as u16);
+        lengths.put(&mut self.data, overlap);
         // Encode key length.
-        self.data.put_u16((key.key_len() - overlap) as u16);
+        lengths.put(&mut self.data, rest_len);
         // Encode key content.
         self.data.put(&key.key_ref()[overlap..]);
         // Encode key ts
         self.data.put_u64(key.ts());
+        // Encode value type.
+        if self.format.has_value_types() {
+            self.data.put_u8(value_type as u8);
+        }
         // Encode value length.
-        self.data.put_u16(value.len() as u16);
+        lengths.put(&mut self.data, value.len());
         // Encode value content.
         self.data.put(value);
 
@@ -91,6 +120,7 @@ impl BlockBuilder {
         Block {
             data: self.data,
             offsets: self.offsets,
+            format: self.format,
         }
     }
 }
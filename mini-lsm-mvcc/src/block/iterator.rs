@@ -3,8 +3,9 @@ use std::sync::Arc;
 use bytes::Buf;
 
 use crate::{
-    block::SIZEOF_U16,
     key::{KeySlice, KeyVec},
+    value_type::ValueType,
+    varint::LengthEncoding,
 };
 
 use super::Block;
@@ -17,17 +18,27 @@ pub struct BlockIterator {
     key: KeyVec,
     /// the current value range in the block.data, corresponds to the current key
     value_range: (usize, usize),
+    /// the type of the current value
+    value_type: ValueType,
     /// the current index at the iterator position
     idx: usize,
     /// the first key in the block
     first_key: KeyVec,
 }
 
+/// Read a length of an entry, which is checked when the block is decoded.
+fn get_len(lengths: LengthEncoding, buf: &mut &[u8]) -> usize {
+    lengths
+        .get(buf)
+        .expect("lengths are checked when a block is decoded")
+}
+
 impl Block {
     fn get_first_key(&self) -> KeyVec {
+        let lengths = self.format.lengths();
         let mut buf = &self.data[..];
-        buf.get_u16();
-        let key_len = buf.get_u16() as usize;
+        get_len(lengths, &mut buf);
+        let key_len = get_len(lengths, &mut buf);
         let key = &buf[..key_len];
         buf.advance(key_len);
         KeyVec::from_vec_with_ts(key.to_vec(), buf.get_u64())
@@ -41,6 +52,7 @@ impl BlockIterator {
             block,
             key: KeyVec::new(),
             value_range: (0, 0),
+            value_type: ValueType::Delete,
             idx: 0,
         }
     }
@@ -52,6 +64,13 @@ impl BlockIterator {
         iter
     }
 
+    /// Creates a block iterator and seek to the last entry.
+    pub fn create_and_seek_to_last(block: Arc<Block>) -> Self {
+        let mut iter = Self::new(block);
+        iter.seek_to_last();
+        iter
+    }
+
     /// Creates a block iterator and seek to the first key that >= `key`.
     pub fn create_and_seek_to_key(block: Arc<Block>, key: KeySlice) -> Self {
         let mut iter = Self::new(block);
@@ -71,6 +90,12 @@ impl BlockIterator {
         &self.block.data[self.value_range.0..self.value_range.1]
     }
 
+    /// Returns the type of the current entry.
+    pub fn value_type(&self) -> ValueType {
+        debug_assert!(!self.key.is_empty(), "invalid iterator");
+        self.value_type
+    }
+
     /// Returns true if the iterator is valid.
     pub fn is_valid(&self) -> bool {
         !self.key.is_empty()
@@ -81,6 +106,11 @@ impl BlockIterator {
         self.seek_to(0);
     }
 
+    /// Seeks to the last key in the block.
+    pub fn seek_to_last(&mut self) {
+        self.seek_to(self.block.offsets.len() - 1);
+    }
+
     /// Seeks to the idx-th key in the block.
     fn seek_to(&mut self, idx: usize) {
         if idx >= self.block.offsets.len() {
@@ -99,14 +129,25 @@ impl BlockIterator {
         self.seek_to(self.idx);
     }
 
+    /// Move to the previous key in the block.
+    pub fn prev(&mut self) {
+        if self.idx == 0 {
+            self.key.clear();
+            self.value_range = (0, 0);
+            return;
+        }
+        self.idx -= 1;
+        self.seek_to(self.idx);
+    }
+
     /// Seek to the specified position and update the current `key` and `value`
     /// Index update will be handled by caller
     fn seek_to_offset(&mut self, offset: usize) {
+        let lengths = self.block.format.lengths();
         let mut entry = &self.block.data[offset..];
-        // Since `get_u16()` will automatically move the ptr 2 bytes ahead here,
-        // we don't need to manually advance it
-        let overlap_len = entry.get_u16() as usize;
-        let key_len = entry.get_u16() as usize;
+        // Reading the lengths moves the slice ahead, so we don't need to manually advance it
+        let overlap_len = get_len(lengths, &mut entry);
+        let key_len = get_len(lengths, &mut entry);
         let key = &entry[..key_len];
         self.key.clear();
         self.key.append(&self.first_key.key_ref()[..overlap_len]);
@@ -114,13 +155,22 @@ impl BlockIterator {
         entry.advance(key_len);
         let ts = entry.get_u64();
         self.key.set_ts(ts);
-        let value_len = entry.get_u16() as usize;
-        // REMEMBER TO CHANGE THIS every time you change the encoding!
-        let value_offset_begin =
-            offset + SIZEOF_U16 + SIZEOF_U16 + std::mem::size_of::<u64>() + key_len + SIZEOF_U16;
+        let value_type = if self.block.format.has_value_types() {
+            Some(entry.get_u8())
+        } else {
+            None
+        };
+        let value_len = get_len(lengths, &mut entry);
+        // The value starts where the rest of the entry does, as the lengths have a variable size.
+        let value_offset_begin = self.block.data.len() - entry.len();
         let value_offset_end = value_offset_begin + value_len;
         self.value_range = (value_offset_begin, value_offset_end);
         entry.advance(value_len);
+        self.value_type = match value_type {
+            Some(value_type) => ValueType::from_u8(value_type)
+                .expect("value types are checked when a block is decoded"),
+            None => ValueType::of_untyped(self.value()),
+        };
     }
 
     /// Seek to the first key that is >= `key`.
@@ -0,0 +1,200 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use moka::sync::{Cache, ConcurrentCacheExt};
+
+use crate::block::Block;
+use crate::table::{IndexPartition, SsTable};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BlockId {
+    Data(usize),
+    IndexPartition(usize),
+}
+
+/// The key of a cached block: the namespace of the storage, the SST id and the block.
+type BlockCacheKey = (u64, usize, BlockId);
+
+#[derive(Clone)]
+enum CachedBlock {
+    Data(Arc<Block>),
+    IndexPartition(Arc<IndexPartition>),
+}
+
+impl CachedBlock {
+    fn size(&self) -> usize {
+        match self {
+            CachedBlock::Data(block) => block.size(),
+            CachedBlock::IndexPartition(partition) => partition.size(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Blocks that were evicted because the cache was full. Blocks of removed SSTs are not counted.
+    pub evictions: u64,
+    pub entry_count: u64,
+    /// The total size of the cached blocks in bytes.
+    pub weighted_size: u64,
+}
+
+struct BlockCacheInner {
+    cache: Cache<BlockCacheKey, CachedBlock>,
+    capacity_bytes: u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: Arc<AtomicU64>,
+    next_namespace: AtomicU64,
+}
+
+/// A cache of decompressed data blocks and index partitions, weighted by their size in bytes. The
+/// bloom filter and the unpartitioned block meta of an SST are not cached, as they are always held
+/// in memory by `SsTable`.
+///
+/// A cache can be shared by several storages with `share`. Each storage has its own namespace, as
+/// the SST ids of different storages overlap.
+pub struct BlockCache {
+    inner: Arc<BlockCacheInner>,
+    namespace: u64,
+}
+
+impl std::fmt::Debug for BlockCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockCache")
+            .field("capacity_bytes", &self.inner.capacity_bytes)
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+impl BlockCache {
+    pub fn new(capacity_bytes: u64) -> Self {
+        let evictions = Arc::new(AtomicU64::new(0));
+        let cache = {
+            let evictions = evictions.clone();
+            Cache::builder()
+                .max_capacity(capacity_bytes)
+                .weigher(|_, block: &CachedBlock| block.size().try_into().unwrap_or(u32::MAX))
+                .eviction_listener(move |_, _, cause| {
+                    if cause.was_evicted() {
+                        evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+                .build()
+        };
+        Self {
+            inner: Arc::new(BlockCacheInner {
+                cache,
+                capacity_bytes,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions,
+                next_namespace: AtomicU64::new(1),
+            }),
+            namespace: 0,
+        }
+    }
+
+    /// A handle to the same cache with a new namespace, for another storage.
+    pub fn share(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            namespace: self.inner.next_namespace.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn capacity_bytes(&self) -> u64 {
+        self.inner.capacity_bytes
+    }
+
+    pub fn get(&self, sst_id: usize, block_idx: usize) -> Option<Arc<Block>> {
+        match self
+            .inner
+            .cache
+            .get(&(self.namespace, sst_id, BlockId::Data(block_idx)))
+        {
+            Some(CachedBlock::Data(block)) => Some(block),
+            _ => None,
+        }
+    }
+
+    fn try_get_block_with(
+        &self,
+        sst_id: usize,
+        block_id: BlockId,
+        init: impl FnOnce() -> Result<CachedBlock>,
+    ) -> Result<CachedBlock> {
+        let mut missed = false;
+        let block = self
+            .inner
+            .cache
+            .try_get_with((self.namespace, sst_id, block_id), || {
+                missed = true;
+                init()
+            })
+            .map_err(|e| anyhow!("{}", e))?;
+        if missed {
+            self.inner.misses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.inner.hits.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(block)
+    }
+
+    /// Get a data block, or read it with `init` on a miss.
+    pub fn try_get_with(
+        &self,
+        sst_id: usize,
+        block_idx: usize,
+        init: impl FnOnce() -> Result<Arc<Block>>,
+    ) -> Result<Arc<Block>> {
+        match self.try_get_block_with(sst_id, BlockId::Data(block_idx), || {
+            init().map(CachedBlock::Data)
+        })? {
+            CachedBlock::Data(block) => Ok(block),
+            CachedBlock::IndexPartition(_) => unreachable!(),
+        }
+    }
+
+    /// Get an index partition, or read it with `init` on a miss.
+    pub fn try_get_index_partition_with(
+        &self,
+        sst_id: usize,
+        partition_idx: usize,
+        init: impl FnOnce() -> Result<Arc<IndexPartition>>,
+    ) -> Result<Arc<IndexPartition>> {
+        match self.try_get_block_with(sst_id, BlockId::IndexPartition(partition_idx), || {
+            init().map(CachedBlock::IndexPartition)
+        })? {
+            CachedBlock::IndexPartition(partition) => Ok(partition),
+            CachedBlock::Data(_) => unreachable!(),
+        }
+    }
+
+    /// Remove the blocks of an SST, e.g. when it is removed by compaction.
+    pub fn invalidate_sst(&self, sst: &SsTable) {
+        let data_blocks = (0..sst.num_of_blocks()).map(BlockId::Data);
+        let index_partitions = (0..sst.num_of_index_partitions()).map(BlockId::IndexPartition);
+        for block_id in data_blocks.chain(index_partitions) {
+            self.inner
+                .cache
+                .invalidate(&(self.namespace, sst.sst_id(), block_id));
+        }
+    }
+
+    /// The statistics of the whole cache, including the other storages that share it.
+    pub fn stats(&self) -> BlockCacheStats {
+        self.inner.cache.sync();
+        BlockCacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+            entry_count: self.inner.cache.entry_count(),
+            weighted_size: self.inner.cache.weighted_size(),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+
+use crate::column_family::ColumnFamily;
+use crate::key::KeyBytes;
+use crate::lsm_storage::LsmStorageInner;
+use crate::manifest::Manifest;
+use crate::rate_limiter::IoPriority;
+use crate::value_type::ValueType;
+
+/// How the files of the database get into a backup directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupMode {
+    /// Copy all files, replacing the ones of an earlier backup.
+    Full,
+    /// Only copy the files that an earlier backup in the directory does not have. SSTs never
+    /// change once written, so an SST of the same name and size is the same file.
+    Incremental,
+}
+
+/// What a checkpoint or a backup did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckpointStats {
+    /// The checkpoint has all transactions committed up to this ts, and none after it.
+    pub commit_ts: u64,
+    /// The files hard-linked into a checkpoint.
+    pub linked_files: usize,
+    /// The files copied into the directory.
+    pub copied_files: usize,
+    /// The files that an incremental backup did not copy, as the directory has them already.
+    pub skipped_files: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    HardLink,
+    Copy(BackupMode),
+}
+
+/// A file to copy into the directory after the locks are released. It is opened with the locks
+/// held, so that it can still be read if compaction or value log GC removes it in the meantime.
+struct PendingCopy {
+    file: File,
+    len: u64,
+    dest: PathBuf,
+}
+
+impl PendingCopy {
+    /// Copy the file through a temporary file, so that the destination is either the old file or
+    /// the complete new one.
+    fn copy(self) -> Result<()> {
+        let mut tmp_name = self.dest.file_name().unwrap().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp = self.dest.with_file_name(tmp_name);
+        let mut out = File::create(&tmp)?;
+        let copied = std::io::copy(&mut (&self.file).take(self.len), &mut out)?;
+        if copied != self.len {
+            bail!("{} is shorter than expected", self.dest.display());
+        }
+        out.flush()?;
+        out.sync_all()?;
+        std::fs::rename(&tmp, &self.dest)?;
+        Ok(())
+    }
+}
+
+/// The file name that a checkpoint cleans up. Other files in a backup directory are left alone.
+fn is_database_file(name: &str) -> bool {
+    name.ends_with(".sst") || name.ends_with(".vlog") || name.ends_with(".tmp")
+}
+
+fn is_empty_dir(dir: &Path) -> Result<bool> {
+    Ok(!dir.exists() || std::fs::read_dir(dir)?.next().is_none())
+}
+
+impl LsmStorageInner {
+    /// Create a checkpoint, which can be opened as a database of its own. The SSTs are hard-linked
+    /// into the directory, and copied if they cannot be linked, e.g. on another file system.
+    pub(crate) fn create_checkpoint(&self, dir: impl AsRef<Path>) -> Result<CheckpointStats> {
+        let dir = dir.as_ref();
+        if !is_empty_dir(dir)? {
+            bail!("checkpoint directory {} is not empty", dir.display());
+        }
+        self.write_checkpoint(dir, Transfer::HardLink)
+    }
+
+    /// Back up the database to a directory, which can be restored with `restore_backup`.
+    pub(crate) fn create_backup(
+        &self,
+        dir: impl AsRef<Path>,
+        mode: BackupMode,
+    ) -> Result<CheckpointStats> {
+        self.write_checkpoint(dir.as_ref(), Transfer::Copy(mode))
+    }
+
+    /// Write a checkpoint at the latest commit ts. The write lock and the state lock are held
+    /// while the SSTs and the value log files to include are collected, and the memtables are
+    /// written to SSTs, so that no transaction commits, and no flush, compaction or value log GC
+    /// finishes in the meantime. The files are copied after the locks are released.
+    ///
+    /// The checkpoint has no WAL. Its manifest starts with a snapshot, and lists the SSTs of the
+    /// memtables in L0.
+    fn write_checkpoint(&self, dir: &Path, transfer: Transfer) -> Result<CheckpointStats> {
+        std::fs::create_dir_all(dir).context("failed to create checkpoint dir")?;
+        let mut stats = CheckpointStats::default();
+        let mut names = HashSet::<OsString>::new();
+        let mut pending = Vec::new();
+        let snapshot = {
+            let _lck = self.mvcc().write_lock.lock();
+            let _state_lock = self.state_lock.lock();
+            self.value_log.sync()?;
+            stats.commit_ts = self.mvcc().latest_commit_ts();
+
+            let mut memtable_ssts = BTreeMap::new();
+            for cf in self.column_families() {
+                if let Some(sst_id) = self.write_memtables_to_sst(&cf, dir)? {
+                    names.insert(self.path_of_sst(sst_id).file_name().unwrap().into());
+                    memtable_ssts.insert(cf.id(), sst_id);
+                }
+            }
+            let mut snapshot = self.manifest_snapshot();
+            // The memtables are in the SSTs.
+            snapshot.memtables.clear();
+            for cf_snapshot in &mut snapshot.column_families {
+                let Some(&sst_id) = memtable_ssts.get(&cf_snapshot.id) else {
+                    continue;
+                };
+                let cf = self.column_family_by_id(cf_snapshot.id);
+                let mut state = cf.state.read().as_ref().clone();
+                cf.add_flushed_sst(&mut state, sst_id);
+                cf_snapshot.l0_sstables = state.l0_sstables;
+                cf_snapshot.levels = state.levels;
+            }
+
+            let active_value_log = self.value_log.active_file();
+            let value_logs = snapshot.value_logs.iter().map(|id| {
+                // Only the part of the active file written so far is in the checkpoint.
+                let len = match active_value_log {
+                    Some((active_id, len)) if active_id == *id => Some(len),
+                    _ => None,
+                };
+                (self.path_of_vlog(*id), len)
+            });
+            let ssts = snapshot
+                .column_families
+                .iter()
+                .flat_map(|cf| {
+                    cf.l0_sstables
+                        .iter()
+                        .chain(cf.levels.iter().flat_map(|(_, ssts)| ssts))
+                })
+                .filter(|id| !memtable_ssts.values().any(|sst_id| sst_id == *id))
+                .map(|id| (self.path_of_sst(*id), None));
+            for (path, len) in value_logs.chain(ssts).collect::<Vec<_>>() {
+                let name = path.file_name().unwrap().to_os_string();
+                if let Some(copy) =
+                    Self::stage_file(&path, dir.join(&name), len, transfer, &mut stats)?
+                {
+                    pending.push(copy);
+                }
+                names.insert(name);
+            }
+            snapshot
+        };
+
+        stats.copied_files += pending.len();
+        for copy in pending {
+            copy.copy()?;
+        }
+        Manifest::write_snapshot(dir, snapshot)?;
+        // Remove the files of an earlier backup that this one does not have.
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if !names.contains(&name) && name.to_str().is_some_and(is_database_file) {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+        File::open(dir)?.sync_all()?;
+        Ok(stats)
+    }
+
+    /// Hard-link a file into the checkpoint, or return the copy to make once the locks are
+    /// released. A file whose `len` is given is still being appended to, and is always copied.
+    fn stage_file(
+        path: &Path,
+        dest: PathBuf,
+        len: Option<u64>,
+        transfer: Transfer,
+        stats: &mut CheckpointStats,
+    ) -> Result<Option<PendingCopy>> {
+        if transfer == Transfer::HardLink
+            && len.is_none()
+            && std::fs::hard_link(path, &dest).is_ok()
+        {
+            stats.linked_files += 1;
+            return Ok(None);
+        }
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let len = match len {
+            Some(len) => len,
+            None => file.metadata()?.len(),
+        };
+        if transfer == Transfer::Copy(BackupMode::Incremental)
+            && dest.metadata().is_ok_and(|metadata| metadata.len() == len)
+        {
+            stats.skipped_files += 1;
+            return Ok(None);
+        }
+        Ok(Some(PendingCopy { file, len, dest }))
+    }
+
+    /// Write the memtables of a column family to one SST in the checkpoint directory. Returns its
+    /// id, or `None` if the memtables are empty. Must be called with the write lock held.
+    fn write_memtables_to_sst(&self, cf: &ColumnFamily, dir: &Path) -> Result<Option<usize>> {
+        let state = cf.state.read().clone();
+        let mut entries = BTreeMap::<KeyBytes, (ValueType, Bytes)>::new();
+        let mut range_tombstones = Vec::new();
+        // From the earliest memtable to the latest, so that a value rewritten by value log GC with
+        // the same ts replaces the old one.
+        for memtable in state
+            .imm_memtables
+            .iter()
+            .rev()
+            .chain(std::iter::once(&state.memtable))
+        {
+            for entry in memtable.map.iter() {
+                entries.insert(entry.key().clone(), entry.value().clone());
+            }
+            range_tombstones.extend(memtable.range_tombstones());
+        }
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let mut builder = self.sst_builder(&cf.options, 0, IoPriority::High);
+        for (key, (value_type, value)) in &entries {
+            builder.add_with_type(key.as_key_slice(), *value_type, value);
+        }
+        for tombstone in range_tombstones {
+            builder.add_range_tombstone(tombstone);
+        }
+        let sst_id = self.next_sst_id();
+        builder.build(
+            sst_id,
+            None,
+            LsmStorageInner::path_of_sst_static(dir, sst_id),
+        )?;
+        Ok(Some(sst_id))
+    }
+}
+
+/// Restore a backup to a new database directory, which can then be opened.
+pub(crate) fn restore_backup(backup_dir: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<()> {
+    let (backup_dir, path) = (backup_dir.as_ref(), path.as_ref());
+    if !is_empty_dir(path)? {
+        bail!("database directory {} is not empty", path.display());
+    }
+    let manifest_path = Manifest::current_path(backup_dir)?;
+    if !manifest_path.exists() {
+        bail!("{} has no backup", backup_dir.display());
+    }
+    std::fs::create_dir_all(path).context("failed to create DB dir")?;
+    // The manifest is copied last, so that a partly restored directory is not taken as a database.
+    for entry in std::fs::read_dir(backup_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_table_or_value_log = name
+            .to_str()
+            .is_some_and(|name| name.ends_with(".sst") || name.ends_with(".vlog"));
+        if !is_table_or_value_log {
+            continue;
+        }
+        std::fs::copy(entry.path(), path.join(&name))?;
+    }
+    std::fs::copy(
+        &manifest_path,
+        path.join(manifest_path.file_name().unwrap()),
+    )?;
+    File::open(path)?.sync_all()?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::compact::{CompactionController, CompactionOptions, CompactionTask};
+use crate::compression::CompressionOptions;
+use crate::filter::FilterOptions;
+use crate::lsm_storage::LsmStorageState;
+use crate::manifest::ManifestRecord;
+use crate::write_stall::{WriteStallCondition, WriteStallOptions};
+
+/// The column family that exists in every storage. Its state is `LsmStorageInner::state`, and its
+/// options are the ones in `LsmStorageOptions`.
+pub const DEFAULT_COLUMN_FAMILY_ID: usize = 0;
+pub const DEFAULT_COLUMN_FAMILY_NAME: &str = "default";
+
+/// The options that each column family sets on its own. The other options in `LsmStorageOptions`
+/// are shared by all column families.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnFamilyOptions {
+    // Block size in bytes
+    pub block_size: usize,
+    // SST size in bytes, also the approximate memtable capacity limit
+    pub target_sst_size: usize,
+    // Maximum number of memtables in memory, flush to L0 when exceeding this limit
+    pub num_memtable_limit: usize,
+    pub compaction_options: CompactionOptions,
+    // Block compression of the SSTs on each level
+    pub compression: CompressionOptions,
+    // Filter of the SSTs on each level
+    #[serde(default)]
+    pub filter: FilterOptions,
+}
+
+/// A logically separate keyspace with its own memtables, SSTs and compaction. All column families
+/// share the WAL, the manifest, the block cache and the MVCC timestamps, so that a transaction can
+/// write to several of them atomically.
+///
+/// The memtables of all column families are frozen and flushed together, so that a WAL can be
+/// removed once its memtables are flushed.
+pub struct ColumnFamily {
+    id: usize,
+    name: String,
+    pub(crate) state: Arc<RwLock<Arc<LsmStorageState>>>,
+    pub(crate) options: ColumnFamilyOptions,
+    pub(crate) compaction_controller: CompactionController,
+}
+
+impl ColumnFamily {
+    pub(crate) fn new(
+        id: usize,
+        name: String,
+        state: Arc<RwLock<Arc<LsmStorageState>>>,
+        options: ColumnFamilyOptions,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            state,
+            compaction_controller: CompactionController::new(&options.compaction_options),
+            options,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn options(&self) -> &ColumnFamilyOptions {
+        &self.options
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        self.id == DEFAULT_COLUMN_FAMILY_ID
+    }
+
+    /// The write stall condition of this column family. L0 and pending compaction bytes are not
+    /// considered without compaction, as nothing would ever resume the writes.
+    pub(crate) fn write_stall_condition(&self, options: &WriteStallOptions) -> WriteStallCondition {
+        let state = self.state.read();
+        if let CompactionController::NoCompaction = self.compaction_controller {
+            return options.condition(state.imm_memtables.len(), 0, 0);
+        }
+        // the debt is only computed when there is a limit on it
+        let pending_compaction_bytes = if options.pending_compaction_bytes_slowdown_limit.is_some()
+            || options.pending_compaction_bytes_stop_limit.is_some()
+        {
+            self.compaction_controller.pending_compaction_bytes(&state)
+        } else {
+            0
+        };
+        options.condition(
+            state.imm_memtables.len(),
+            state.l0_sstables.len(),
+            pending_compaction_bytes,
+        )
+    }
+
+    /// Add a flushed SST to L0, or as a new tier in tiered compaction.
+    pub(crate) fn add_flushed_sst(&self, snapshot: &mut LsmStorageState, sst_id: usize) {
+        if self.compaction_controller.flush_to_l0() {
+            // In leveled compaction or no compaction, simply flush to L0
+            snapshot.l0_sstables.insert(0, sst_id);
+        } else {
+            // In tiered compaction, create a new tier
+            snapshot.levels.insert(0, (sst_id, vec![sst_id]));
+        }
+    }
+
+    /// The manifest record of flushing a memtable of this column family to an SST.
+    pub(crate) fn flush_record(&self, sst_id: usize) -> ManifestRecord {
+        if self.is_default() {
+            ManifestRecord::Flush(sst_id)
+        } else {
+            ManifestRecord::ColumnFamilyFlush(self.id, sst_id)
+        }
+    }
+
+    /// The manifest record of a compaction of this column family.
+    pub(crate) fn compaction_record(
+        &self,
+        task: CompactionTask,
+        output: Vec<usize>,
+    ) -> ManifestRecord {
+        if self.is_default() {
+            ManifestRecord::Compaction(task, output)
+        } else {
+            ManifestRecord::ColumnFamilyCompaction(self.id, task, output)
+        }
+    }
+}
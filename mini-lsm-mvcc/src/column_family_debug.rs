@@ -0,0 +1,34 @@
+use crate::column_family::ColumnFamily;
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+
+impl LsmStorageInner {
+    pub fn dump_column_family(&self, cf: &ColumnFamily) {
+        let snapshot = cf.state.read();
+        if !snapshot.l0_sstables.is_empty() {
+            println!(
+                "L0 ({}): {:?}",
+                snapshot.l0_sstables.len(),
+                snapshot.l0_sstables,
+            );
+        }
+        for (level, files) in &snapshot.levels {
+            println!("L{level} ({}): {:?}", files.len(), files);
+        }
+    }
+
+    /// Dumps the structure of every column family, with the name of each one except the default.
+    pub fn dump_column_families(&self) {
+        for cf in self.column_families() {
+            if !cf.is_default() {
+                println!("column family {} ({}):", cf.name(), cf.id());
+            }
+            self.dump_column_family(&cf);
+        }
+    }
+}
+
+impl MiniLsm {
+    pub fn dump_column_families(&self) {
+        self.inner.dump_column_families()
+    }
+}
@@ -2,11 +2,14 @@ mod leveled;
 mod simple_leveled;
 mod tiered;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
 pub use leveled::{LeveledCompactionController, LeveledCompactionOptions, LeveledCompactionTask};
 use serde::{Deserialize, Serialize};
 pub use simple_leveled::{
@@ -14,14 +17,19 @@ pub use simple_leveled::{
 };
 pub use tiered::{TieredCompactionController, TieredCompactionOptions, TieredCompactionTask};
 
+use crate::column_family::{ColumnFamily, ColumnFamilyOptions};
+use crate::compaction_filter::{CompactionDecision, CompactionFilter};
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
-use crate::key::KeySlice;
-use crate::lsm_storage::{CompactionFilter, LsmStorageInner, LsmStorageState};
-use crate::manifest::ManifestRecord;
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::key::{KeySlice, KeyVec, TS_RANGE_BEGIN};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageState};
+use crate::range_tombstone::RangeTombstone;
+use crate::rate_limiter::IoPriority;
+use crate::table::{SsTable, SsTableIterator};
+use crate::value_type::ValueType;
+use crate::vlog::{self, ValueLogReader};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CompactionTask {
@@ -43,6 +51,153 @@ impl CompactionTask {
             CompactionTask::Tiered(task) => task.bottom_tier_included,
         }
     }
+
+    /// The level of the SSTs produced by this compaction task, whose compression and filter they
+    /// use. Tiered compaction has no fixed levels, so a merged tier uses the options of L1, and
+    /// the bottom tier uses the options of the last configured level.
+    fn output_level(&self) -> usize {
+        match self {
+            CompactionTask::ForceFullCompaction { .. } => 1,
+            CompactionTask::Leveled(task) => task.lower_level,
+            CompactionTask::Simple(task) => task.lower_level,
+            CompactionTask::Tiered(task) if task.bottom_tier_included => usize::MAX,
+            CompactionTask::Tiered(_) => 1,
+        }
+    }
+
+    fn is_trivial_move(&self) -> bool {
+        match self {
+            CompactionTask::Leveled(task) => task.is_trivial_move,
+            CompactionTask::Simple(task) => task.is_trivial_move,
+            CompactionTask::ForceFullCompaction { .. } | CompactionTask::Tiered(_) => false,
+        }
+    }
+
+    /// A trivial move keeps the SSTs as they are, so it is only done if the lower level uses the
+    /// same compression and filter as the upper level.
+    fn check_trivial_move(&mut self, options: &ColumnFamilyOptions) {
+        let output_level = self.output_level();
+        let (CompactionTask::Leveled(LeveledCompactionTask {
+            upper_level,
+            is_trivial_move,
+            ..
+        })
+        | CompactionTask::Simple(SimpleLeveledCompactionTask {
+            upper_level,
+            is_trivial_move,
+            ..
+        })) = self
+        else {
+            return;
+        };
+        let upper_level = upper_level.unwrap_or(0);
+        if options.compression.for_level(upper_level) != options.compression.for_level(output_level)
+            || options.filter.for_level(upper_level) != options.filter.for_level(output_level)
+        {
+            *is_trivial_move = false;
+        }
+    }
+
+    /// All SSTs that are read by this compaction task.
+    fn input_sst_ids(&self) -> HashSet<usize> {
+        match self {
+            CompactionTask::ForceFullCompaction {
+                l0_sstables,
+                l1_sstables,
+            } => l0_sstables.iter().chain(l1_sstables).copied().collect(),
+            CompactionTask::Leveled(task) => task
+                .upper_level_sst_ids
+                .iter()
+                .chain(&task.lower_level_sst_ids)
+                .copied()
+                .collect(),
+            CompactionTask::Simple(task) => task
+                .upper_level_sst_ids
+                .iter()
+                .chain(&task.lower_level_sst_ids)
+                .copied()
+                .collect(),
+            CompactionTask::Tiered(task) => task
+                .tiers
+                .iter()
+                .flat_map(|(_, ssts)| ssts)
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+/// Whether the upper level SSTs of a compaction task can be moved to the lower level as they are,
+/// i.e., they overlap neither each other nor the lower level SSTs of the task, which stay in the
+/// lower level as they are. SSTs that are not in the snapshot are never moved.
+fn can_trivially_move(
+    snapshot: &LsmStorageState,
+    upper_level_sst_ids: &[usize],
+    lower_level_sst_ids: &[usize],
+) -> bool {
+    if upper_level_sst_ids.is_empty() {
+        return false;
+    }
+    let Some(mut ssts) = upper_level_sst_ids
+        .iter()
+        .chain(lower_level_sst_ids)
+        .map(|id| snapshot.sstables.get(id))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return false;
+    };
+    ssts.sort_by(|x, y| x.first_key().cmp(y.first_key()));
+    // The user keys are compared without the timestamps, as two SSTs with versions of the same
+    // key overlap even if the versions do not.
+    ssts.windows(2)
+        .all(|pair| pair[0].last_key().key_ref() < pair[1].first_key().key_ref())
+}
+
+/// The total size of the SSTs in the snapshot.
+fn sst_bytes(snapshot: &LsmStorageState, sst_ids: &[usize]) -> u64 {
+    sst_ids
+        .iter()
+        .filter_map(|id| snapshot.sstables.get(id))
+        .map(|sst| sst.table_size())
+        .sum()
+}
+
+/// The key range `[start, end)` of a compaction task that is compacted by one subcompaction.
+/// `None` means unbounded.
+#[derive(Clone)]
+struct SubcompactionRange {
+    start: Option<Bytes>,
+    end: Option<Bytes>,
+}
+
+impl SubcompactionRange {
+    fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_ref().is_none_or(|start| key >= start) && self.is_before_end(key)
+    }
+
+    fn is_before_end(&self, key: &[u8]) -> bool {
+        self.end.as_ref().is_none_or(|end| key < end)
+    }
+
+    fn seek_sst(&self, sst: Arc<SsTable>) -> Result<SsTableIterator> {
+        match &self.start {
+            Some(start) => SsTableIterator::create_and_seek_to_key(
+                sst,
+                KeySlice::from_slice(start, TS_RANGE_BEGIN),
+            ),
+            None => SsTableIterator::create_and_seek_to_first(sst),
+        }
+    }
+
+    fn seek_ssts(&self, ssts: Vec<Arc<SsTable>>) -> Result<SstConcatIterator> {
+        match &self.start {
+            Some(start) => SstConcatIterator::create_and_seek_to_key(
+                ssts,
+                KeySlice::from_slice(start, TS_RANGE_BEGIN),
+            ),
+            None => SstConcatIterator::create_and_seek_to_first(ssts),
+        }
+    }
 }
 
 pub(crate) enum CompactionController {
@@ -53,6 +208,21 @@ pub(crate) enum CompactionController {
 }
 
 impl CompactionController {
+    pub fn new(options: &CompactionOptions) -> Self {
+        match options {
+            CompactionOptions::Leveled(options) => {
+                Self::Leveled(LeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Tiered(options) => {
+                Self::Tiered(TieredCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Simple(options) => {
+                Self::Simple(SimpleLeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::NoCompaction => Self::NoCompaction,
+        }
+    }
+
     pub fn generate_compaction_task(&self, snapshot: &LsmStorageState) -> Option<CompactionTask> {
         match self {
             CompactionController::Leveled(ctrl) => ctrl
@@ -68,6 +238,17 @@ impl CompactionController {
         }
     }
 
+    /// The bytes that compaction has to rewrite before the snapshot is in the shape that the
+    /// controller aims at, for write stalls.
+    pub fn pending_compaction_bytes(&self, snapshot: &LsmStorageState) -> u64 {
+        match self {
+            CompactionController::Leveled(ctrl) => ctrl.pending_compaction_bytes(snapshot),
+            CompactionController::Simple(ctrl) => ctrl.pending_compaction_bytes(snapshot),
+            CompactionController::Tiered(ctrl) => ctrl.pending_compaction_bytes(snapshot),
+            CompactionController::NoCompaction => 0,
+        }
+    }
+
     pub fn apply_compaction_result(
         &self,
         snapshot: &LsmStorageState,
@@ -98,7 +279,7 @@ impl CompactionController {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompactionOptions {
     /// Leveled compaction with partial compaction + dynamic level support (= RocksDB's Leveled
     /// Compaction)
@@ -112,64 +293,238 @@ pub enum CompactionOptions {
 }
 
 impl LsmStorageInner {
+    /// Collect the range tombstones of the input SSTs of a compaction task. Returns the tombstones
+    /// that are visible to all readers, whose deleted versions can be removed, and the tombstones
+    /// to keep whose sentinel is in the subcompaction range, keyed by their sentinel.
+    ///
+    /// Range tombstones below the watermark can be removed at the bottom level, if all SSTs they
+    /// overlap with are part of this compaction.
+    #[allow(clippy::type_complexity)]
+    fn compaction_range_tombstones(
+        &self,
+        cf: &ColumnFamily,
+        task: &CompactionTask,
+        watermark: u64,
+        range: &SubcompactionRange,
+    ) -> (
+        Vec<RangeTombstone>,
+        HashMap<(Bytes, u64), Vec<RangeTombstone>>,
+    ) {
+        let compact_to_bottom_level = task.compact_to_bottom_level();
+        let input_sst_ids = task.input_sst_ids();
+        let snapshot = cf.state.read();
+        let mut gc_range_tombstones = Vec::new();
+        let mut retained_range_tombstones = HashMap::<_, Vec<_>>::new();
+        for id in &input_sst_ids {
+            for tombstone in snapshot.sstables[id].range_tombstones() {
+                let droppable = compact_to_bottom_level
+                    && tombstone.ts <= watermark
+                    && snapshot.sstables.iter().all(|(id, sst)| {
+                        input_sst_ids.contains(id)
+                            || !tombstone
+                                .overlaps(sst.first_key().key_ref(), sst.last_key().key_ref())
+                    });
+                if tombstone.ts <= watermark {
+                    gc_range_tombstones.push(tombstone.clone());
+                }
+                if !droppable && range.contains(&tombstone.start) {
+                    retained_range_tombstones
+                        .entry((tombstone.start.clone(), tombstone.ts))
+                        .or_default()
+                        .push(tombstone.clone());
+                }
+            }
+        }
+        (gc_range_tombstones, retained_range_tombstones)
+    }
+
+    /// Collect the range tombstones of the column family that are visible at the watermark and
+    /// overlap the subcompaction range, including the ones outside the input SSTs. Merges are only
+    /// folded into a version that none of them delete, as reads do not see it.
+    fn merge_range_tombstones(
+        &self,
+        cf: &ColumnFamily,
+        watermark: u64,
+        range: &SubcompactionRange,
+    ) -> Vec<RangeTombstone> {
+        let lower = range
+            .start
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Included);
+        let upper = range
+            .end
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Excluded);
+        cf.state.read().range_tombstones(watermark, lower, upper)
+    }
+
     fn compact_generate_sst_from_iter(
         &self,
+        cf: &ColumnFamily,
         mut iter: impl for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
-        compact_to_bottom_level: bool,
+        task: &CompactionTask,
+        range: SubcompactionRange,
     ) -> Result<Vec<Arc<SsTable>>> {
+        let compact_to_bottom_level = task.compact_to_bottom_level();
         let mut builder = None;
         let mut new_sst = Vec::new();
+        // The value log files are taken before the watermark, so that the files the versions at
+        // the watermark point to are kept open even if they are garbage collected meanwhile.
+        let value_log = self.value_log.reader();
         let watermark = self.mvcc().watermark();
         let mut last_key = Vec::<u8>::new();
         let mut first_key_below_watermark = false;
         let compaction_filters = self.compaction_filters.lock().clone();
-        'outer: while iter.is_valid() {
-            if builder.is_none() {
-                builder = Some(SsTableBuilder::new(self.options.block_size));
-            }
+        let now = vlog::now_millis();
+        let output_level = task.output_level();
 
+        let (gc_range_tombstones, mut retained_range_tombstones) =
+            self.compaction_range_tombstones(cf, task, watermark, &range);
+        let merge_range_tombstones = self.merge_range_tombstones(cf, watermark, &range);
+
+        while iter.is_valid() && range.is_before_end(iter.key().key_ref()) {
             let same_as_last_key = iter.key().key_ref() == last_key;
+            // The value to store instead of the current one with its type, as decided by the
+            // compaction filters.
+            let mut filtered_value = None;
+            // The version that the merges are folded into. `iter` is already past it.
+            let mut folded_key: Option<KeyVec> = None;
             if !same_as_last_key {
                 first_key_below_watermark = true;
             }
 
-            if compact_to_bottom_level
-                && !same_as_last_key
-                && iter.key().ts() <= watermark
-                && iter.value().is_empty()
-            {
-                last_key.clear();
-                last_key.extend(iter.key().key_ref());
-                iter.next()?;
-                first_key_below_watermark = false;
-                continue;
-            }
+            let sentinel_of = if retained_range_tombstones.is_empty() {
+                None
+            } else {
+                retained_range_tombstones.remove(&(
+                    Bytes::copy_from_slice(iter.key().key_ref()),
+                    iter.key().ts(),
+                ))
+            };
 
-            if iter.key().ts() <= watermark {
-                if same_as_last_key && !first_key_below_watermark {
+            if sentinel_of.is_some() {
+                // The sentinel of a retained range tombstone is always kept.
+                if iter.key().ts() <= watermark {
+                    first_key_below_watermark = false;
+                }
+            } else {
+                if RangeTombstone::any_covers(&gc_range_tombstones, iter.key()) {
+                    if !same_as_last_key {
+                        last_key.clear();
+                        last_key.extend(iter.key().key_ref());
+                    }
+                    iter.next()?;
+                    first_key_below_watermark = false;
+                    continue;
+                }
+
+                if compact_to_bottom_level
+                    && !same_as_last_key
+                    && iter.key().ts() <= watermark
+                    && iter.value_type() == ValueType::Delete
+                {
+                    last_key.clear();
+                    last_key.extend(iter.key().key_ref());
                     iter.next()?;
+                    first_key_below_watermark = false;
                     continue;
                 }
 
-                first_key_below_watermark = false;
+                if iter.key().ts() <= watermark {
+                    if same_as_last_key && !first_key_below_watermark {
+                        iter.next()?;
+                        continue;
+                    }
+
+                    first_key_below_watermark = false;
 
-                if !compaction_filters.is_empty() {
-                    for filter in &compaction_filters {
-                        match filter {
-                            CompactionFilter::Prefix(x) => {
-                                if iter.key().key_ref().starts_with(x) {
-                                    iter.next()?;
-                                    continue 'outer;
-                                }
+                    // An expired version is removed as the compaction filters remove a key.
+                    let decision = if vlog::is_expired(iter.value_type(), iter.value(), now) {
+                        CompactionDecision::Remove
+                    } else if iter.value_type() == ValueType::Merge {
+                        last_key.clear();
+                        last_key.extend(iter.key().key_ref());
+                        folded_key = Some(iter.key().to_key_vec());
+                        // The merges from the latest to the oldest, and the value they are
+                        // merged into if it is in this compaction, where `Some(None)` means that
+                        // the key does not exist before them. The version that stops the loop is
+                        // dropped unless it is a sentinel or a value with a TTL, which the merges
+                        // are not folded into as reads fold them without it once it expires.
+                        let mut merges = Vec::new();
+                        let mut base = None;
+                        let mut keep_base = false;
+                        while iter.is_valid() && iter.key().key_ref() == last_key {
+                            let value_type = iter.value_type();
+                            let value = iter.value();
+                            let is_sentinel = retained_range_tombstones.contains_key(&(
+                                Bytes::copy_from_slice(&last_key),
+                                iter.key().ts(),
+                            ));
+                            if is_sentinel
+                                || value_type == ValueType::Delete
+                                || RangeTombstone::any_covers(&merge_range_tombstones, iter.key())
+                                || vlog::is_expired(value_type, value, now)
+                            {
+                                base = Some(None);
+                                break;
+                            }
+                            if value_type.has_ttl() {
+                                keep_base = true;
+                                first_key_below_watermark = true;
+                                break;
+                            }
+                            if value_type != ValueType::Merge {
+                                base = Some(Some((value_type, value.to_vec())));
+                                break;
                             }
+                            merges.push(value.to_vec());
+                            iter.next()?;
+                        }
+                        filtered_value = Some(self.fold_merges(
+                            &value_log,
+                            &last_key,
+                            merges,
+                            base,
+                            compact_to_bottom_level && !keep_base,
+                        )?);
+                        CompactionDecision::Keep
+                    } else if !compaction_filters.is_empty() && iter.value_type().is_put() {
+                        Self::run_compaction_filters(
+                            &compaction_filters,
+                            &value_log,
+                            iter.key(),
+                            iter.value_type(),
+                            iter.value(),
+                            output_level,
+                        )?
+                    } else {
+                        CompactionDecision::Keep
+                    };
+                    match decision {
+                        CompactionDecision::Keep => {}
+                        CompactionDecision::Remove if compact_to_bottom_level => {
+                            last_key.clear();
+                            last_key.extend(iter.key().key_ref());
+                            iter.next()?;
+                            continue;
+                        }
+                        CompactionDecision::Remove => {
+                            filtered_value = Some((ValueType::Delete, Vec::new()))
+                        }
+                        CompactionDecision::ChangeValue(value) => {
+                            filtered_value = Some((ValueType::Put, value.to_vec()));
                         }
                     }
                 }
             }
 
+            // The builder is created for the first key kept, as all keys in the range may be dropped.
+            if builder.is_none() {
+                builder = Some(self.sst_builder(&cf.options, output_level, IoPriority::Low));
+            }
             let builder_inner = builder.as_mut().unwrap();
 
-            if builder_inner.estimated_size() >= self.options.target_sst_size && !same_as_last_key {
+            if builder_inner.estimated_size() >= cf.options.target_sst_size && !same_as_last_key {
                 let sst_id = self.next_sst_id();
                 let old_builder = builder.take().unwrap();
                 let sst = Arc::new(old_builder.build(
@@ -178,11 +533,24 @@ impl LsmStorageInner {
                     self.path_of_sst(sst_id),
                 )?);
                 new_sst.push(sst);
-                builder = Some(SsTableBuilder::new(self.options.block_size));
+                builder = Some(self.sst_builder(&cf.options, output_level, IoPriority::Low));
             }
 
             let builder_inner = builder.as_mut().unwrap();
-            builder_inner.add(iter.key(), iter.value());
+            if let Some(key) = &folded_key {
+                let (value_type, value) = filtered_value.as_ref().unwrap();
+                builder_inner.add_with_type(key.as_key_slice(), *value_type, value);
+                continue;
+            }
+            match &filtered_value {
+                Some((value_type, value)) => {
+                    builder_inner.add_with_type(iter.key(), *value_type, value)
+                }
+                None => builder_inner.add_with_type(iter.key(), iter.value_type(), iter.value()),
+            }
+            for tombstone in sentinel_of.into_iter().flatten() {
+                builder_inner.add_range_tombstone(tombstone);
+            }
 
             if !same_as_last_key {
                 last_key.clear();
@@ -191,6 +559,12 @@ impl LsmStorageInner {
 
             iter.next()?;
         }
+        if let Some(builder) = builder.as_mut() {
+            // The sentinels are never dropped, so this should be empty. Never lose a tombstone.
+            for tombstone in retained_range_tombstones.into_values().flatten() {
+                builder.add_range_tombstone(tombstone);
+            }
+        }
         if let Some(builder) = builder {
             let sst_id = self.next_sst_id(); // lock dropped here
             let sst = Arc::new(builder.build(
@@ -203,11 +577,190 @@ impl LsmStorageInner {
         Ok(new_sst)
     }
 
-    fn compact(&self, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
+    /// Fold the merges of a key, from the latest to the oldest, into the record to store at the
+    /// latest of them. If the value they are merged into is not known, it may be in a lower level,
+    /// so the merges are only combined with `MergeOperator::partial_merge` into a merge unless
+    /// `no_lower_versions` is set.
+    fn fold_merges(
+        &self,
+        value_log: &ValueLogReader,
+        key: &[u8],
+        mut merges: Vec<Vec<u8>>,
+        base: Option<Option<(ValueType, Vec<u8>)>>,
+        no_lower_versions: bool,
+    ) -> Result<(ValueType, Vec<u8>)> {
+        merges.reverse();
+        let operands = merges
+            .iter()
+            .flat_map(|merge| vlog::merge_operands(merge).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let Some(merge_operator) = &self.options.merge_operator else {
+            bail!("merge operator is not set");
+        };
+
+        let (value_type, value) = match base {
+            None if !no_lower_versions => {
+                if merges.len() == 1 {
+                    return Ok((ValueType::Merge, merges.pop().unwrap()));
+                }
+                let value = match merge_operator.partial_merge(key, &operands) {
+                    Some(operand) => vlog::encode_merge_operands(&[&operand]),
+                    None => vlog::encode_merge_operands(&operands),
+                };
+                (ValueType::Merge, value)
+            }
+            base => {
+                let base = base.flatten();
+                let existing = match &base {
+                    Some((value_type, base)) => Some(value_log.user_value(*value_type, base)?),
+                    None => None,
+                };
+                let value = merge_operator.full_merge(key, existing.as_deref(), &operands);
+                (ValueType::Put, value.to_vec())
+            }
+        };
+        Ok((value_type, value))
+    }
+
+    /// Pass a version to the compaction filters in the order they are added. A value changed by a
+    /// filter is passed to the next one, and a removed version is not passed further.
+    fn run_compaction_filters(
+        compaction_filters: &[Arc<dyn CompactionFilter>],
+        value_log: &ValueLogReader,
+        key: KeySlice,
+        value_type: ValueType,
+        value: &[u8],
+        level: usize,
+    ) -> Result<CompactionDecision> {
+        let mut value = value_log.user_value(value_type, value)?;
+        let mut decision = CompactionDecision::Keep;
+        for filter in compaction_filters {
+            match filter.filter(key.key_ref(), key.ts(), &value, level) {
+                CompactionDecision::Keep => {}
+                CompactionDecision::Remove => return Ok(CompactionDecision::Remove),
+                CompactionDecision::ChangeValue(new_value) => {
+                    value = new_value.clone();
+                    decision = CompactionDecision::ChangeValue(new_value);
+                }
+            }
+        }
+        Ok(decision)
+    }
+
+    fn compact(&self, cf: &ColumnFamily, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
         let snapshot = {
-            let state = self.state.read();
+            let state = cf.state.read();
             state.clone()
         };
+        if task.is_trivial_move() {
+            // The SSTs are relinked to the lower level by `apply_compaction_result`.
+            let mut ssts = task
+                .input_sst_ids()
+                .iter()
+                .map(|id| snapshot.sstables[id].clone())
+                .collect::<Vec<_>>();
+            ssts.sort_by(|x, y| x.first_key().cmp(y.first_key()));
+            println!(
+                "trivial move: {:?}",
+                ssts.iter().map(|x| x.sst_id()).collect::<Vec<_>>()
+            );
+            return Ok(ssts);
+        }
+
+        let boundaries = self.subcompaction_boundaries(&snapshot, task);
+        if boundaries.is_empty() {
+            return self.compact_range(
+                cf,
+                &snapshot,
+                task,
+                SubcompactionRange {
+                    start: None,
+                    end: None,
+                },
+            );
+        }
+        println!(
+            "running {} subcompactions split at {:?}",
+            boundaries.len() + 1,
+            boundaries
+        );
+        let ranges = (0..=boundaries.len())
+            .map(|i| SubcompactionRange {
+                start: i.checked_sub(1).map(|i| boundaries[i].clone()),
+                end: boundaries.get(i).cloned(),
+            })
+            .collect::<Vec<_>>();
+        // At most `max_subcompactions` workers take the ranges one by one.
+        let next_range = AtomicUsize::new(0);
+        let num_workers = self.options.max_subcompactions.min(ranges.len());
+        let results = std::thread::scope(|scope| {
+            let workers = (0..num_workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut outputs = Vec::new();
+                        loop {
+                            let i = next_range.fetch_add(1, Ordering::Relaxed);
+                            let Some(range) = ranges.get(i) else {
+                                return Ok(outputs);
+                            };
+                            outputs
+                                .push((i, self.compact_range(cf, &snapshot, task, range.clone())?));
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            // all the workers are joined before returning, so that a panic is not re-raised by
+            // the scope
+            workers
+                .into_iter()
+                .map(|worker| worker.join())
+                .collect::<Vec<_>>()
+        });
+        let mut outputs = Vec::new();
+        for result in results {
+            let result: Result<Vec<_>> = result.map_err(|_| anyhow!("subcompaction panicked"))?;
+            outputs.extend(result?);
+        }
+        // The ranges are disjoint, so the outputs in the order of the ranges form one sorted run.
+        outputs.sort_by_key(|(i, _)| *i);
+        Ok(outputs.into_iter().flat_map(|(_, ssts)| ssts).collect())
+    }
+
+    /// Split a compaction task into at most `max_subcompactions` key ranges with about the same
+    /// number of blocks, using the first keys of the blocks (or index partitions) of the input SSTs
+    /// as boundaries.
+    /// Returns the boundaries, which are user keys, so that all versions of a key are in the same
+    /// range.
+    fn subcompaction_boundaries(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+    ) -> Vec<Bytes> {
+        if self.options.max_subcompactions <= 1 {
+            return Vec::new();
+        }
+        let mut keys = task
+            .input_sst_ids()
+            .iter()
+            .flat_map(|id| snapshot.sstables[id].index_first_keys())
+            .map(|key| key.key_ref())
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        let num_ranges = self.options.max_subcompactions.min(keys.len());
+        (1..num_ranges)
+            .map(|i| Bytes::copy_from_slice(keys[i * keys.len() / num_ranges]))
+            .collect()
+    }
+
+    /// Compact the keys of a compaction task in the range.
+    fn compact_range(
+        &self,
+        cf: &ColumnFamily,
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+        range: SubcompactionRange,
+    ) -> Result<Vec<Arc<SsTable>>> {
         match task {
             CompactionTask::ForceFullCompaction {
                 l0_sstables,
@@ -215,9 +768,9 @@ impl LsmStorageInner {
             } => {
                 let mut l0_iters = Vec::with_capacity(l0_sstables.len());
                 for id in l0_sstables.iter() {
-                    l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
-                        snapshot.sstables.get(id).unwrap().clone(),
-                    )?));
+                    l0_iters.push(Box::new(
+                        range.seek_sst(snapshot.sstables.get(id).unwrap().clone())?,
+                    ));
                 }
                 let mut l1_iters = Vec::with_capacity(l1_sstables.len());
                 for id in l1_sstables.iter() {
@@ -225,9 +778,9 @@ impl LsmStorageInner {
                 }
                 let iter = TwoMergeIterator::create(
                     MergeIterator::create(l0_iters),
-                    SstConcatIterator::create_and_seek_to_first(l1_iters)?,
+                    range.seek_ssts(l1_iters)?,
                 )?;
-                self.compact_generate_sst_from_iter(iter, task.compact_to_bottom_level())
+                self.compact_generate_sst_from_iter(cf, iter, task, range)
             }
             CompactionTask::Simple(SimpleLeveledCompactionTask {
                 upper_level,
@@ -248,33 +801,37 @@ impl LsmStorageInner {
                     for id in upper_level_sst_ids.iter() {
                         upper_ssts.push(snapshot.sstables.get(id).unwrap().clone());
                     }
-                    let upper_iter = SstConcatIterator::create_and_seek_to_first(upper_ssts)?;
+                    let upper_iter = range.seek_ssts(upper_ssts)?;
                     let mut lower_ssts = Vec::with_capacity(lower_level_sst_ids.len());
                     for id in lower_level_sst_ids.iter() {
                         lower_ssts.push(snapshot.sstables.get(id).unwrap().clone());
                     }
-                    let lower_iter = SstConcatIterator::create_and_seek_to_first(lower_ssts)?;
+                    let lower_iter = range.seek_ssts(lower_ssts)?;
                     self.compact_generate_sst_from_iter(
+                        cf,
                         TwoMergeIterator::create(upper_iter, lower_iter)?,
-                        task.compact_to_bottom_level(),
+                        task,
+                        range,
                     )
                 }
                 None => {
                     let mut upper_iters = Vec::with_capacity(upper_level_sst_ids.len());
                     for id in upper_level_sst_ids.iter() {
-                        upper_iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
-                            snapshot.sstables.get(id).unwrap().clone(),
-                        )?));
+                        upper_iters.push(Box::new(
+                            range.seek_sst(snapshot.sstables.get(id).unwrap().clone())?,
+                        ));
                     }
                     let upper_iter = MergeIterator::create(upper_iters);
                     let mut lower_ssts = Vec::with_capacity(lower_level_sst_ids.len());
                     for id in lower_level_sst_ids.iter() {
                         lower_ssts.push(snapshot.sstables.get(id).unwrap().clone());
                     }
-                    let lower_iter = SstConcatIterator::create_and_seek_to_first(lower_ssts)?;
+                    let lower_iter = range.seek_ssts(lower_ssts)?;
                     self.compact_generate_sst_from_iter(
+                        cf,
                         TwoMergeIterator::create(upper_iter, lower_iter)?,
-                        task.compact_to_bottom_level(),
+                        task,
+                        range,
                     )
                 }
             },
@@ -285,23 +842,24 @@ impl LsmStorageInner {
                     for id in tier_sst_ids.iter() {
                         ssts.push(snapshot.sstables.get(id).unwrap().clone());
                     }
-                    iters.push(Box::new(SstConcatIterator::create_and_seek_to_first(ssts)?));
+                    iters.push(Box::new(range.seek_ssts(ssts)?));
                 }
-                self.compact_generate_sst_from_iter(
-                    MergeIterator::create(iters),
-                    task.compact_to_bottom_level(),
-                )
+                self.compact_generate_sst_from_iter(cf, MergeIterator::create(iters), task, range)
             }
         }
     }
 
     pub fn force_full_compaction(&self) -> Result<()> {
-        let CompactionOptions::NoCompaction = self.options.compaction_options else {
+        self.force_full_compaction_cf(&self.default_column_family())
+    }
+
+    pub fn force_full_compaction_cf(&self, cf: &ColumnFamily) -> Result<()> {
+        let CompactionOptions::NoCompaction = cf.options.compaction_options else {
             panic!("full compaction can only be called with compaction is not enabled")
         };
 
         let snapshot = {
-            let state = self.state.read();
+            let state = cf.state.read();
             state.clone()
         };
 
@@ -314,15 +872,16 @@ impl LsmStorageInner {
 
         println!("force full compaction: {:?}", compaction_task);
 
-        let sstables = self.compact(&compaction_task)?;
+        let sstables = self.compact(cf, &compaction_task)?;
         let mut ids = Vec::with_capacity(sstables.len());
 
-        {
+        let ssts_to_remove = {
             let state_lock = self.state_lock.lock();
-            let mut state = self.state.read().as_ref().clone();
+            let mut state = cf.state.read().as_ref().clone();
+            let mut ssts_to_remove = Vec::with_capacity(l0_sstables.len() + l1_sstables.len());
             for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
                 let result = state.sstables.remove(sst);
-                assert!(result.is_some());
+                ssts_to_remove.push(result.unwrap());
             }
             for new_sst in sstables {
                 ids.push(new_sst.sst_id());
@@ -339,47 +898,60 @@ impl LsmStorageInner {
                 .copied()
                 .collect::<Vec<_>>();
             assert!(l0_sstables_map.is_empty());
-            *self.state.write() = Arc::new(state);
+            *cf.state.write() = Arc::new(state);
             self.sync_dir()?;
             self.manifest.as_ref().unwrap().add_record(
                 &state_lock,
-                ManifestRecord::Compaction(compaction_task, ids.clone()),
+                cf.compaction_record(compaction_task, ids.clone()),
             )?;
-        }
-        for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
-            std::fs::remove_file(self.path_of_sst(*sst))?;
+            self.maybe_rotate_manifest(&state_lock)?;
+            ssts_to_remove
+        };
+        for sst in ssts_to_remove {
+            self.block_cache.invalidate_sst(&sst);
+            std::fs::remove_file(self.path_of_sst(sst.sst_id()))?;
         }
 
         println!("force full compaction done, new SSTs: {:?}", ids);
+        self.write_stall.notify();
 
         Ok(())
     }
 
     fn trigger_compaction(&self) -> Result<()> {
+        for cf in self.column_families() {
+            if let CompactionController::NoCompaction = cf.compaction_controller {
+                continue;
+            }
+            self.trigger_column_family_compaction(&cf)?;
+        }
+        Ok(())
+    }
+
+    fn trigger_column_family_compaction(&self, cf: &ColumnFamily) -> Result<()> {
         let snapshot = {
-            let state = self.state.read();
+            let state = cf.state.read();
             state.clone()
         };
-        let task = self
-            .compaction_controller
-            .generate_compaction_task(&snapshot);
-        let Some(task) = task else {
+        let task = cf.compaction_controller.generate_compaction_task(&snapshot);
+        let Some(mut task) = task else {
             return Ok(());
         };
-        self.dump_structure();
+        task.check_trivial_move(&cf.options);
+        self.dump_column_family(cf);
         println!("running compaction task: {:?}", task);
-        let sstables = self.compact(&task)?;
+        let sstables = self.compact(cf, &task)?;
         let output = sstables.iter().map(|x| x.sst_id()).collect::<Vec<_>>();
         let ssts_to_remove = {
             let state_lock = self.state_lock.lock();
-            let mut snapshot = self.state.read().as_ref().clone();
+            let mut snapshot = cf.state.read().as_ref().clone();
             let mut new_sst_ids = Vec::new();
             for file_to_add in sstables {
                 new_sst_ids.push(file_to_add.sst_id());
                 let result = snapshot.sstables.insert(file_to_add.sst_id(), file_to_add);
-                assert!(result.is_none());
+                assert!(result.is_none() || task.is_trivial_move());
             }
-            let (mut snapshot, files_to_remove) = self
+            let (mut snapshot, files_to_remove) = cf
                 .compaction_controller
                 .apply_compaction_result(&snapshot, &task, &output);
             let mut ssts_to_remove = Vec::with_capacity(files_to_remove.len());
@@ -388,12 +960,13 @@ impl LsmStorageInner {
                 assert!(result.is_some(), "cannot remove {}.sst", file_to_remove);
                 ssts_to_remove.push(result.unwrap());
             }
-            let mut state = self.state.write();
+            let mut state = cf.state.write();
             *state = Arc::new(snapshot);
             drop(state);
             self.sync_dir()?;
             self.manifest()
-                .add_record(&state_lock, ManifestRecord::Compaction(task, new_sst_ids))?;
+                .add_record(&state_lock, cf.compaction_record(task, new_sst_ids))?;
+            self.maybe_rotate_manifest(&state_lock)?;
             ssts_to_remove
         };
         println!(
@@ -403,9 +976,11 @@ impl LsmStorageInner {
             output
         );
         for sst in ssts_to_remove {
+            self.block_cache.invalidate_sst(&sst);
             std::fs::remove_file(self.path_of_sst(sst.sst_id()))?;
         }
         self.sync_dir()?;
+        self.write_stall.notify();
 
         Ok(())
     }
@@ -414,32 +989,27 @@ impl LsmStorageInner {
         self: &Arc<Self>,
         rx: crossbeam_channel::Receiver<()>,
     ) -> Result<Option<std::thread::JoinHandle<()>>> {
-        if let CompactionOptions::Leveled(_)
-        | CompactionOptions::Simple(_)
-        | CompactionOptions::Tiered(_) = self.options.compaction_options
-        {
-            let this = self.clone();
-            let handle = std::thread::spawn(move || {
-                let ticker = crossbeam_channel::tick(Duration::from_millis(50));
-                loop {
-                    crossbeam_channel::select! {
-                        recv(ticker) -> _ => if let Err(e) = this.trigger_compaction() {
-                            eprintln!("compaction failed: {}", e);
-                        },
-                        recv(rx) -> _ => return
-                    }
+        // Column families with compaction can be created at any time, so the thread always runs.
+        let this = self.clone();
+        let handle = std::thread::spawn(move || {
+            let ticker = crossbeam_channel::tick(Duration::from_millis(50));
+            loop {
+                crossbeam_channel::select! {
+                    recv(ticker) -> _ => if let Err(e) = this.trigger_compaction() {
+                        eprintln!("compaction failed: {}", e);
+                    },
+                    recv(rx) -> _ => return
                 }
-            });
-            return Ok(Some(handle));
-        }
-        Ok(None)
+            }
+        });
+        Ok(Some(handle))
     }
 
     fn trigger_flush(&self) -> Result<()> {
-        let res = {
-            let state = self.state.read();
-            state.imm_memtables.len() >= self.options.num_memtable_limit
-        };
+        let res = self.column_families().iter().any(|cf| {
+            let state = cf.state.read();
+            state.imm_memtables.len() >= cf.options.num_memtable_limit
+        });
         if res {
             self.force_flush_next_imm_memtable()?;
         }
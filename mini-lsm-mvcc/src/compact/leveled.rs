@@ -2,6 +2,7 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
+use super::{can_trivially_move, sst_bytes};
 use crate::lsm_storage::LsmStorageState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,9 +13,12 @@ pub struct LeveledCompactionTask {
     pub lower_level: usize,
     pub lower_level_sst_ids: Vec<usize>,
     pub is_lower_level_bottom_level: bool,
+    // if true, the upper level SSTs are moved to the lower level without being rewritten
+    #[serde(default)]
+    pub is_trivial_move: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LeveledCompactionOptions {
     pub level_size_multiplier: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -61,11 +65,8 @@ impl LeveledCompactionController {
         overlap_ssts
     }
 
-    pub fn generate_compaction_task(
-        &self,
-        snapshot: &LsmStorageState,
-    ) -> Option<LeveledCompactionTask> {
-        // step 1: compute target level size
+    /// The target and real sizes of the levels excluding level 0, and the base level.
+    fn level_sizes(&self, snapshot: &LsmStorageState) -> (Vec<usize>, Vec<usize>, usize) {
         let mut target_level_size = (0..self.options.max_levels).map(|_| 0).collect::<Vec<_>>(); // exclude level 0
         let mut real_level_size = Vec::with_capacity(self.options.max_levels);
         let mut base_level = self.options.max_levels;
@@ -93,19 +94,45 @@ impl LeveledCompactionController {
                 base_level = i + 1;
             }
         }
+        (target_level_size, real_level_size, base_level)
+    }
+
+    /// The bytes of L0 once it reaches the compaction trigger, plus the bytes of each level above
+    /// its target size.
+    pub fn pending_compaction_bytes(&self, snapshot: &LsmStorageState) -> u64 {
+        let (target_level_size, real_level_size, _) = self.level_sizes(snapshot);
+        let mut bytes = 0;
+        if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
+            bytes += sst_bytes(snapshot, &snapshot.l0_sstables);
+        }
+        for (real, target) in real_level_size.iter().zip(&target_level_size) {
+            bytes += real.saturating_sub(*target) as u64;
+        }
+        bytes
+    }
+
+    pub fn generate_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<LeveledCompactionTask> {
+        // step 1: compute target level size
+        let (target_level_size, real_level_size, base_level) = self.level_sizes(snapshot);
 
         // Flush L0 SST is the top priority
         if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
             println!("flush L0 SST to base level {}", base_level);
+            let lower_level_sst_ids =
+                self.find_overlapping_ssts(snapshot, &snapshot.l0_sstables, base_level);
             return Some(LeveledCompactionTask {
                 upper_level: None,
                 upper_level_sst_ids: snapshot.l0_sstables.clone(),
                 lower_level: base_level,
-                lower_level_sst_ids: self.find_overlapping_ssts(
+                is_trivial_move: can_trivially_move(
                     snapshot,
                     &snapshot.l0_sstables,
-                    base_level,
+                    &lower_level_sst_ids,
                 ),
+                lower_level_sst_ids,
                 is_lower_level_bottom_level: base_level == self.options.max_levels,
             });
         }
@@ -140,15 +167,18 @@ impl LeveledCompactionController {
                 "compaction triggered by priority: {level} out of {:?}, select {selected_sst} for compaction",
                 priorities
             );
+            let lower_level_sst_ids =
+                self.find_overlapping_ssts(snapshot, &[selected_sst], level + 1);
             return Some(LeveledCompactionTask {
                 upper_level: Some(level),
                 upper_level_sst_ids: vec![selected_sst],
                 lower_level: level + 1,
-                lower_level_sst_ids: self.find_overlapping_ssts(
+                is_trivial_move: can_trivially_move(
                     snapshot,
                     &[selected_sst],
-                    level + 1,
+                    &lower_level_sst_ids,
                 ),
+                lower_level_sst_ids,
                 is_lower_level_bottom_level: level + 1 == self.options.max_levels,
             });
         }
@@ -203,6 +233,8 @@ impl LeveledCompactionController {
 
         files_to_remove.extend(&task.upper_level_sst_ids);
         files_to_remove.extend(&task.lower_level_sst_ids);
+        // the input SSTs that are also in the output are relinked instead of removed
+        files_to_remove.retain(|x| !output.contains(x));
 
         let mut new_lower_level_ssts = snapshot.levels[task.lower_level - 1]
             .1
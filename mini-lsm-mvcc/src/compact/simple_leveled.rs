@@ -2,9 +2,10 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
+use super::{can_trivially_move, sst_bytes};
 use crate::lsm_storage::LsmStorageState;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleLeveledCompactionOptions {
     pub size_ratio_percent: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -19,6 +20,9 @@ pub struct SimpleLeveledCompactionTask {
     pub lower_level: usize,
     pub lower_level_sst_ids: Vec<usize>,
     pub is_lower_level_bottom_level: bool,
+    // if true, the upper level SSTs are moved to the lower level without being rewritten
+    #[serde(default)]
+    pub is_trivial_move: bool,
 }
 
 pub struct SimpleLeveledCompactionController {
@@ -30,6 +34,23 @@ impl SimpleLeveledCompactionController {
         Self { options }
     }
 
+    /// The bytes of the upper levels that are to be compacted to their lower levels, i.e., L0
+    /// once it reaches the compaction trigger and the levels that are over the size ratio.
+    pub fn pending_compaction_bytes(&self, snapshot: &LsmStorageState) -> u64 {
+        let mut bytes = 0;
+        if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
+            bytes += sst_bytes(snapshot, &snapshot.l0_sstables);
+        }
+        for pair in snapshot.levels.windows(2) {
+            let (upper, lower) = (&pair[0].1, &pair[1].1);
+            let size_ratio = lower.len() as f64 / upper.len() as f64;
+            if size_ratio < self.options.size_ratio_percent as f64 / 100.0 {
+                bytes += sst_bytes(snapshot, upper);
+            }
+        }
+        bytes
+    }
+
     /// Generates a compaction task.
     ///
     /// Returns `None` if no compaction needs to be scheduled. The order of SSTs in the compaction task id vector matters.
@@ -57,15 +78,22 @@ impl SimpleLeveledCompactionController {
                     "compaction triggered at level {} and {} with size ratio {}",
                     i, lower_level, size_ratio
                 );
+                let upper_level_sst_ids = if i == 0 {
+                    snapshot.l0_sstables.clone()
+                } else {
+                    snapshot.levels[i - 1].1.clone()
+                };
+                let lower_level_sst_ids = snapshot.levels[lower_level - 1].1.clone();
                 return Some(SimpleLeveledCompactionTask {
                     upper_level: if i == 0 { None } else { Some(i) },
-                    upper_level_sst_ids: if i == 0 {
-                        snapshot.l0_sstables.clone()
-                    } else {
-                        snapshot.levels[i - 1].1.clone()
-                    },
+                    is_trivial_move: can_trivially_move(
+                        snapshot,
+                        &upper_level_sst_ids,
+                        &lower_level_sst_ids,
+                    ),
+                    upper_level_sst_ids,
                     lower_level,
-                    lower_level_sst_ids: snapshot.levels[lower_level - 1].1.clone(),
+                    lower_level_sst_ids,
                     is_lower_level_bottom_level: lower_level == self.options.max_levels,
                 });
             }
@@ -118,6 +146,8 @@ impl SimpleLeveledCompactionController {
             "sst mismatched"
         );
         files_to_remove.extend(&snapshot.levels[task.lower_level - 1].1);
+        // the input SSTs that are also in the output are relinked instead of removed
+        files_to_remove.retain(|x| !output.contains(x));
         snapshot.levels[task.lower_level - 1].1 = output.to_vec();
         (snapshot, files_to_remove)
     }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::sst_bytes;
 use crate::lsm_storage::LsmStorageState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -10,7 +11,7 @@ pub struct TieredCompactionTask {
     pub bottom_tier_included: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TieredCompactionOptions {
     pub num_tiers: usize,
     pub max_size_amplification_percent: usize,
@@ -27,6 +28,17 @@ impl TieredCompactionController {
         Self { options }
     }
 
+    /// The bytes of the tiers above the bottom tier once there are enough tiers to compact.
+    pub fn pending_compaction_bytes(&self, snapshot: &LsmStorageState) -> u64 {
+        if snapshot.levels.len() < self.options.num_tiers {
+            return 0;
+        }
+        snapshot.levels[..snapshot.levels.len() - 1]
+            .iter()
+            .map(|(_, ssts)| sst_bytes(snapshot, ssts))
+            .sum()
+    }
+
     pub fn generate_compaction_task(
         &self,
         snapshot: &LsmStorageState,
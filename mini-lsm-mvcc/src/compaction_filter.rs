@@ -0,0 +1,50 @@
+use bytes::Bytes;
+
+/// What compaction does with a version of a key passed to a compaction filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactionDecision {
+    Keep,
+    /// Remove the key. Its older versions are removed as well, and a delete tombstone is written
+    /// unless the compaction goes to the bottom level, so that the versions in the lower levels do
+    /// not show up again.
+    Remove,
+    /// Replace the value of the version, which may be empty.
+    ChangeValue(Bytes),
+}
+
+/// Application logic that runs during compaction, e.g. to drop the expired keys or rewrite the
+/// values to a new schema.
+///
+/// Only the latest version of each key at or below the watermark is passed to the filters, as
+/// the older ones are dropped anyway. The versions newer than the watermark are kept as they are
+/// until a later compaction, while the oldest running txn may see the effect of a filter. Delete
+/// tombstones, range tombstones, expired values and merges are not passed.
+pub trait CompactionFilter: Send + Sync {
+    /// Decide what to do with the version of `key` at `ts`. `level` is the level that compaction
+    /// writes to. For tiered compaction, it is 1, or `usize::MAX` if the bottom tier is included.
+    fn filter(&self, key: &[u8], ts: u64, value: &[u8], level: usize) -> CompactionDecision;
+}
+
+/// Removes all keys that start with a prefix.
+#[derive(Debug, Clone)]
+pub struct PrefixCompactionFilter {
+    prefix: Bytes,
+}
+
+impl PrefixCompactionFilter {
+    pub fn new(prefix: impl Into<Bytes>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl CompactionFilter for PrefixCompactionFilter {
+    fn filter(&self, key: &[u8], _ts: u64, _value: &[u8], _level: usize) -> CompactionDecision {
+        if key.starts_with(&self.prefix) {
+            CompactionDecision::Remove
+        } else {
+            CompactionDecision::Keep
+        }
+    }
+}
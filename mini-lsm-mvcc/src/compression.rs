@@ -0,0 +1,78 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// The compression algorithm of a data block. It is stored as a single byte after each block in
+/// the SST file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionType {
+    None,
+    Lz4,
+    Snappy,
+    /// Zstd with the given compression level.
+    Zstd(i32),
+}
+
+impl CompressionType {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            CompressionType::None => 0,
+            CompressionType::Lz4 => 1,
+            CompressionType::Snappy => 2,
+            CompressionType::Zstd(_) => 3,
+        }
+    }
+
+    /// Get the compression type from its byte. The zstd level is not needed for decompression.
+    pub fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            0 => CompressionType::None,
+            1 => CompressionType::Lz4,
+            2 => CompressionType::Snappy,
+            3 => CompressionType::Zstd(0),
+            _ => bail!("unknown compression type {}", byte),
+        })
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Lz4 => lz4_flex::compress_prepend_size(data),
+            CompressionType::Snappy => snap::raw::Encoder::new().compress_vec(data)?,
+            CompressionType::Zstd(level) => zstd::bulk::compress(data, level)?,
+        })
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            CompressionType::None => data.to_vec(),
+            CompressionType::Lz4 => lz4_flex::decompress_size_prepended(data)?,
+            CompressionType::Snappy => snap::raw::Decoder::new().decompress_vec(data)?,
+            CompressionType::Zstd(_) => zstd::stream::decode_all(data)?,
+        })
+    }
+}
+
+/// The compression of data blocks on each level. L0 uses the first entry, L1 uses the second, and
+/// so on. Levels beyond the end of the list use the last entry, and an empty list disables
+/// compression.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompressionOptions {
+    pub per_level: Vec<CompressionType>,
+}
+
+impl CompressionOptions {
+    /// Use the same compression on all levels.
+    pub fn all_levels(compression: CompressionType) -> Self {
+        Self {
+            per_level: vec![compression],
+        }
+    }
+
+    pub fn for_level(&self, level: usize) -> CompressionType {
+        self.per_level
+            .get(level)
+            .or(self.per_level.last())
+            .copied()
+            .unwrap_or(CompressionType::None)
+    }
+}
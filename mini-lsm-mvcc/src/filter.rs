@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::table::bloom::Bloom;
+
+/// The filter of the keys in an SST. Its tag is stored with the filter, and SSTs without a tag use
+/// the standard bloom filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterType {
+    /// A standard bloom filter with the given bits per key.
+    Bloom(usize),
+    /// A bloom filter whose probes for a key all fall into one 64-byte cache line, with the given
+    /// bits per key. It needs a few more bits than a standard bloom filter for the same false
+    /// positive rate.
+    BlockedBloom(usize),
+    /// An xor filter with 8-bit fingerprints, which takes about 9.9 bits per key for a false
+    /// positive rate of about 0.4%.
+    Xor8,
+}
+
+impl Default for FilterType {
+    /// A standard bloom filter with a false positive rate of about 1%.
+    fn default() -> Self {
+        FilterType::Bloom(Bloom::bloom_bits_per_key(1, 0.01))
+    }
+}
+
+impl FilterType {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            FilterType::Bloom(_) => 0,
+            FilterType::BlockedBloom(_) => 1,
+            FilterType::Xor8 => 2,
+        }
+    }
+
+    /// Get the filter type from its byte. The bits per key are not needed to probe a filter.
+    pub fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            0 => FilterType::Bloom(0),
+            1 => FilterType::BlockedBloom(0),
+            2 => FilterType::Xor8,
+            _ => bail!("unknown filter type {}", byte),
+        })
+    }
+}
+
+/// The filter of the SSTs on each level. L0 uses the first entry, L1 uses the second, and so on.
+/// Levels beyond the end of the list use the last entry, and an empty list uses the default filter.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilterOptions {
+    pub per_level: Vec<FilterType>,
+}
+
+impl FilterOptions {
+    /// Use the same filter on all levels.
+    pub fn all_levels(filter: FilterType) -> Self {
+        Self {
+            per_level: vec![filter],
+        }
+    }
+
+    pub fn for_level(&self, level: usize) -> FilterType {
+        self.per_level
+            .get(level)
+            .or(self.per_level.last())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// How well the SST filters worked for point lookups.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterStats {
+    /// The number of times a filter was checked.
+    pub checks: u64,
+    /// The checks that ruled out the SST, so that it was not read.
+    pub negatives: u64,
+    /// The checks that passed, but the SST did not have the key.
+    pub false_positives: u64,
+}
+
+impl FilterStats {
+    /// The share of the checks of absent keys that passed.
+    pub fn false_positive_rate(&self) -> f64 {
+        let absent = self.negatives + self.false_positives;
+        if absent == 0 {
+            return 0.0;
+        }
+        self.false_positives as f64 / absent as f64
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FilterStatsRecorder {
+    checks: AtomicU64,
+    negatives: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl FilterStatsRecorder {
+    pub(crate) fn record_check(&self, may_contain: bool) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        if !may_contain {
+            self.negatives.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_false_positive(&self) {
+        self.false_positives.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> FilterStats {
+        FilterStats {
+            checks: self.checks.load(Ordering::Relaxed),
+            negatives: self.negatives.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -2,14 +2,22 @@ pub mod concat_iterator;
 pub mod merge_iterator;
 pub mod two_merge_iterator;
 
+use crate::value_type::ValueType;
+
 pub trait StorageIterator {
-    type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord
+    type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord + Copy
     where
         Self: 'a;
 
     /// Get the current value.
     fn value(&self) -> &[u8];
 
+    /// Get the type of the current record. Iterators over untyped values treat an empty value as a
+    /// delete tombstone.
+    fn value_type(&self) -> ValueType {
+        ValueType::of_untyped(self.value())
+    }
+
     /// Get the current key.
     fn key(&self) -> Self::KeyType<'_>;
 
@@ -19,6 +27,21 @@ pub trait StorageIterator {
     /// Move to the next position.
     fn next(&mut self) -> anyhow::Result<()>;
 
+    /// Move to the previous position.
+    fn prev(&mut self) -> anyhow::Result<()> {
+        anyhow::bail!("backward iteration is not supported")
+    }
+
+    /// Move to the first position whose key is >= `key`.
+    fn seek(&mut self, _key: Self::KeyType<'_>) -> anyhow::Result<()> {
+        anyhow::bail!("seek is not supported")
+    }
+
+    /// Move to the last position.
+    fn seek_to_last(&mut self) -> anyhow::Result<()> {
+        anyhow::bail!("seek is not supported")
+    }
+
     /// Number of underlying active iterators for this iterator.
     fn num_active_iterators(&self) -> usize {
         1
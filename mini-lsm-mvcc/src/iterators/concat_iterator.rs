@@ -5,6 +5,7 @@ use anyhow::Result;
 use crate::{
     key::KeySlice,
     table::{SsTable, SsTableIterator},
+    value_type::ValueType,
 };
 
 use super::StorageIterator;
@@ -51,28 +52,33 @@ impl SstConcatIterator {
 
     pub fn create_and_seek_to_key(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
         Self::check_sst_valid(&sstables);
-        let idx: usize = sstables
-            .partition_point(|table| table.first_key().as_key_slice() <= key)
-            .saturating_sub(1);
-        if idx >= sstables.len() {
-            return Ok(Self {
-                current: None,
-                next_sst_idx: sstables.len(),
-                sstables,
-            });
-        }
         let mut iter = Self {
-            current: Some(SsTableIterator::create_and_seek_to_key(
-                sstables[idx].clone(),
-                key,
-            )?),
-            next_sst_idx: idx + 1,
+            current: None,
+            next_sst_idx: 0,
             sstables,
         };
-        iter.move_until_valid()?;
+        iter.seek_to_key(key)?;
         Ok(iter)
     }
 
+    fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
+        let idx: usize = self
+            .sstables
+            .partition_point(|table| table.first_key().as_key_slice() <= key)
+            .saturating_sub(1);
+        if idx >= self.sstables.len() {
+            self.current = None;
+            self.next_sst_idx = self.sstables.len();
+            return Ok(());
+        }
+        self.current = Some(SsTableIterator::create_and_seek_to_key(
+            self.sstables[idx].clone(),
+            key,
+        )?);
+        self.next_sst_idx = idx + 1;
+        self.move_until_valid()
+    }
+
     fn move_until_valid(&mut self) -> Result<()> {
         while let Some(iter) = self.current.as_mut() {
             if iter.is_valid() {
@@ -89,6 +95,25 @@ impl SstConcatIterator {
         }
         Ok(())
     }
+
+    /// Move to the last key of the previous SSTs if the current SST is exhausted.
+    fn move_back_until_valid(&mut self) -> Result<()> {
+        while let Some(iter) = self.current.as_mut() {
+            if iter.is_valid() {
+                break;
+            }
+            // `next_sst_idx - 1` is the index of the current SST.
+            if self.next_sst_idx <= 1 {
+                self.current = None;
+            } else {
+                self.next_sst_idx -= 1;
+                self.current = Some(SsTableIterator::create_and_seek_to_last(
+                    self.sstables[self.next_sst_idx - 1].clone(),
+                )?);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl StorageIterator for SstConcatIterator {
@@ -102,6 +127,10 @@ impl StorageIterator for SstConcatIterator {
         self.current.as_ref().unwrap().value()
     }
 
+    fn value_type(&self) -> ValueType {
+        self.current.as_ref().unwrap().value_type()
+    }
+
     fn is_valid(&self) -> bool {
         if let Some(current) = &self.current {
             assert!(current.is_valid());
@@ -117,6 +146,25 @@ impl StorageIterator for SstConcatIterator {
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        self.current.as_mut().unwrap().prev()?;
+        self.move_back_until_valid()?;
+        Ok(())
+    }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        self.seek_to_key(key)
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.next_sst_idx = self.sstables.len();
+        self.current = match self.sstables.last() {
+            Some(table) => Some(SsTableIterator::create_and_seek_to_last(table.clone())?),
+            None => None,
+        };
+        Ok(())
+    }
+
     fn num_active_iterators(&self) -> usize {
         1
     }
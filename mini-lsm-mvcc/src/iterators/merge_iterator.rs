@@ -5,10 +5,13 @@ use std::collections::BinaryHeap;
 use anyhow::Result;
 
 use crate::key::KeySlice;
+use crate::value_type::ValueType;
 
 use super::StorageIterator;
 
-struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
+/// An iterator in the heap, with its index and whether the heap is ordered for backward
+/// iteration.
+struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>, pub bool);
 
 impl<I: StorageIterator> PartialEq for HeapWrapper<I> {
     fn eq(&self, other: &Self) -> bool {
@@ -21,12 +24,18 @@ impl<I: StorageIterator> Eq for HeapWrapper<I> {}
 impl<I: StorageIterator> PartialOrd for HeapWrapper<I> {
     #[allow(clippy::non_canonical_partial_ord_impl)]
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        match self.1.key().cmp(&other.1.key()) {
+        // The heap top is the smallest key when moving forward and the largest key when moving
+        // backward. The iterator with the smaller index always wins a tie.
+        let ord = match self.1.key().cmp(&other.1.key()) {
             cmp::Ordering::Greater => Some(cmp::Ordering::Greater),
             cmp::Ordering::Less => Some(cmp::Ordering::Less),
-            cmp::Ordering::Equal => self.0.partial_cmp(&other.0),
+            cmp::Ordering::Equal => return self.0.partial_cmp(&other.0).map(|x| x.reverse()),
+        };
+        if self.2 {
+            ord
+        } else {
+            ord.map(|x| x.reverse())
         }
-        .map(|x| x.reverse())
     }
 }
 
@@ -41,92 +50,124 @@ impl<I: StorageIterator> Ord for HeapWrapper<I> {
 pub struct MergeIterator<I: StorageIterator> {
     iters: BinaryHeap<HeapWrapper<I>>,
     current: Option<HeapWrapper<I>>,
+    /// Iterators that are no longer valid in the current direction. They are kept so that they
+    /// can be repositioned when seeking or changing direction.
+    exhausted: Vec<HeapWrapper<I>>,
+    backward: bool,
 }
 
 impl<I: StorageIterator> MergeIterator<I> {
     pub fn create(iters: Vec<Box<I>>) -> Self {
-        if iters.is_empty() {
-            return Self {
-                iters: BinaryHeap::new(),
-                current: None,
-            };
-        }
-
-        let mut heap = BinaryHeap::new();
+        let mut iter = Self {
+            iters: BinaryHeap::new(),
+            current: None,
+            exhausted: Vec::new(),
+            backward: false,
+        };
+        iter.rebuild(
+            iters
+                .into_iter()
+                .enumerate()
+                .map(|(idx, iter)| HeapWrapper(idx, iter, false))
+                .collect(),
+        );
+        iter
+    }
 
-        if iters.iter().all(|x| !x.is_valid()) {
-            // All invalid, select the last one as the current.
-            let mut iters = iters;
-            return Self {
-                iters: heap,
-                current: Some(HeapWrapper(0, iters.pop().unwrap())),
-            };
-        }
+    fn take_all(&mut self) -> Vec<HeapWrapper<I>> {
+        let mut iters = std::mem::take(&mut self.iters).into_vec();
+        iters.append(&mut self.exhausted);
+        iters.extend(self.current.take());
+        iters
+    }
 
-        for (idx, iter) in iters.into_iter().enumerate() {
-            if iter.is_valid() {
-                heap.push(HeapWrapper(idx, iter));
+    /// Rebuild the heap from all iterators, which are already positioned for the current
+    /// direction.
+    fn rebuild(&mut self, iters: Vec<HeapWrapper<I>>) {
+        let mut heap = BinaryHeap::new();
+        for mut iter in iters {
+            iter.2 = self.backward;
+            if iter.1.is_valid() {
+                heap.push(iter);
+            } else {
+                self.exhausted.push(iter);
             }
         }
-
-        let current = heap.pop().unwrap();
-        Self {
-            iters: heap,
-            current: Some(current),
-        }
+        // If all iterators are invalid, select any of them as the current.
+        self.current = heap.pop().or_else(|| self.exhausted.pop());
+        self.iters = heap;
     }
 }
 
-impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIterator
-    for MergeIterator<I>
-{
-    type KeyType<'a> = KeySlice<'a>;
-
-    fn key(&self) -> KeySlice {
-        self.current.as_ref().unwrap().1.key()
-    }
-
-    fn value(&self) -> &[u8] {
-        self.current.as_ref().unwrap().1.value()
-    }
-
-    fn is_valid(&self) -> bool {
-        self.current
-            .as_ref()
-            .map(|x| x.1.is_valid())
-            .unwrap_or(false)
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> MergeIterator<I> {
+    /// Move all iterators except the current one to the current key, so that the iteration can
+    /// continue in the other direction.
+    fn switch_direction(&mut self) -> Result<()> {
+        let key = self.current.as_ref().unwrap().1.key();
+        let mut iters = std::mem::take(&mut self.iters).into_vec();
+        iters.append(&mut self.exhausted);
+        for iter in iters.iter_mut() {
+            iter.1.seek(key)?;
+            if !self.backward {
+                // Move to the last key that is < the current key.
+                if iter.1.is_valid() {
+                    iter.1.prev()?;
+                } else {
+                    iter.1.seek_to_last()?;
+                }
+            }
+        }
+        self.backward = !self.backward;
+        iters.extend(self.current.take());
+        self.rebuild(iters);
+        Ok(())
     }
 
-    fn next(&mut self) -> Result<()> {
+    /// Move the current iterator forward or backward, and skip the same key in other iterators.
+    fn step(&mut self) -> Result<()> {
+        let backward = self.backward;
         let current = self.current.as_mut().unwrap();
         // Pop the item out of the heap if they have the same value.
         while let Some(mut inner_iter) = self.iters.peek_mut() {
             debug_assert!(
-                inner_iter.1.key() >= current.1.key(),
+                if backward {
+                    inner_iter.1.key() <= current.1.key()
+                } else {
+                    inner_iter.1.key() >= current.1.key()
+                },
                 "heap invariant violated"
             );
             if inner_iter.1.key() == current.1.key() {
+                let result = if backward {
+                    inner_iter.1.prev()
+                } else {
+                    inner_iter.1.next()
+                };
                 // Case 1: an error occurred when calling `next`.
-                if let e @ Err(_) = inner_iter.1.next() {
+                if let e @ Err(_) = result {
                     PeekMut::pop(inner_iter);
                     return e;
                 }
 
                 // Case 2: iter is no longer valid.
                 if !inner_iter.1.is_valid() {
-                    PeekMut::pop(inner_iter);
+                    self.exhausted.push(PeekMut::pop(inner_iter));
                 }
             } else {
                 break;
             }
         }
 
-        current.1.next()?;
+        if backward {
+            current.1.prev()?;
+        } else {
+            current.1.next()?;
+        }
 
         // If the current iterator is invalid, pop it out of the heap and select the next one.
         if !current.1.is_valid() {
             if let Some(iter) = self.iters.pop() {
-                *current = iter;
+                self.exhausted.push(std::mem::replace(current, iter));
             }
             return Ok(());
         }
@@ -140,6 +181,65 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
 
         Ok(())
     }
+}
+
+impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIterator
+    for MergeIterator<I>
+{
+    type KeyType<'a> = KeySlice<'a>;
+
+    fn key(&self) -> KeySlice {
+        self.current.as_ref().unwrap().1.key()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.current.as_ref().unwrap().1.value()
+    }
+
+    fn value_type(&self) -> ValueType {
+        self.current.as_ref().unwrap().1.value_type()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.current
+            .as_ref()
+            .map(|x| x.1.is_valid())
+            .unwrap_or(false)
+    }
+
+    fn next(&mut self) -> Result<()> {
+        if self.backward {
+            self.switch_direction()?;
+        }
+        self.step()
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        if !self.backward {
+            self.switch_direction()?;
+        }
+        self.step()
+    }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        let mut iters = self.take_all();
+        for iter in iters.iter_mut() {
+            iter.1.seek(key)?;
+        }
+        self.backward = false;
+        self.rebuild(iters);
+        Ok(())
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        let mut iters = self.take_all();
+        for iter in iters.iter_mut() {
+            iter.1.seek_to_last()?;
+        }
+        self.backward = true;
+        self.rebuild(iters);
+        Ok(())
+    }
 
     fn num_active_iterators(&self) -> usize {
         self.iters
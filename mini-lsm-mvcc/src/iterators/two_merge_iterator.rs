@@ -1,5 +1,7 @@
 use anyhow::Result;
 
+use crate::value_type::ValueType;
+
 use super::StorageIterator;
 
 /// Merges two iterators of different types into one. If the two iterators have the same key, only
@@ -8,6 +10,7 @@ pub struct TwoMergeIterator<A: StorageIterator, B: StorageIterator> {
     a: A,
     b: B,
     choose_a: bool,
+    backward: bool,
 }
 
 impl<
@@ -15,33 +18,84 @@ impl<
         B: 'static + for<'a> StorageIterator<KeyType<'a> = A::KeyType<'a>>,
     > TwoMergeIterator<A, B>
 {
-    fn choose_a(a: &A, b: &B) -> bool {
+    fn choose_a(a: &A, b: &B, backward: bool) -> bool {
         if !a.is_valid() {
             return false;
         }
         if !b.is_valid() {
             return true;
         }
-        a.key() < b.key()
+        if backward {
+            a.key() > b.key()
+        } else {
+            a.key() < b.key()
+        }
     }
 
     fn skip_b(&mut self) -> Result<()> {
         if self.a.is_valid() && self.b.is_valid() && self.b.key() == self.a.key() {
-            self.b.next()?;
+            if self.backward {
+                self.b.prev()?;
+            } else {
+                self.b.next()?;
+            }
         }
         Ok(())
     }
 
+    fn update_choice(&mut self) -> Result<()> {
+        self.skip_b()?;
+        self.choose_a = Self::choose_a(&self.a, &self.b, self.backward);
+        Ok(())
+    }
+
     pub fn create(a: A, b: B) -> Result<Self> {
         let mut iter = Self {
             choose_a: false,
+            backward: false,
             a,
             b,
         };
-        iter.skip_b()?;
-        iter.choose_a = Self::choose_a(&iter.a, &iter.b);
+        iter.update_choice()?;
         Ok(iter)
     }
+
+    /// Move the iterator that is not chosen to the current key, so that the iteration can
+    /// continue in the other direction.
+    fn switch_direction(&mut self) -> Result<()> {
+        if self.choose_a {
+            self.b.seek(self.a.key())?;
+        } else {
+            self.a.seek(self.b.key())?;
+        }
+        if !self.backward {
+            // Move to the last key that is < the current key.
+            let other_is_valid = if self.choose_a {
+                self.b.is_valid()
+            } else {
+                self.a.is_valid()
+            };
+            match (self.choose_a, other_is_valid) {
+                (true, true) => self.b.prev()?,
+                (true, false) => self.b.seek_to_last()?,
+                (false, true) => self.a.prev()?,
+                (false, false) => self.a.seek_to_last()?,
+            }
+        }
+        self.backward = !self.backward;
+        // Skip the current key in B if A is chosen.
+        self.skip_b()
+    }
+
+    fn step(&mut self) -> Result<()> {
+        match (self.choose_a, self.backward) {
+            (true, false) => self.a.next()?,
+            (true, true) => self.a.prev()?,
+            (false, false) => self.b.next()?,
+            (false, true) => self.b.prev()?,
+        }
+        self.update_choice()
+    }
 }
 
 impl<
@@ -69,6 +123,14 @@ impl<
         }
     }
 
+    fn value_type(&self) -> ValueType {
+        if self.choose_a {
+            self.a.value_type()
+        } else {
+            self.b.value_type()
+        }
+    }
+
     fn is_valid(&self) -> bool {
         if self.choose_a {
             self.a.is_valid()
@@ -78,14 +140,31 @@ impl<
     }
 
     fn next(&mut self) -> Result<()> {
-        if self.choose_a {
-            self.a.next()?;
-        } else {
-            self.b.next()?;
+        if self.backward {
+            self.switch_direction()?;
         }
-        self.skip_b()?;
-        self.choose_a = Self::choose_a(&self.a, &self.b);
-        Ok(())
+        self.step()
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        if !self.backward {
+            self.switch_direction()?;
+        }
+        self.step()
+    }
+
+    fn seek(&mut self, key: A::KeyType<'_>) -> Result<()> {
+        self.a.seek(key)?;
+        self.b.seek(key)?;
+        self.backward = false;
+        self.update_choice()
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.a.seek_to_last()?;
+        self.b.seek_to_last()?;
+        self.backward = true;
+        self.update_choice()
     }
 
     fn num_active_iterators(&self) -> usize {
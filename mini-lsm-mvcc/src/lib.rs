@@ -1,15 +1,30 @@
 pub mod block;
+pub mod block_cache;
+pub mod checkpoint;
+pub mod column_family;
+pub mod column_family_debug;
 pub mod compact;
+pub mod compaction_filter;
+pub mod compression;
 pub mod debug;
+pub mod filter;
 pub mod iterators;
 pub mod key;
 pub mod lsm_iterator;
 pub mod lsm_storage;
 pub mod manifest;
 pub mod mem_table;
+pub mod merge_operator;
 pub mod mvcc;
+pub mod prefix_extractor;
+pub mod range_tombstone;
+pub mod rate_limiter;
 pub mod table;
+pub mod value_type;
+pub mod varint;
+pub mod vlog;
 pub mod wal;
+pub mod write_stall;
 
 #[cfg(test)]
 mod tests;
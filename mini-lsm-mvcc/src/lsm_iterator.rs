@@ -1,4 +1,5 @@
 use std::ops::Bound;
+use std::sync::Arc;
 
 use anyhow::{bail, Result};
 use bytes::Bytes;
@@ -7,8 +8,13 @@ use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
+use crate::key::{self, KeySlice};
 use crate::mem_table::MemTableIterator;
+use crate::merge_operator::MergeOperator;
+use crate::range_tombstone::RangeTombstone;
 use crate::table::SsTableIterator;
+use crate::value_type::ValueType;
+use crate::vlog::{self, StoredValue, ValueLogReader};
 
 /// Represents the internal type for an LSM iterator. This type will be changed across the tutorial for multiple times.
 type LsmIteratorInner = TwoMergeIterator<
@@ -18,68 +24,297 @@ type LsmIteratorInner = TwoMergeIterator<
 
 pub struct LsmIterator {
     inner: LsmIteratorInner,
+    start_bound: Bound<Bytes>,
     end_bound: Bound<Bytes>,
     is_valid: bool,
     read_ts: u64,
+    /// The current key. When moving backward, the inner iterator is already positioned before
+    /// this key, and the current value is saved in `prev_value`. When moving forward, the inner
+    /// iterator is past the current version if the value is folded from merges.
     prev_key: Vec<u8>,
+    prev_value: Vec<u8>,
+    prev_value_type: ValueType,
+    backward: bool,
+    /// Range tombstones visible at `read_ts`.
+    range_tombstones: Vec<RangeTombstone>,
+    /// Resolves the values in the value log. Without it, the values are returned as stored.
+    value_log: Option<ValueLogReader>,
+    /// The current value if it is read from the value log, or folded from merges.
+    value: Option<Bytes>,
+    /// The values that expire before this time are deleted.
+    now: u64,
+    /// Folds the merges of a key with the value it is merged into.
+    merge_operator: Option<Arc<dyn MergeOperator>>,
 }
 
 impl LsmIterator {
     pub(crate) fn new(
         iter: LsmIteratorInner,
+        start_bound: Bound<Bytes>,
         end_bound: Bound<Bytes>,
         read_ts: u64,
+        range_tombstones: Vec<RangeTombstone>,
+        value_log: Option<ValueLogReader>,
+        merge_operator: Option<Arc<dyn MergeOperator>>,
     ) -> Result<Self> {
         let mut iter = Self {
             is_valid: iter.is_valid(),
             inner: iter,
+            start_bound,
             end_bound,
             read_ts,
             prev_key: Vec::new(),
+            prev_value: Vec::new(),
+            prev_value_type: ValueType::Delete,
+            backward: false,
+            range_tombstones,
+            value_log,
+            value: None,
+            now: vlog::now_millis(),
+            merge_operator,
         };
+        iter.check_bound();
         iter.move_to_key()?;
         Ok(iter)
     }
 
-    fn next_inner(&mut self) -> Result<()> {
-        self.inner.next()?;
+    /// Check if the inner iterator is still within the bound in the current direction.
+    fn check_bound(&mut self) {
         if !self.inner.is_valid() {
             self.is_valid = false;
-            return Ok(());
-        }
-        match self.end_bound.as_ref() {
-            Bound::Unbounded => {}
-            Bound::Included(key) => self.is_valid = self.inner.key().key_ref() <= key.as_ref(),
-            Bound::Excluded(key) => self.is_valid = self.inner.key().key_ref() < key.as_ref(),
+            return;
         }
+        let key = self.inner.key().key_ref();
+        self.is_valid = if self.backward {
+            match self.start_bound.as_ref() {
+                Bound::Unbounded => true,
+                Bound::Included(start) => key >= start.as_ref(),
+                Bound::Excluded(start) => key > start.as_ref(),
+            }
+        } else {
+            match self.end_bound.as_ref() {
+                Bound::Unbounded => true,
+                Bound::Included(end) => key <= end.as_ref(),
+                Bound::Excluded(end) => key < end.as_ref(),
+            }
+        };
+    }
+
+    fn next_inner(&mut self) -> Result<()> {
+        self.inner.next()?;
+        self.check_bound();
+        Ok(())
+    }
+
+    fn prev_inner(&mut self) -> Result<()> {
+        self.inner.prev()?;
+        self.check_bound();
         Ok(())
     }
 
+    /// Whether the current version of the inner iterator is deleted, by a tombstone, a range
+    /// tombstone, or as it has expired.
+    fn is_deleted(&self) -> bool {
+        self.inner.value_type() == ValueType::Delete
+            || RangeTombstone::any_covers(&self.range_tombstones, self.inner.key())
+            || vlog::is_expired(self.inner.value_type(), self.inner.value(), self.now)
+    }
+
     fn move_to_key(&mut self) -> Result<()> {
         loop {
-            while self.inner.is_valid() && self.inner.key().key_ref() == self.prev_key {
+            while self.is_valid && self.inner.key().key_ref() == self.prev_key {
                 self.next_inner()?;
             }
-            if !self.inner.is_valid() {
+            if !self.is_valid {
                 break;
             }
             self.prev_key.clear();
             self.prev_key.extend(self.inner.key().key_ref());
-            while self.inner.is_valid()
+            while self.is_valid
                 && self.inner.key().key_ref() == self.prev_key
                 && self.inner.key().ts() > self.read_ts
             {
                 self.next_inner()?;
             }
-            if !self.inner.is_valid() {
+            if !self.is_valid {
                 break;
             }
             if self.inner.key().key_ref() != self.prev_key {
                 continue;
             }
-            if !self.inner.value().is_empty() {
+            if !self.is_deleted() {
+                if self.inner.value_type() != ValueType::Merge {
+                    break;
+                }
+                if self.fold_forward()? {
+                    self.is_valid = true;
+                    return Ok(());
+                }
+            }
+        }
+        self.resolve_value()
+    }
+
+    /// Fold the merges of `prev_key` from the current version of the inner iterator down to the
+    /// value they are merged into. The inner iterator is left at the version where it stops.
+    fn fold_forward(&mut self) -> Result<bool> {
+        let mut merges = Vec::new();
+        let mut base = None;
+        while self.is_valid && self.inner.key().key_ref() == self.prev_key && !self.is_deleted() {
+            if self.inner.value_type() != ValueType::Merge {
+                base = Some((self.inner.value_type(), self.inner.value().to_vec()));
                 break;
             }
+            merges.push(self.inner.value().to_vec());
+            self.next_inner()?;
+        }
+        merges.reverse();
+        self.fold(
+            base.as_ref()
+                .map(|(value_type, base)| (*value_type, &base[..])),
+            &merges,
+        )
+    }
+
+    /// Apply the merges, from the oldest to the latest, to `base`, which is `None` if the key does
+    /// not exist before them, and save the result in `value`. Returns `false` if the key does not
+    /// exist. Without the value log, the result is `base` as stored, whose type is the one of the
+    /// version of the inner iterator or `prev_value_type`.
+    fn fold(&mut self, base: Option<(ValueType, &[u8])>, merges: &[Vec<u8>]) -> Result<bool> {
+        let Some(value_log) = &self.value_log else {
+            self.value = Some(Bytes::copy_from_slice(
+                base.map(|(_, base)| base).unwrap_or_default(),
+            ));
+            return Ok(base.is_some());
+        };
+        let Some(merge_operator) = &self.merge_operator else {
+            bail!("merge operator is not set");
+        };
+        let base = base
+            .map(|(value_type, base)| value_log.user_value(value_type, base))
+            .transpose()?;
+        let operands = merges
+            .iter()
+            .flat_map(|merge| vlog::merge_operands(merge).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let value = merge_operator.full_merge(&self.prev_key, base.as_deref(), &operands);
+        self.value = Some(value);
+        Ok(true)
+    }
+
+    /// Find the previous visible key. The inner iterator visits the versions of a key from the
+    /// oldest to the latest, so the latest visible version is known only after moving past all
+    /// versions of the key.
+    fn move_to_prev_key(&mut self) -> Result<()> {
+        // Whether `prev_key` holds a key that is not deleted at `read_ts`.
+        let mut found = false;
+        // The merges of `prev_key` after `prev_value`, from the oldest to the latest.
+        let mut merges = Vec::new();
+        // Whether `prev_value` holds the value that the merges are merged into.
+        let mut has_base = false;
+        // `is_valid` tells whether the previous key was found, so check the inner iterator again.
+        self.check_bound();
+        self.prev_key.clear();
+        self.prev_value.clear();
+        while self.is_valid {
+            if self.inner.key().ts() <= self.read_ts {
+                if found && self.inner.key().key_ref() < self.prev_key.as_slice() {
+                    if merges.is_empty() {
+                        break;
+                    }
+                    let base = std::mem::take(&mut self.prev_value);
+                    let base = has_base.then_some((self.prev_value_type, &base[..]));
+                    if self.fold(base, &merges)? {
+                        return Ok(());
+                    }
+                    found = false;
+                    merges.clear();
+                }
+                let deleted = self.is_deleted();
+                let key = self.inner.key();
+                if deleted {
+                    found = false;
+                    merges.clear();
+                    self.prev_key.clear();
+                    self.prev_value.clear();
+                } else if self.inner.value_type() == ValueType::Merge {
+                    if !found {
+                        found = true;
+                        has_base = false;
+                        self.prev_key.clear();
+                        self.prev_key.extend(key.key_ref());
+                        self.prev_value.clear();
+                    }
+                    merges.push(self.inner.value().to_vec());
+                } else {
+                    found = true;
+                    has_base = true;
+                    merges.clear();
+                    self.prev_key.clear();
+                    self.prev_key.extend(key.key_ref());
+                    self.prev_value.clear();
+                    self.prev_value.extend(self.inner.value());
+                    self.prev_value_type = self.inner.value_type();
+                }
+            }
+            self.prev_inner()?;
+        }
+        if found && !merges.is_empty() {
+            let base = std::mem::take(&mut self.prev_value);
+            let base = has_base.then_some((self.prev_value_type, &base[..]));
+            self.is_valid = self.fold(base, &merges)?;
+            return Ok(());
+        }
+        self.is_valid = found;
+        self.resolve_value()
+    }
+
+    /// Read the current value from the value log if it is stored there.
+    fn resolve_value(&mut self) -> Result<()> {
+        self.value = None;
+        if let (true, Some(value_log)) = (self.is_valid, &self.value_log) {
+            let value = if self.backward {
+                &self.prev_value
+            } else {
+                self.inner.value()
+            };
+            self.value = value_log.resolve(self.stored_value_type(), value)?;
+        }
+        Ok(())
+    }
+
+    /// The type of the current value as it is stored. A value folded from merges is stored at the
+    /// version that they are merged into.
+    fn stored_value_type(&self) -> ValueType {
+        if self.backward {
+            self.prev_value_type
+        } else {
+            self.inner.value_type()
+        }
+    }
+
+    /// Position the inner iterator at the last key that is within the end bound.
+    fn seek_inner_to_last(&mut self) -> Result<()> {
+        match self.end_bound.clone() {
+            Bound::Unbounded => self.inner.seek_to_last()?,
+            Bound::Included(end) => {
+                self.inner
+                    .seek(KeySlice::from_slice(&end, key::TS_RANGE_END))?;
+                if !self.inner.is_valid() {
+                    self.inner.seek_to_last()?;
+                } else if self.inner.key().key_ref() > end.as_ref() {
+                    self.inner.prev()?;
+                }
+            }
+            Bound::Excluded(end) => {
+                self.inner
+                    .seek(KeySlice::from_slice(&end, key::TS_RANGE_BEGIN))?;
+                if !self.inner.is_valid() {
+                    self.inner.seek_to_last()?;
+                } else {
+                    self.inner.prev()?;
+                }
+            }
         }
         Ok(())
     }
@@ -93,19 +328,95 @@ impl StorageIterator for LsmIterator {
     }
 
     fn key(&self) -> &[u8] {
-        self.inner.key().key_ref()
+        &self.prev_key
     }
 
     fn value(&self) -> &[u8] {
-        self.inner.value()
+        if let Some(value) = &self.value {
+            return value;
+        }
+        let value = if self.backward {
+            &self.prev_value
+        } else {
+            self.inner.value()
+        };
+        if self.value_log.is_none() {
+            return value;
+        }
+        match vlog::decode_value(self.stored_value_type(), value) {
+            StoredValue::Inline(value) => value,
+            StoredValue::Pointer(_) => unreachable!("value log pointer is not resolved"),
+        }
+    }
+
+    /// Without the value log, the type of the value as it is stored.
+    fn value_type(&self) -> ValueType {
+        if self.value_log.is_none() {
+            return self.stored_value_type();
+        }
+        // deleted keys are skipped
+        ValueType::Put
     }
 
     fn next(&mut self) -> Result<()> {
-        self.next_inner()?;
+        if self.backward {
+            // The inner iterator is before the current key. Move it to the current key, and
+            // `move_to_key` will skip all versions of it.
+            self.backward = false;
+            self.inner
+                .seek(KeySlice::from_slice(&self.prev_key, key::TS_RANGE_BEGIN))?;
+        }
+        // `move_to_key` skips the versions of the current key that are left.
+        self.check_bound();
         self.move_to_key()?;
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        if !self.backward {
+            // Move the inner iterator before all versions of the current key. It may be past them
+            // if the current value is folded from merges.
+            self.backward = true;
+            if !(self.inner.is_valid() && self.inner.key().key_ref() == self.prev_key) {
+                self.inner
+                    .seek(KeySlice::from_slice(&self.prev_key, key::TS_RANGE_BEGIN))?;
+            }
+            while self.inner.is_valid() && self.inner.key().key_ref() == self.prev_key {
+                self.inner.prev()?;
+            }
+        }
+        self.move_to_prev_key()
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        self.backward = false;
+        self.prev_key.clear();
+        let within_start = match self.start_bound.as_ref() {
+            Bound::Unbounded => true,
+            Bound::Included(start) => key >= start.as_ref(),
+            Bound::Excluded(start) => key > start.as_ref(),
+        };
+        if within_start {
+            self.inner
+                .seek(KeySlice::from_slice(key, key::TS_RANGE_BEGIN))?;
+        } else if let Bound::Included(start) | Bound::Excluded(start) = self.start_bound.clone() {
+            self.inner
+                .seek(KeySlice::from_slice(&start, key::TS_RANGE_BEGIN))?;
+            if matches!(self.start_bound, Bound::Excluded(_)) {
+                // `move_to_key` skips all versions of `prev_key`.
+                self.prev_key.extend(start.as_ref());
+            }
+        }
+        self.check_bound();
+        self.move_to_key()
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.backward = true;
+        self.seek_inner_to_last()?;
+        self.move_to_prev_key()
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.inner.num_active_iterators()
     }
@@ -149,6 +460,13 @@ impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
         self.iter.value()
     }
 
+    fn value_type(&self) -> ValueType {
+        if !self.is_valid() {
+            panic!("invalid access to the underlying iterator");
+        }
+        self.iter.value_type()
+    }
+
     fn next(&mut self) -> Result<()> {
         // only move when the iterator is valid and not errored
         if self.has_errored {
@@ -163,6 +481,41 @@ impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
         Ok(())
     }
 
+    fn prev(&mut self) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        if self.iter.is_valid() {
+            if let Err(e) = self.iter.prev() {
+                self.has_errored = true;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, key: Self::KeyType<'_>) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        if let Err(e) = self.iter.seek(key) {
+            self.has_errored = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        if let Err(e) = self.iter.seek_to_last() {
+            self.has_errored = true;
+            return Err(e);
+        }
+        Ok(())
+    }
+
     fn num_active_iterators(&self) -> usize {
         self.iter.num_active_iterators()
     }
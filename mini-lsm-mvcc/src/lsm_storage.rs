@@ -1,32 +1,46 @@
-use std::collections::{BTreeSet, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::ops::Bound;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bytes::Bytes;
 use parking_lot::{Mutex, MutexGuard, RwLock};
 
-use crate::block::Block;
-use crate::compact::{
-    CompactionController, CompactionOptions, LeveledCompactionController, LeveledCompactionOptions,
-    SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, TieredCompactionController,
+use crate::block_cache::BlockCacheStats;
+use crate::checkpoint::{self, BackupMode, CheckpointStats};
+use crate::column_family::{
+    ColumnFamily, ColumnFamilyOptions, DEFAULT_COLUMN_FAMILY_ID, DEFAULT_COLUMN_FAMILY_NAME,
 };
+use crate::compact::{CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions};
+use crate::compaction_filter::CompactionFilter;
+use crate::compression::CompressionOptions;
+use crate::filter::{FilterOptions, FilterStats, FilterStatsRecorder};
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
 use crate::iterators::StorageIterator;
 use crate::key::{self, KeySlice};
 use crate::lsm_iterator::{FusedIterator, LsmIterator};
-use crate::manifest::{Manifest, ManifestRecord};
+use crate::manifest::{ColumnFamilySnapshot, Manifest, ManifestRecord, ManifestSnapshot};
 use crate::mem_table::{map_bound, map_key_bound_plus_ts, MemTable};
+use crate::merge_operator::MergeOperator;
 use crate::mvcc::txn::{Transaction, TxnIterator};
-use crate::mvcc::LsmMvccInner;
+use crate::mvcc::{HistoryRetention, LsmMvccInner};
+use crate::prefix_extractor::PrefixExtractor;
+use crate::range_tombstone::RangeTombstone;
+use crate::rate_limiter::{IoPriority, RateLimiter};
 use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::value_type::ValueType;
+use crate::vlog::{self, StoredValue, ValueLog, ValueLogReader, ValueLogRecord};
+use crate::wal::ColumnFamilyBatch;
+use crate::write_stall::{WriteStall, WriteStallCondition, WriteStallOptions, WriteStallStats};
 
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+pub use crate::block_cache::BlockCache;
 
 /// Represents the state of the storage engine.
 #[derive(Clone)]
@@ -46,12 +60,28 @@ pub struct LsmStorageState {
 
 pub enum WriteBatchRecord<T: AsRef<[u8]>> {
     Put(T, T),
+    /// Put a key that expires after the TTL, counted from when the batch is written.
+    PutWithTtl(T, T, Duration),
+    /// Merge an operand into the value of a key with the merge operator.
+    Merge(T, T),
     Del(T),
+    /// Delete all keys in `[lower, upper)`.
+    DelRange(T, T),
+}
+
+/// A value of a write batch to store.
+enum BatchValue<'a> {
+    /// A value, which may be empty, with the TTL that it is put with.
+    Value(Cow<'a, [u8]>, Option<Duration>),
+    /// A delete tombstone.
+    Delete,
+    /// The operands of `merge`, from the oldest to the latest.
+    Merge(Vec<&'a [u8]>),
 }
 
 impl LsmStorageState {
-    fn create(options: &LsmStorageOptions) -> Self {
-        let levels = match &options.compaction_options {
+    pub(crate) fn create(compaction_options: &CompactionOptions) -> Self {
+        let levels = match compaction_options {
             CompactionOptions::Leveled(LeveledCompactionOptions { max_levels, .. })
             | CompactionOptions::Simple(SimpleLeveledCompactionOptions { max_levels, .. }) => (1
                 ..=*max_levels)
@@ -68,6 +98,31 @@ impl LsmStorageState {
             sstables: Default::default(),
         }
     }
+
+    /// Collect the range tombstones in memtables and SSTs that are visible at `read_ts` and
+    /// overlap the key range of a get or a scan. Only these tombstones are cloned.
+    pub(crate) fn range_tombstones(
+        &self,
+        read_ts: u64,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Vec<RangeTombstone> {
+        let mut range_tombstones = Vec::new();
+        for memtable in std::iter::once(&self.memtable).chain(self.imm_memtables.iter()) {
+            range_tombstones.extend(memtable.overlapping_range_tombstones(read_ts, lower, upper));
+        }
+        for sst in self.sstables.values() {
+            range_tombstones.extend(
+                sst.range_tombstones()
+                    .iter()
+                    .filter(|tombstone| {
+                        tombstone.ts <= read_ts && tombstone.overlaps_range(lower, upper)
+                    })
+                    .cloned(),
+            );
+        }
+        range_tombstones
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,6 +136,34 @@ pub struct LsmStorageOptions {
     pub compaction_options: CompactionOptions,
     pub enable_wal: bool,
     pub serializable: bool,
+    // Block compression of the SSTs on each level
+    pub compression: CompressionOptions,
+    // Filter of the SSTs on each level
+    pub filter: FilterOptions,
+    // Values of at least this size are stored in the value log, `None` keeps all values in the LSM tree
+    pub value_log_threshold: Option<usize>,
+    // Rotate the manifest into a snapshot once it grows beyond this size in bytes
+    pub max_manifest_size: usize,
+    // Maximum number of key ranges that a compaction task is split into and compacted in parallel
+    pub max_subcompactions: usize,
+    // Slow down or stop writes when flush or compaction falls behind
+    pub write_stall: WriteStallOptions,
+    // Limit the write throughput of flushes and compactions, `None` for no limit
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    // Capacity of the block cache in bytes, unused if `block_cache` is set
+    pub block_cache_capacity_bytes: u64,
+    // A block cache shared with other storages, `None` to create one of `block_cache_capacity_bytes`
+    pub block_cache: Option<Arc<BlockCache>>,
+    // Split the index of each SST into partitions of about this size in bytes, which are loaded
+    // lazily through the block cache, `None` keeps the whole index in memory
+    pub index_partition_size: Option<usize>,
+    // Add the prefixes of the keys to the bloom filters, so that `prefix_scan` can skip SSTs
+    pub prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    // Keep the history that can be read with `get_at` and `scan_at` even if no txn reads it,
+    // `None` only keeps the versions that the running txns and the named snapshots read
+    pub history_retention: Option<HistoryRetention>,
+    // Folds the operands written by `merge`, which cannot be used without it
+    pub merge_operator: Option<Arc<dyn MergeOperator>>,
 }
 
 impl LsmStorageOptions {
@@ -92,6 +175,19 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 50,
             serializable: false,
+            compression: CompressionOptions::default(),
+            filter: FilterOptions::default(),
+            value_log_threshold: None,
+            max_manifest_size: 1 << 20,
+            max_subcompactions: 1,
+            write_stall: WriteStallOptions::default(),
+            rate_limiter: None,
+            block_cache_capacity_bytes: 4 << 30,
+            block_cache: None,
+            index_partition_size: None,
+            prefix_extractor: None,
+            history_retention: None,
+            merge_operator: None,
         }
     }
 
@@ -103,6 +199,19 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            compression: CompressionOptions::default(),
+            filter: FilterOptions::default(),
+            value_log_threshold: None,
+            max_manifest_size: 1 << 20,
+            max_subcompactions: 1,
+            write_stall: WriteStallOptions::default(),
+            rate_limiter: None,
+            block_cache_capacity_bytes: 4 << 30,
+            block_cache: None,
+            index_partition_size: None,
+            prefix_extractor: None,
+            history_retention: None,
+            merge_operator: None,
         }
     }
 
@@ -114,6 +223,31 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            compression: CompressionOptions::default(),
+            filter: FilterOptions::default(),
+            value_log_threshold: None,
+            max_manifest_size: 1 << 20,
+            max_subcompactions: 1,
+            write_stall: WriteStallOptions::default(),
+            rate_limiter: None,
+            block_cache_capacity_bytes: 4 << 30,
+            block_cache: None,
+            index_partition_size: None,
+            prefix_extractor: None,
+            history_retention: None,
+            merge_operator: None,
+        }
+    }
+
+    /// The options of the default column family.
+    pub fn column_family_options(&self) -> ColumnFamilyOptions {
+        ColumnFamilyOptions {
+            block_size: self.block_size,
+            target_sst_size: self.target_sst_size,
+            num_memtable_limit: self.num_memtable_limit,
+            compaction_options: self.compaction_options.clone(),
+            compression: self.compression.clone(),
+            filter: self.filter.clone(),
         }
     }
 }
@@ -149,23 +283,23 @@ fn key_within(user_key: &[u8], table_begin: KeySlice, table_end: KeySlice) -> bo
     table_begin.key_ref() <= user_key && user_key <= table_end.key_ref()
 }
 
-#[derive(Clone, Debug)]
-pub enum CompactionFilter {
-    Prefix(Bytes),
-}
-
 /// The storage interface of the LSM tree.
 pub(crate) struct LsmStorageInner {
+    /// The state of the default column family.
     pub(crate) state: Arc<RwLock<Arc<LsmStorageState>>>,
+    /// All column families by id, including the default one.
+    pub(crate) column_families: RwLock<BTreeMap<usize, Arc<ColumnFamily>>>,
     pub(crate) state_lock: Mutex<()>,
     path: PathBuf,
     pub(crate) block_cache: Arc<BlockCache>,
     next_sst_id: AtomicUsize,
     pub(crate) options: Arc<LsmStorageOptions>,
-    pub(crate) compaction_controller: CompactionController,
     pub(crate) manifest: Option<Manifest>,
     pub(crate) mvcc: Option<LsmMvccInner>,
-    pub(crate) compaction_filters: Arc<Mutex<Vec<CompactionFilter>>>,
+    pub(crate) compaction_filters: Arc<Mutex<Vec<Arc<dyn CompactionFilter>>>>,
+    pub(crate) value_log: Arc<ValueLog>,
+    pub(crate) write_stall: WriteStall,
+    pub(crate) filter_stats: FilterStatsRecorder,
 }
 
 /// A thin wrapper for `LsmStorageInner` and the user interface for MiniLSM.
@@ -214,7 +348,7 @@ impl MiniLsm {
         }
 
         // create memtable and skip updating manifest
-        if !self.inner.state.read().memtable.is_empty() {
+        if !self.inner.memtables_empty() {
             self.inner
                 .freeze_memtable_with_memtable(Arc::new(MemTable::create(
                     self.inner.next_sst_id(),
@@ -249,26 +383,93 @@ impl MiniLsm {
         }))
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
+    /// Add a filter that runs on the keys compacted from now on, after the filters added before.
+    pub fn add_compaction_filter(&self, compaction_filter: Arc<dyn CompactionFilter>) {
         self.inner.add_compaction_filter(compaction_filter)
     }
 
+    /// Create a column family. Fails if a column family with the same name exists.
+    pub fn create_column_family(
+        &self,
+        name: &str,
+        options: ColumnFamilyOptions,
+    ) -> Result<Arc<ColumnFamily>> {
+        self.inner.create_column_family(name, options)
+    }
+
+    /// Get a column family by name.
+    pub fn column_family(&self, name: &str) -> Option<Arc<ColumnFamily>> {
+        self.inner.column_family(name)
+    }
+
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
         self.inner.get(key)
     }
 
+    pub fn get_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<Option<Bytes>> {
+        self.inner.get_cf(cf, key)
+    }
+
     pub fn write_batch<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
         self.inner.write_batch(batch)
     }
 
+    pub fn write_batch_cf<T: AsRef<[u8]>>(
+        &self,
+        cf: &ColumnFamily,
+        batch: &[WriteBatchRecord<T>],
+    ) -> Result<()> {
+        self.inner.write_batch_cf(cf, batch)
+    }
+
     pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
         self.inner.put(key, value)
     }
 
+    pub fn put_cf(&self, cf: &ColumnFamily, key: &[u8], value: &[u8]) -> Result<()> {
+        self.inner.put_cf(cf, key, value)
+    }
+
+    /// Put a key-value pair that expires after `ttl`.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.inner.put_with_ttl(key, value, ttl)
+    }
+
+    pub fn put_with_ttl_cf(
+        &self,
+        cf: &ColumnFamily,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        self.inner.put_with_ttl_cf(cf, key, value, ttl)
+    }
+
+    pub fn merge(&self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.inner.merge(key, operand)
+    }
+
+    pub fn merge_cf(&self, cf: &ColumnFamily, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.inner.merge_cf(cf, key, operand)
+    }
+
     pub fn delete(&self, key: &[u8]) -> Result<()> {
         self.inner.delete(key)
     }
 
+    pub fn delete_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<()> {
+        self.inner.delete_cf(cf, key)
+    }
+
+    /// Delete all keys in `[lower, upper)` with a single range tombstone.
+    pub fn delete_range(&self, lower: &[u8], upper: &[u8]) -> Result<()> {
+        self.inner.delete_range(lower, upper)
+    }
+
+    pub fn delete_range_cf(&self, cf: &ColumnFamily, lower: &[u8], upper: &[u8]) -> Result<()> {
+        self.inner.delete_range_cf(cf, lower, upper)
+    }
+
     pub fn sync(&self) -> Result<()> {
         self.inner.sync()
     }
@@ -277,13 +478,89 @@ impl MiniLsm {
         self.inner.new_txn()
     }
 
+    /// Get a key as of a past commit ts, which must not be older than `oldest_readable_ts`.
+    pub fn get_at(&self, key: &[u8], ts: u64) -> Result<Option<Bytes>> {
+        self.get_cf_at(&self.inner.default_column_family(), key, ts)
+    }
+
+    pub fn get_cf_at(&self, cf: &ColumnFamily, key: &[u8], ts: u64) -> Result<Option<Bytes>> {
+        self.inner.get_cf_at(cf, key, ts)
+    }
+
+    /// Create an iterator over a range of keys as of a past commit ts, which must not be older
+    /// than `oldest_readable_ts`.
+    pub fn scan_at(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        ts: u64,
+    ) -> Result<TxnIterator> {
+        self.scan_cf_at(&self.inner.default_column_family(), lower, upper, ts)
+    }
+
+    pub fn scan_cf_at(
+        &self,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        ts: u64,
+    ) -> Result<TxnIterator> {
+        self.inner.scan_cf_at(cf, lower, upper, ts)
+    }
+
+    /// The oldest commit ts that `get_at` and `scan_at` can read, below which compaction may have
+    /// dropped the versions. It only goes back further than the oldest running txn and named
+    /// snapshot with `history_retention`.
+    pub fn oldest_readable_ts(&self) -> u64 {
+        self.inner.mvcc().watermark()
+    }
+
+    pub fn latest_commit_ts(&self) -> u64 {
+        self.inner.mvcc().latest_commit_ts()
+    }
+
+    /// The number of committed txns kept for the serializable check. They are removed once no
+    /// running txn started before they committed.
+    pub fn num_committed_txns(&self) -> usize {
+        self.inner.mvcc().num_committed_txns()
+    }
+
+    /// Create a named snapshot at the latest commit ts, which is kept across restarts until it is
+    /// deleted. Compaction keeps the versions it sees. Returns its read ts.
+    pub fn create_snapshot(&self, name: &str) -> Result<u64> {
+        self.inner.create_snapshot(name)
+    }
+
+    /// Open a named snapshot as a read-only txn.
+    pub fn open_snapshot(&self, name: &str) -> Result<Arc<Transaction>> {
+        self.inner.open_snapshot(name)
+    }
+
+    pub fn delete_snapshot(&self, name: &str) -> Result<()> {
+        self.inner.delete_snapshot(name)
+    }
+
+    /// The named snapshots and their read ts.
+    pub fn snapshots(&self) -> BTreeMap<String, u64> {
+        self.inner.mvcc().named_snapshots.lock().clone()
+    }
+
     pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
         self.inner.scan(lower, upper)
     }
 
+    pub fn scan_cf(
+        &self,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        self.inner.scan_cf(cf, lower, upper)
+    }
+
     /// Only call this in test cases due to race conditions
     pub fn force_flush(&self) -> Result<()> {
-        if !self.inner.state.read().memtable.is_empty() {
+        if !self.inner.memtables_empty() {
             self.inner
                 .force_freeze_memtable(&self.inner.state_lock.lock())?;
         }
@@ -293,9 +570,66 @@ impl MiniLsm {
         Ok(())
     }
 
+    /// Create an iterator over the keys that start with `prefix`. If a prefix extractor is set,
+    /// the SSTs whose bloom filters do not have the prefix of `prefix` are skipped.
+    pub fn prefix_scan(&self, prefix: &[u8]) -> Result<TxnIterator> {
+        self.inner.prefix_scan(prefix)
+    }
+
+    pub fn prefix_scan_cf(&self, cf: &ColumnFamily, prefix: &[u8]) -> Result<TxnIterator> {
+        self.inner.prefix_scan_cf(cf, prefix)
+    }
+
     pub fn force_full_compaction(&self) -> Result<()> {
         self.inner.force_full_compaction()
     }
+
+    /// The write stall condition and how often writes were delayed or stopped so far.
+    pub fn write_stall_stats(&self) -> WriteStallStats {
+        self.inner
+            .write_stall
+            .stats(self.inner.write_stall_condition())
+    }
+
+    /// How often the SST filters ruled out an SST, or let through one without the key, in point
+    /// lookups.
+    pub fn filter_stats(&self) -> FilterStats {
+        self.inner.filter_stats.stats()
+    }
+
+    /// The hits, misses and evictions of the block cache. A shared cache reports the statistics of
+    /// all the storages that use it.
+    pub fn block_cache_stats(&self) -> BlockCacheStats {
+        self.inner.block_cache.stats()
+    }
+
+    /// Create a checkpoint in an empty directory, which can be opened as a database of its own.
+    /// The SSTs are hard-linked into the directory if possible.
+    pub fn create_checkpoint(&self, dir: impl AsRef<Path>) -> Result<CheckpointStats> {
+        self.inner.create_checkpoint(dir)
+    }
+
+    /// Back up the database to a directory by copying its files. An incremental backup only copies
+    /// the files that the earlier backup in the directory does not have.
+    pub fn create_backup(
+        &self,
+        dir: impl AsRef<Path>,
+        mode: BackupMode,
+    ) -> Result<CheckpointStats> {
+        self.inner.create_backup(dir, mode)
+    }
+
+    /// Restore a backup to a new database directory, which can then be opened.
+    pub fn restore_backup(backup_dir: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<()> {
+        checkpoint::restore_backup(backup_dir, path)
+    }
+
+    /// Garbage collect the oldest value log file. Returns false if there is no file to collect, or
+    /// if some of its values are still visible to running transactions, in which case it can be
+    /// collected by a later call once they finish.
+    pub fn gc_value_log(&self) -> Result<bool> {
+        self.inner.gc_value_log()
+    }
 }
 
 impl LsmStorageInner {
@@ -312,34 +646,128 @@ impl LsmStorageInner {
         self.manifest.as_ref().unwrap()
     }
 
+    pub(crate) fn default_column_family(&self) -> Arc<ColumnFamily> {
+        self.column_families.read()[&DEFAULT_COLUMN_FAMILY_ID].clone()
+    }
+
+    pub(crate) fn column_family(&self, name: &str) -> Option<Arc<ColumnFamily>> {
+        self.column_families
+            .read()
+            .values()
+            .find(|cf| cf.name() == name)
+            .cloned()
+    }
+
+    pub(crate) fn column_family_by_id(&self, id: usize) -> Arc<ColumnFamily> {
+        self.column_families.read()[&id].clone()
+    }
+
+    /// All column families, ordered by id, so the default one goes first.
+    pub(crate) fn column_families(&self) -> Vec<Arc<ColumnFamily>> {
+        self.column_families.read().values().cloned().collect()
+    }
+
+    /// Create a builder of an SST on `level` with the compression and filter of the level. It
+    /// writes at the pace of the rate limiter, with a partitioned index and prefix bloom filters if
+    /// configured.
+    pub(crate) fn sst_builder(
+        &self,
+        options: &ColumnFamilyOptions,
+        level: usize,
+        priority: IoPriority,
+    ) -> SsTableBuilder {
+        let mut builder = SsTableBuilder::new_with_compression(
+            options.block_size,
+            options.compression.for_level(level),
+        );
+        builder.set_filter_type(options.filter.for_level(level));
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            builder.set_rate_limiter(rate_limiter.clone(), priority);
+        }
+        if let Some(partition_size) = self.options.index_partition_size {
+            builder.set_index_partition_size(partition_size);
+        }
+        if let Some(prefix_extractor) = &self.options.prefix_extractor {
+            builder.set_prefix_extractor(prefix_extractor.clone());
+        }
+        builder
+    }
+
+    /// The most severe write stall condition of all column families. The memtables of all column
+    /// families are frozen and flushed together, so a write to any of them is stalled.
+    pub(crate) fn write_stall_condition(&self) -> WriteStallCondition {
+        self.column_families()
+            .iter()
+            .map(|cf| cf.write_stall_condition(&self.options.write_stall))
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Freeze the memtables and flush all the immutable memtables, so that everything committed
+    /// so far is persisted without the WAL.
+    pub(crate) fn flush_all_memtables(&self) -> Result<()> {
+        {
+            let state_lock = self.state_lock.lock();
+            if !self.memtables_empty() {
+                self.force_freeze_memtable(&state_lock)?;
+            }
+        }
+        while !self.state.read().imm_memtables.is_empty() {
+            self.force_flush_next_imm_memtable()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the current memtables of all column families are empty.
+    pub(crate) fn memtables_empty(&self) -> bool {
+        self.column_families()
+            .iter()
+            .all(|cf| cf.state.read().memtable.is_empty())
+    }
+
     /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
     /// not exist.
     pub(crate) fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
-        let mut state = LsmStorageState::create(&options);
         let path = path.as_ref();
+        if options
+            .write_stall
+            .imm_memtables_stop_trigger
+            .is_some_and(|trigger| trigger < options.num_memtable_limit)
+        {
+            bail!("imm_memtables_stop_trigger must not be below num_memtable_limit, or writes stop before the memtables are flushed");
+        }
         let mut next_sst_id = 1;
-        let block_cache = Arc::new(BlockCache::new(1 << 20)); // 4GB block cache,
+        let block_cache = Arc::new(match &options.block_cache {
+            Some(block_cache) => block_cache.share(),
+            None => BlockCache::new(options.block_cache_capacity_bytes),
+        });
         let manifest;
 
-        let compaction_controller = match &options.compaction_options {
-            CompactionOptions::Leveled(options) => {
-                CompactionController::Leveled(LeveledCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Tiered(options) => {
-                CompactionController::Tiered(TieredCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Simple(options) => CompactionController::Simple(
-                SimpleLeveledCompactionController::new(options.clone()),
-            ),
-            CompactionOptions::NoCompaction => CompactionController::NoCompaction,
+        let new_column_family = |id: usize, name: String, options: ColumnFamilyOptions| {
+            let state = LsmStorageState::create(&options.compaction_options);
+            ColumnFamily::new(id, name, Arc::new(RwLock::new(Arc::new(state))), options)
         };
+        let mut column_families = BTreeMap::new();
+        column_families.insert(
+            DEFAULT_COLUMN_FAMILY_ID,
+            new_column_family(
+                DEFAULT_COLUMN_FAMILY_ID,
+                DEFAULT_COLUMN_FAMILY_NAME.to_string(),
+                options.column_family_options(),
+            ),
+        );
 
         if !path.exists() {
             std::fs::create_dir_all(path).context("failed to create DB dir")?;
         }
-        let manifest_path = path.join("MANIFEST");
+        let manifest_path = Manifest::current_path(path)?;
         let mut last_commit_ts = 0;
+        let value_log = ValueLog::new();
+        let mut named_snapshots = BTreeMap::new();
         if !manifest_path.exists() {
+            let default = &column_families[&DEFAULT_COLUMN_FAMILY_ID];
+            let mut state = default.state.write();
+            let state = Arc::make_mut(&mut state);
             if options.enable_wal {
                 state.memtable = Arc::new(MemTable::create_with_wal(
                     state.memtable.id(),
@@ -351,16 +779,20 @@ impl LsmStorageInner {
         } else {
             let (m, records) = Manifest::recover(&manifest_path)?;
             let mut memtables = BTreeSet::new();
+            let mut value_logs = BTreeSet::new();
+            let mut collected_value_logs = Vec::new();
             for record in records {
                 match record {
                     ManifestRecord::Flush(sst_id) => {
                         let res = memtables.remove(&sst_id);
                         assert!(res, "memtable not exist?");
-                        if compaction_controller.flush_to_l0() {
-                            state.l0_sstables.insert(0, sst_id);
-                        } else {
-                            state.levels.insert(0, (sst_id, vec![sst_id]));
-                        }
+                        let cf = &column_families[&DEFAULT_COLUMN_FAMILY_ID];
+                        cf.add_flushed_sst(Arc::make_mut(&mut cf.state.write()), sst_id);
+                        next_sst_id = next_sst_id.max(sst_id);
+                    }
+                    ManifestRecord::ColumnFamilyFlush(cf_id, sst_id) => {
+                        let cf = &column_families[&cf_id];
+                        cf.add_flushed_sst(Arc::make_mut(&mut cf.state.write()), sst_id);
                         next_sst_id = next_sst_id.max(sst_id);
                     }
                     ManifestRecord::NewMemtable(x) => {
@@ -368,33 +800,101 @@ impl LsmStorageInner {
                         memtables.insert(x);
                     }
                     ManifestRecord::Compaction(task, output) => {
-                        let (new_state, _) =
-                            compaction_controller.apply_compaction_result(&state, &task, &output);
+                        let cf = &column_families[&DEFAULT_COLUMN_FAMILY_ID];
+                        let mut state = cf.state.write();
+                        let (new_state, _) = cf
+                            .compaction_controller
+                            .apply_compaction_result(&state, &task, &output);
                         // TODO: apply remove again
-                        state = new_state;
+                        *state = Arc::new(new_state);
+                        next_sst_id =
+                            next_sst_id.max(output.iter().max().copied().unwrap_or_default());
+                    }
+                    ManifestRecord::ColumnFamilyCompaction(cf_id, task, output) => {
+                        let cf = &column_families[&cf_id];
+                        let mut state = cf.state.write();
+                        let (new_state, _) = cf
+                            .compaction_controller
+                            .apply_compaction_result(&state, &task, &output);
+                        *state = Arc::new(new_state);
                         next_sst_id =
                             next_sst_id.max(output.iter().max().copied().unwrap_or_default());
                     }
+                    ManifestRecord::NewValueLog(x) => {
+                        next_sst_id = next_sst_id.max(x);
+                        value_logs.insert(x);
+                    }
+                    ManifestRecord::ValueLogGc(x) => {
+                        value_logs.remove(&x);
+                        collected_value_logs.push(x);
+                    }
+                    ManifestRecord::NewColumnFamily(id, name, options) => {
+                        column_families.insert(id, new_column_family(id, name, options));
+                    }
+                    ManifestRecord::NewNamedSnapshot(name, read_ts) => {
+                        named_snapshots.insert(name, read_ts);
+                    }
+                    ManifestRecord::DeleteNamedSnapshot(name) => {
+                        named_snapshots.remove(&name);
+                    }
+                    ManifestRecord::Snapshot(snapshot) => {
+                        next_sst_id = next_sst_id.max(snapshot.next_sst_id);
+                        memtables = snapshot.memtables.into_iter().collect();
+                        value_logs = snapshot.value_logs.into_iter().collect();
+                        named_snapshots = snapshot.named_snapshots;
+                        column_families.clear();
+                        for cf in snapshot.column_families {
+                            let cf_options = if cf.id == DEFAULT_COLUMN_FAMILY_ID {
+                                options.column_family_options()
+                            } else {
+                                cf.options
+                            };
+                            let column_family = new_column_family(cf.id, cf.name, cf_options);
+                            {
+                                let mut state = column_family.state.write();
+                                let state = Arc::make_mut(&mut state);
+                                state.l0_sstables = cf.l0_sstables;
+                                state.levels = cf.levels;
+                            }
+                            column_families.insert(cf.id, column_family);
+                        }
+                    }
+                }
+            }
+
+            // recover value logs, and remove the collected ones that were not removed before a crash
+            for id in value_logs {
+                value_log.open_file(id, Self::path_of_vlog_static(path, id))?;
+            }
+            for id in collected_value_logs {
+                let vlog_path = Self::path_of_vlog_static(path, id);
+                if vlog_path.exists() {
+                    std::fs::remove_file(vlog_path)?;
                 }
             }
 
             let mut sst_cnt = 0;
             // recover SSTs
-            for table_id in state
-                .l0_sstables
-                .iter()
-                .chain(state.levels.iter().flat_map(|(_, files)| files))
-            {
-                let table_id = *table_id;
-                let sst = SsTable::open(
-                    table_id,
-                    Some(block_cache.clone()),
-                    FileObject::open(&Self::path_of_sst_static(path, table_id))
-                        .context("failed to open SST")?,
-                )?;
-                last_commit_ts = last_commit_ts.max(sst.max_ts());
-                state.sstables.insert(table_id, Arc::new(sst));
-                sst_cnt += 1;
+            for cf in column_families.values() {
+                let mut state = cf.state.write();
+                let state = Arc::make_mut(&mut state);
+                let table_ids = state
+                    .l0_sstables
+                    .iter()
+                    .chain(state.levels.iter().flat_map(|(_, files)| files))
+                    .copied()
+                    .collect::<Vec<_>>();
+                for table_id in table_ids {
+                    let sst = SsTable::open(
+                        table_id,
+                        Some(block_cache.clone()),
+                        FileObject::open(&Self::path_of_sst_static(path, table_id))
+                            .context("failed to open SST")?,
+                    )?;
+                    last_commit_ts = last_commit_ts.max(sst.max_ts());
+                    state.sstables.insert(table_id, Arc::new(sst));
+                    sst_cnt += 1;
+                }
             }
             println!("{} SSTs opened", sst_cnt);
 
@@ -404,56 +904,136 @@ impl LsmStorageInner {
             if options.enable_wal {
                 let mut wal_cnt = 0;
                 for id in memtables.iter() {
-                    let memtable =
-                        MemTable::recover_from_wal(*id, Self::path_of_wal_static(path, *id))?;
-                    let max_ts = memtable
-                        .map
-                        .iter()
-                        .map(|x| x.key().ts())
+                    let wal_path = Self::path_of_wal_static(path, *id);
+                    // The WAL is removed without a flush record if all memtables of it are empty.
+                    if !wal_path.exists() {
+                        continue;
+                    }
+                    let (memtable, mut cf_memtables) =
+                        MemTable::recover_column_families_from_wal(*id, wal_path)?;
+                    let max_ts = std::iter::once(&memtable)
+                        .chain(cf_memtables.values())
+                        .flat_map(|memtable| memtable.map.iter().map(|x| x.key().ts()))
                         .max()
                         .unwrap_or_default();
                     last_commit_ts = last_commit_ts.max(max_ts);
-                    if !memtable.is_empty() {
-                        state.imm_memtables.insert(0, Arc::new(memtable));
-                        wal_cnt += 1;
+                    if memtable.is_empty() && cf_memtables.is_empty() {
+                        continue;
                     }
+                    // Keep the immutable memtables of all column families aligned, so that they
+                    // are flushed together.
+                    let mut memtable = Some(memtable);
+                    for cf in column_families.values() {
+                        let cf_memtable = if cf.is_default() {
+                            memtable.take().unwrap()
+                        } else {
+                            cf_memtables
+                                .remove(&cf.id())
+                                .unwrap_or_else(|| MemTable::create(*id))
+                        };
+                        let mut state = cf.state.write();
+                        Arc::make_mut(&mut state)
+                            .imm_memtables
+                            .insert(0, Arc::new(cf_memtable));
+                    }
+                    if let Some(cf_id) = cf_memtables.keys().next() {
+                        bail!("WAL {} has entries of unknown column family {}", id, cf_id);
+                    }
+                    wal_cnt += 1;
                 }
                 println!("{} WALs recovered", wal_cnt);
-                state.memtable = Arc::new(MemTable::create_with_wal(
-                    next_sst_id,
-                    Self::path_of_wal_static(path, next_sst_id),
-                )?);
-            } else {
-                state.memtable = Arc::new(MemTable::create(next_sst_id));
             }
-            m.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
+            for cf in column_families.values() {
+                let mut state = cf.state.write();
+                let state = Arc::make_mut(&mut state);
+                state.memtable = if options.enable_wal && cf.is_default() {
+                    Arc::new(MemTable::create_with_wal(
+                        next_sst_id,
+                        Self::path_of_wal_static(path, next_sst_id),
+                    )?)
+                } else {
+                    Arc::new(MemTable::create(next_sst_id))
+                };
+            }
+            m.add_record_when_init(ManifestRecord::NewMemtable(next_sst_id))?;
             next_sst_id += 1;
             manifest = m;
         };
+        Manifest::remove_stale_files(path, &manifest_path)?;
+        // The writes seen by a snapshot may be lost without WAL, and the new ones must not be seen
+        // by it.
+        last_commit_ts = named_snapshots
+            .values()
+            .copied()
+            .fold(last_commit_ts, u64::max);
+        let mvcc = LsmMvccInner::new(last_commit_ts, options.history_retention);
+        for (name, read_ts) in named_snapshots {
+            mvcc.add_named_snapshot(name, read_ts);
+        }
 
+        let state = column_families[&DEFAULT_COLUMN_FAMILY_ID].state.clone();
         let storage = Self {
-            state: Arc::new(RwLock::new(Arc::new(state))),
+            state,
+            column_families: RwLock::new(
+                column_families
+                    .into_iter()
+                    .map(|(id, cf)| (id, Arc::new(cf)))
+                    .collect(),
+            ),
             state_lock: Mutex::new(()),
             path: path.to_path_buf(),
             block_cache,
             next_sst_id: AtomicUsize::new(next_sst_id),
-            compaction_controller,
             manifest: Some(manifest),
             options: options.into(),
-            mvcc: Some(LsmMvccInner::new(last_commit_ts)),
+            mvcc: Some(mvcc),
             compaction_filters: Arc::new(Mutex::new(Vec::new())),
+            value_log: Arc::new(value_log),
+            write_stall: WriteStall::default(),
+            filter_stats: FilterStatsRecorder::default(),
         };
         storage.sync_dir()?;
 
         Ok(storage)
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
+    /// Create a column family. Its memtable joins the current generation of memtables, i.e., it is
+    /// frozen with the memtables of the other column families.
+    pub(crate) fn create_column_family(
+        &self,
+        name: &str,
+        options: ColumnFamilyOptions,
+    ) -> Result<Arc<ColumnFamily>> {
+        let state_lock = self.state_lock.lock();
+        let mut column_families = self.column_families.write();
+        if column_families.values().any(|cf| cf.name() == name) {
+            bail!("column family {} already exists", name);
+        }
+        let id = column_families.keys().next_back().unwrap() + 1;
+        let mut state = LsmStorageState::create(&options.compaction_options);
+        state.memtable = Arc::new(MemTable::create(self.state.read().memtable.id()));
+        self.manifest().add_record(
+            &state_lock,
+            ManifestRecord::NewColumnFamily(id, name.to_string(), options.clone()),
+        )?;
+        let cf = Arc::new(ColumnFamily::new(
+            id,
+            name.to_string(),
+            Arc::new(RwLock::new(Arc::new(state))),
+            options,
+        ));
+        column_families.insert(id, cf.clone());
+        Ok(cf)
+    }
+
+    pub fn add_compaction_filter(&self, compaction_filter: Arc<dyn CompactionFilter>) {
         let mut compaction_filters = self.compaction_filters.lock();
         compaction_filters.push(compaction_filter);
     }
 
     pub fn sync(&self) -> Result<()> {
+        // The WAL refers to the values in the value log, so sync the value log first.
+        self.value_log.sync()?;
         self.state.read().memtable.sync_wal()
     }
 
@@ -463,9 +1043,34 @@ impl LsmStorageInner {
         txn.get(key)
     }
 
-    pub(crate) fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
+    pub fn get_cf(self: &Arc<Self>, cf: &ColumnFamily, key: &[u8]) -> Result<Option<Bytes>> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.get_cf(cf, key)
+    }
+
+    pub(crate) fn get_with_ts(
+        &self,
+        cf: &ColumnFamily,
+        key: &[u8],
+        read_ts: u64,
+    ) -> Result<Option<Bytes>> {
+        Ok(self
+            .get_stored_with_ts(cf, key, read_ts, Some(self.value_log.reader()))?
+            .map(|(_, value)| value))
+    }
+
+    /// Get a key from the storage with the type of its value. If `value_log` is not provided, the
+    /// value is returned as it is stored in the LSM tree, i.e., values in the value log are returned
+    /// as pointers. Otherwise, its type is always `ValueType::Put`.
+    fn get_stored_with_ts(
+        &self,
+        cf: &ColumnFamily,
+        key: &[u8],
+        read_ts: u64,
+        value_log: Option<ValueLogReader>,
+    ) -> Result<Option<(ValueType, Bytes)>> {
         let snapshot = {
-            let guard = self.state.read();
+            let guard = cf.state.read();
             Arc::clone(&guard)
         }; // drop global lock here
 
@@ -491,7 +1096,9 @@ impl LsmStorageInner {
                 table.last_key().as_key_slice(),
             ) {
                 if let Some(bloom) = &table.bloom {
-                    if bloom.may_contain(farmhash::fingerprint32(key)) {
+                    let may_contain = bloom.may_contain(farmhash::fingerprint32(key));
+                    self.filter_stats.record_check(may_contain);
+                    if may_contain {
                         return true;
                     }
                 } else {
@@ -504,10 +1111,15 @@ impl LsmStorageInner {
         for table in snapshot.l0_sstables.iter() {
             let table = snapshot.sstables[table].clone();
             if keep_table(key, &table) {
-                l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
+                let has_filter = table.bloom.is_some();
+                let iter = SsTableIterator::create_and_seek_to_key(
                     table,
                     KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
-                )?));
+                )?;
+                if has_filter && !(iter.is_valid() && iter.key().key_ref() == key) {
+                    self.filter_stats.record_false_positive();
+                }
+                l0_iters.push(Box::new(iter));
             }
         }
         let l0_iter = MergeIterator::create(l0_iters);
@@ -520,78 +1132,307 @@ impl LsmStorageInner {
                     level_ssts.push(table);
                 }
             }
+            // the SSTs of a level do not overlap, so at most one of them passed the filter
+            let has_filter = level_ssts.iter().any(|table| table.bloom.is_some());
             let level_iter = SstConcatIterator::create_and_seek_to_key(
                 level_ssts,
                 KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
             )?;
+            if has_filter && !(level_iter.is_valid() && level_iter.key().key_ref() == key) {
+                self.filter_stats.record_false_positive();
+            }
             level_iters.push(Box::new(level_iter));
         }
 
+        let range_tombstones =
+            snapshot.range_tombstones(read_ts, Bound::Included(key), Bound::Included(key));
+
         let iter = LsmIterator::new(
             TwoMergeIterator::create(
                 TwoMergeIterator::create(memtable_iter, l0_iter)?,
                 MergeIterator::create(level_iters),
             )?,
             Bound::Unbounded,
+            Bound::Unbounded,
             read_ts,
+            range_tombstones,
+            value_log,
+            self.options.merge_operator.clone(),
         )?;
 
-        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        if iter.is_valid() && iter.key() == key {
+            return Ok(Some((
+                iter.value_type(),
+                Bytes::copy_from_slice(iter.value()),
+            )));
         }
         Ok(None)
     }
 
-    pub fn write_batch_inner<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<u64> {
+    /// Encode a value to be stored in the LSM tree with its type, and write it to the value log if
+    /// it is large. A value with a TTL is stored with the time it expires at, counted from `now`.
+    fn encode_value<'a>(
+        &self,
+        key: KeySlice,
+        value: &BatchValue<'a>,
+        now: u64,
+    ) -> Result<(ValueType, Cow<'a, [u8]>)> {
+        Ok(match value {
+            BatchValue::Value(value, ttl) => {
+                let (value_type, value) = match self.options.value_log_threshold {
+                    Some(threshold) if !value.is_empty() && value.len() >= threshold => (
+                        ValueType::ValuePointer,
+                        Cow::Owned(self.append_value_log(key, value)?.encode()),
+                    ),
+                    _ => (ValueType::Put, value.clone()),
+                };
+                match ttl {
+                    Some(ttl) => (
+                        value_type.with_ttl(),
+                        Cow::Owned(vlog::encode_expiring(now + ttl.as_millis() as u64, &value)),
+                    ),
+                    None => (value_type, value),
+                }
+            }
+            BatchValue::Delete => (ValueType::Delete, Cow::Borrowed(&b""[..])),
+            BatchValue::Merge(operands) => (
+                ValueType::Merge,
+                Cow::Owned(vlog::encode_merge_operands(operands)),
+            ),
+        })
+    }
+
+    /// Append a value to the value log, and start a new value log file when the active one is
+    /// full. Must be called with the write lock held.
+    fn append_value_log(&self, key: KeySlice, value: &[u8]) -> Result<vlog::ValuePointer> {
+        let full = self
+            .value_log
+            .active_file()
+            .is_none_or(|(_, size)| size >= self.options.target_sst_size as u64);
+        if full {
+            let state_lock = self.state_lock.lock();
+            self.value_log.seal()?;
+            let id = self.next_sst_id();
+            self.value_log.create_file(id, self.path_of_vlog(id))?;
+            self.manifest()
+                .add_record(&state_lock, ManifestRecord::NewValueLog(id))?;
+            self.sync_dir()?;
+        }
+        self.value_log.append(key, value)
+    }
+
+    /// Write the batches of several column families atomically with the same commit ts. The
+    /// batches are keyed by column family id.
+    pub(crate) fn write_column_family_batches_inner<T: AsRef<[u8]>>(
+        &self,
+        batches: &[(usize, &[WriteBatchRecord<T>])],
+    ) -> Result<u64> {
+        self.write_stall
+            .wait(&self.options.write_stall, || self.write_stall_condition());
         let _lck = self.mvcc().write_lock.lock();
         let ts = self.mvcc().latest_commit_ts() + 1;
+        let now = vlog::now_millis();
+        let mut entries = Vec::with_capacity(batches.len());
+        for (cf_id, batch) in batches {
+            let (data, range_tombstones) = self.batch_entries(batch, ts)?;
+            let values = data
+                .iter()
+                .map(|(key, value)| self.encode_value(*key, value, now))
+                .collect::<Result<Vec<_>>>()?;
+            entries.push((*cf_id, data, values, range_tombstones));
+        }
+        let data = entries
+            .iter()
+            .map(|(_, data, values, _)| {
+                data.iter()
+                    .zip(values)
+                    .map(|((key, _), (value_type, value))| (*key, *value_type, value.as_ref()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut batches = entries
+            .iter()
+            .zip(&data)
+            .map(|((cf_id, _, _, range_tombstones), data)| {
+                (*cf_id, data.as_slice(), range_tombstones.as_slice())
+            })
+            .collect::<Vec<ColumnFamilyBatch>>();
+        batches.sort_by_key(|(cf_id, _, _)| *cf_id);
+        let sizes = self.write_memtables(&batches)?;
+        for (cf, size) in sizes {
+            self.try_freeze(&cf, size)?;
+        }
+        self.mvcc().update_commit_ts(ts);
+        Ok(ts)
+    }
+
+    /// Convert a write batch to the values of the keys, and the range tombstones to write at `ts`.
+    /// Each key has one value, so a merge is folded with an earlier record of its key in the batch.
+    #[allow(clippy::type_complexity)]
+    fn batch_entries<'a, T: AsRef<[u8]>>(
+        &self,
+        batch: &'a [WriteBatchRecord<T>],
+        ts: u64,
+    ) -> Result<(Vec<(KeySlice<'a>, BatchValue<'a>)>, Vec<RangeTombstone>)> {
+        let mut data: Vec<(KeySlice, BatchValue)> = Vec::with_capacity(batch.len());
+        // The position of each key in `data`.
+        let mut positions = HashMap::<&[u8], usize>::new();
+        let mut range_tombstones = Vec::new();
         for record in batch {
             match record {
+                WriteBatchRecord::DelRange(lower, upper) => {
+                    let (lower, upper) = (lower.as_ref(), upper.as_ref());
+                    assert!(!lower.is_empty(), "key cannot be empty");
+                    if lower >= upper {
+                        continue;
+                    }
+                    // Records are applied in order, so the tombstone overrides the earlier
+                    // records of this batch in its range.
+                    data.retain(|(key, _)| !(lower <= key.key_ref() && key.key_ref() < upper));
+                    positions = data
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, (key, _))| (key.key_ref(), idx))
+                        .collect();
+                    range_tombstones.push(RangeTombstone::new(
+                        Bytes::copy_from_slice(lower),
+                        Bytes::copy_from_slice(upper),
+                        ts,
+                    ));
+                    // The sentinel of the range tombstone.
+                    Self::set_batch_value(&mut data, &mut positions, ts, lower, BatchValue::Delete);
+                }
                 WriteBatchRecord::Del(key) => {
                     let key = key.as_ref();
                     assert!(!key.is_empty(), "key cannot be empty");
-                    let size;
-                    {
-                        let guard = self.state.read();
-                        guard.memtable.put(KeySlice::from_slice(key, ts), b"")?;
-                        size = guard.memtable.approximate_size();
-                    }
-                    self.try_freeze(size)?;
+                    Self::set_batch_value(&mut data, &mut positions, ts, key, BatchValue::Delete);
                 }
-                WriteBatchRecord::Put(key, value) => {
+                WriteBatchRecord::Put(key, value) | WriteBatchRecord::PutWithTtl(key, value, _) => {
                     let key = key.as_ref();
                     let value = value.as_ref();
                     assert!(!key.is_empty(), "key cannot be empty");
-                    assert!(!value.is_empty(), "value cannot be empty");
-                    let size;
-                    {
-                        let guard = self.state.read();
-                        guard.memtable.put(KeySlice::from_slice(key, ts), value)?;
-                        size = guard.memtable.approximate_size();
-                    }
-                    self.try_freeze(size)?;
+                    let ttl = match record {
+                        WriteBatchRecord::PutWithTtl(_, _, ttl) => Some(*ttl),
+                        _ => None,
+                    };
+                    Self::set_batch_value(
+                        &mut data,
+                        &mut positions,
+                        ts,
+                        key,
+                        BatchValue::Value(Cow::Borrowed(value), ttl),
+                    );
+                }
+                WriteBatchRecord::Merge(key, operand) => {
+                    let (key, operand) = (key.as_ref(), operand.as_ref());
+                    assert!(!key.is_empty(), "key cannot be empty");
+                    let merge_operator = self.merge_operator()?;
+                    let value = match positions.get(key).map(|&idx| &mut data[idx].1) {
+                        Some(BatchValue::Merge(operands)) => {
+                            operands.push(operand);
+                            continue;
+                        }
+                        Some(BatchValue::Value(value, ttl)) => {
+                            let value = merge_operator.full_merge(key, Some(value), &[operand]);
+                            BatchValue::Value(Cow::Owned(value.to_vec()), *ttl)
+                        }
+                        Some(BatchValue::Delete) => {
+                            let value = merge_operator.full_merge(key, None, &[operand]);
+                            BatchValue::Value(Cow::Owned(value.to_vec()), None)
+                        }
+                        None => BatchValue::Merge(vec![operand]),
+                    };
+                    Self::set_batch_value(&mut data, &mut positions, ts, key, value);
                 }
             }
         }
-        self.mvcc().update_commit_ts(ts);
-        Ok(ts)
+        Ok((data, range_tombstones))
+    }
+
+    fn set_batch_value<'a>(
+        data: &mut Vec<(KeySlice<'a>, BatchValue<'a>)>,
+        positions: &mut HashMap<&'a [u8], usize>,
+        ts: u64,
+        key: &'a [u8],
+        value: BatchValue<'a>,
+    ) {
+        match positions.get(key) {
+            Some(&idx) => data[idx].1 = value,
+            None => {
+                positions.insert(key, data.len());
+                data.push((KeySlice::from_slice(key, ts), value));
+            }
+        }
+    }
+
+    pub(crate) fn merge_operator(&self) -> Result<&Arc<dyn MergeOperator>> {
+        match &self.options.merge_operator {
+            Some(merge_operator) => Ok(merge_operator),
+            None => bail!("merge operator is not set"),
+        }
+    }
+
+    /// Write the batches of several column families, ordered by column family id, to their
+    /// memtables. All of them go into the same WAL record, so that a crash never exposes part of
+    /// them. Returns the memtable size of each column family after the write. Must be called with
+    /// the write lock held.
+    fn write_memtables(
+        &self,
+        batches: &[ColumnFamilyBatch],
+    ) -> Result<Vec<(Arc<ColumnFamily>, usize)>> {
+        // Hold the default state while writing, so that the memtables are not frozen in the
+        // middle of the write. The other column families are frozen after the default one, so
+        // that their new memtables only get the writes logged in the new WAL.
+        let guard = self.state.read();
+        guard.memtable.log_column_family_batches(batches)?;
+        let mut sizes = Vec::with_capacity(batches.len());
+        for (cf_id, data, range_tombstones) in batches {
+            let cf = self.column_family_by_id(*cf_id);
+            let size = if cf.is_default() {
+                guard.memtable.insert_batch(data, range_tombstones);
+                guard.memtable.approximate_size()
+            } else {
+                let cf_guard = cf.state.read();
+                cf_guard.memtable.insert_batch(data, range_tombstones);
+                cf_guard.memtable.approximate_size()
+            };
+            sizes.push((cf, size));
+        }
+        Ok(sizes)
     }
 
     pub fn write_batch<T: AsRef<[u8]>>(
         self: &Arc<Self>,
         batch: &[WriteBatchRecord<T>],
+    ) -> Result<()> {
+        self.write_batch_cf(&self.default_column_family(), batch)
+    }
+
+    pub fn write_batch_cf<T: AsRef<[u8]>>(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        batch: &[WriteBatchRecord<T>],
     ) -> Result<()> {
         if !self.options.serializable {
-            self.write_batch_inner(batch)?;
+            self.write_column_family_batches_inner(&[(cf.id(), batch)])?;
         } else {
             let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
             for record in batch {
                 match record {
                     WriteBatchRecord::Del(key) => {
-                        txn.delete(key.as_ref());
+                        txn.delete_cf(cf, key.as_ref());
                     }
                     WriteBatchRecord::Put(key, value) => {
-                        txn.put(key.as_ref(), value.as_ref());
+                        txn.put_cf(cf, key.as_ref(), value.as_ref());
+                    }
+                    WriteBatchRecord::PutWithTtl(key, value, ttl) => {
+                        txn.put_with_ttl_cf(cf, key.as_ref(), value.as_ref(), *ttl);
+                    }
+                    WriteBatchRecord::DelRange(lower, upper) => {
+                        txn.delete_range_cf(cf, lower.as_ref(), upper.as_ref());
+                    }
+                    WriteBatchRecord::Merge(key, operand) => {
+                        txn.merge_cf(cf, key.as_ref(), operand.as_ref())?;
                     }
                 }
             }
@@ -602,34 +1443,100 @@ impl LsmStorageInner {
 
     /// Put a key-value pair into the storage by writing into the current memtable.
     pub fn put(self: &Arc<Self>, key: &[u8], value: &[u8]) -> Result<()> {
+        self.put_cf(&self.default_column_family(), key, value)
+    }
+
+    pub fn put_cf(self: &Arc<Self>, cf: &ColumnFamily, key: &[u8], value: &[u8]) -> Result<()> {
         if !self.options.serializable {
-            self.write_batch_inner(&[WriteBatchRecord::Put(key, value)])?;
+            self.write_column_family_batches_inner(&[(
+                cf.id(),
+                &[WriteBatchRecord::Put(key, value)][..],
+            )])?;
         } else {
             let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-            txn.put(key, value);
+            txn.put_cf(cf, key, value);
             txn.commit()?;
         }
         Ok(())
     }
 
-    /// Remove a key from the storage by writing an empty value.
+    /// Put a key-value pair that expires after `ttl`. Reads do not see it once it expires, and
+    /// compaction removes it.
+    pub fn put_with_ttl(self: &Arc<Self>, key: &[u8], value: &[u8], ttl: Duration) -> Result<()> {
+        self.put_with_ttl_cf(&self.default_column_family(), key, value, ttl)
+    }
+
+    pub fn put_with_ttl_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        key: &[u8],
+        value: &[u8],
+        ttl: Duration,
+    ) -> Result<()> {
+        self.write_batch_cf(cf, &[WriteBatchRecord::PutWithTtl(key, value, ttl)])
+    }
+
+    /// Merge an operand into the value of a key with the merge operator, without reading the key.
+    /// The operand is folded when the key is read or compacted.
+    pub fn merge(self: &Arc<Self>, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.merge_cf(&self.default_column_family(), key, operand)
+    }
+
+    pub fn merge_cf(self: &Arc<Self>, cf: &ColumnFamily, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.write_batch_cf(cf, &[WriteBatchRecord::Merge(key, operand)])
+    }
+
+    /// Remove a key from the storage by writing a delete tombstone.
     pub fn delete(self: &Arc<Self>, key: &[u8]) -> Result<()> {
+        self.delete_cf(&self.default_column_family(), key)
+    }
+
+    pub fn delete_cf(self: &Arc<Self>, cf: &ColumnFamily, key: &[u8]) -> Result<()> {
         if !self.options.serializable {
-            self.write_batch_inner(&[WriteBatchRecord::Del(key)])?;
+            self.write_column_family_batches_inner(&[(
+                cf.id(),
+                &[WriteBatchRecord::Del(key)][..],
+            )])?;
         } else {
             let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-            txn.delete(key);
+            txn.delete_cf(cf, key);
             txn.commit()?;
         }
         Ok(())
     }
 
-    fn try_freeze(&self, estimated_size: usize) -> Result<()> {
-        if estimated_size >= self.options.target_sst_size {
+    /// Remove all keys in `[lower, upper)` from the storage by writing a range tombstone.
+    pub fn delete_range(self: &Arc<Self>, lower: &[u8], upper: &[u8]) -> Result<()> {
+        self.delete_range_cf(&self.default_column_family(), lower, upper)
+    }
+
+    pub fn delete_range_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        lower: &[u8],
+        upper: &[u8],
+    ) -> Result<()> {
+        if !self.options.serializable {
+            self.write_column_family_batches_inner(&[(
+                cf.id(),
+                &[WriteBatchRecord::DelRange(lower, upper)][..],
+            )])?;
+        } else {
+            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            txn.delete_range_cf(cf, lower, upper);
+            txn.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Freeze the memtables if the memtable of the column family is full. The memtables of all
+    /// column families are frozen together.
+    fn try_freeze(&self, cf: &ColumnFamily, estimated_size: usize) -> Result<()> {
+        if estimated_size >= cf.options.target_sst_size {
             let state_lock = self.state_lock.lock();
-            let guard = self.state.read();
+            let guard = cf.state.read();
             // the memtable could have already been frozen, check again to ensure we really need to freeze
-            if guard.memtable.approximate_size() >= self.options.target_sst_size {
+            if guard.memtable.approximate_size() >= cf.options.target_sst_size {
                 drop(guard);
                 self.force_freeze_memtable(&state_lock)?;
             }
@@ -653,12 +1560,21 @@ impl LsmStorageInner {
         Self::path_of_wal_static(&self.path, id)
     }
 
+    pub(crate) fn path_of_vlog_static(path: impl AsRef<Path>, id: usize) -> PathBuf {
+        path.as_ref().join(format!("{:05}.vlog", id))
+    }
+
+    pub(crate) fn path_of_vlog(&self, id: usize) -> PathBuf {
+        Self::path_of_vlog_static(&self.path, id)
+    }
+
     pub(super) fn sync_dir(&self) -> Result<()> {
         File::open(&self.path)?.sync_all()?;
         Ok(())
     }
 
     fn freeze_memtable_with_memtable(&self, memtable: Arc<MemTable>) -> Result<()> {
+        let memtable_id = memtable.id();
         let mut guard = self.state.write();
         // Swap the current memtable with a new one.
         let mut snapshot = guard.as_ref().clone();
@@ -667,8 +1583,24 @@ impl LsmStorageInner {
         snapshot.imm_memtables.insert(0, old_memtable.clone());
         // Update the snapshot.
         *guard = Arc::new(snapshot);
-
         drop(guard);
+
+        // Freeze the other column families after the default one, which owns the WAL.
+        for cf in self.column_families() {
+            if cf.is_default() {
+                continue;
+            }
+            let mut guard = cf.state.write();
+            let mut snapshot = guard.as_ref().clone();
+            let old_memtable = std::mem::replace(
+                &mut snapshot.memtable,
+                Arc::new(MemTable::create(memtable_id)),
+            );
+            snapshot.imm_memtables.insert(0, old_memtable);
+            *guard = Arc::new(snapshot);
+        }
+
+        self.value_log.sync()?;
         old_memtable.sync_wal()?;
 
         Ok(())
@@ -697,11 +1629,14 @@ impl LsmStorageInner {
         Ok(())
     }
 
-    /// Force flush the earliest-created immutable memtable to disk
+    /// Force flush the earliest-created immutable memtable to disk. The immutable memtables of the
+    /// other column families created at the same time are flushed with it, so that their WAL can
+    /// be removed.
     pub fn force_flush_next_imm_memtable(&self) -> Result<()> {
         let state_lock = self.state_lock.lock();
 
         let flush_memtable;
+        let num_imm_memtables;
 
         {
             let guard = self.state.read();
@@ -710,54 +1645,350 @@ impl LsmStorageInner {
                 .last()
                 .expect("no imm memtables!")
                 .clone();
+            num_imm_memtables = guard.imm_memtables.len();
         }
 
-        let mut builder = SsTableBuilder::new(self.options.block_size);
-        flush_memtable.flush(&mut builder)?;
+        // The SSTs refer to the values in the value log.
+        self.value_log.sync()?;
+        // Flush the other column families first, as the default one marks the WAL as flushed.
+        for cf in self.column_families() {
+            if cf.is_default() {
+                continue;
+            }
+            let memtable = {
+                let guard = cf.state.read();
+                // The column family is created after the memtable is frozen.
+                if guard.imm_memtables.len() < num_imm_memtables {
+                    continue;
+                }
+                guard.imm_memtables.last().unwrap().clone()
+            };
+            self.flush_imm_memtable(&state_lock, &cf, memtable)?;
+        }
         let sst_id = flush_memtable.id();
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+        self.flush_imm_memtable(&state_lock, &self.default_column_family(), flush_memtable)?;
+
+        if self.options.enable_wal {
+            std::fs::remove_file(self.path_of_wal(sst_id))?;
+        }
+        self.maybe_rotate_manifest(&state_lock)?;
+
+        self.sync_dir()?;
+        self.write_stall.notify();
+
+        Ok(())
+    }
+
+    /// Everything that the manifest records describe at this point. Must be called with the state
+    /// lock held, so that no record is added in between.
+    pub(crate) fn manifest_snapshot(&self) -> ManifestSnapshot {
+        let column_families = self
+            .column_families()
+            .iter()
+            .map(|cf| {
+                let state = cf.state.read();
+                ColumnFamilySnapshot {
+                    id: cf.id(),
+                    name: cf.name().to_string(),
+                    options: cf.options.clone(),
+                    l0_sstables: state.l0_sstables.clone(),
+                    levels: state.levels.clone(),
+                }
+            })
+            .collect();
+        let state = self.state.read();
+        let memtables = state
+            .imm_memtables
+            .iter()
+            .rev()
+            .chain(std::iter::once(&state.memtable))
+            .map(|memtable| memtable.id())
+            .collect();
+        ManifestSnapshot {
+            next_sst_id: self.next_sst_id.load(std::sync::atomic::Ordering::SeqCst),
+            memtables,
+            value_logs: self.value_log.file_ids(),
+            column_families,
+            named_snapshots: self.mvcc().named_snapshots.lock().clone(),
+        }
+    }
+
+    /// Rotate the manifest into a new file that starts with a snapshot once it grows beyond
+    /// `max_manifest_size`, so that it does not grow forever and recovery does not replay the
+    /// whole history.
+    pub(crate) fn maybe_rotate_manifest(&self, state_lock: &MutexGuard<'_, ()>) -> Result<()> {
+        if self.manifest().size()? <= self.options.max_manifest_size as u64 {
+            return Ok(());
+        }
+        let id = self.next_sst_id();
+        self.manifest()
+            .rotate(state_lock, id, self.manifest_snapshot())
+    }
+
+    /// Flush the earliest immutable memtable of a column family to an L0 SST. An empty memtable
+    /// is dropped without creating an SST.
+    fn flush_imm_memtable(
+        &self,
+        state_lock: &MutexGuard<'_, ()>,
+        cf: &ColumnFamily,
+        flush_memtable: Arc<MemTable>,
+    ) -> Result<()> {
+        let sst = if flush_memtable.is_empty() {
+            None
+        } else {
+            let mut builder = self.sst_builder(&cf.options, 0, IoPriority::High);
+            flush_memtable.flush(&mut builder)?;
+            // The SST of the default column family takes the id of the memtable (and its WAL).
+            let sst_id = if cf.is_default() {
+                flush_memtable.id()
+            } else {
+                self.next_sst_id()
+            };
+            Some(Arc::new(builder.build(
+                sst_id,
+                Some(self.block_cache.clone()),
+                self.path_of_sst(sst_id),
+            )?))
+        };
 
         // Add the flushed L0 table to the list.
         {
-            let mut guard = self.state.write();
+            let mut guard = cf.state.write();
             let mut snapshot = guard.as_ref().clone();
             // Remove the memtable from the immutable memtables.
             let mem = snapshot.imm_memtables.pop().unwrap();
-            assert_eq!(mem.id(), sst_id);
-            // Add L0 table
-            if self.compaction_controller.flush_to_l0() {
-                // In leveled compaction or no compaction, simply flush to L0
-                snapshot.l0_sstables.insert(0, sst_id);
-            } else {
-                // In tiered compaction, create a new tier
-                snapshot.levels.insert(0, (sst_id, vec![sst_id]));
+            assert_eq!(mem.id(), flush_memtable.id());
+            if let Some(sst) = &sst {
+                cf.add_flushed_sst(&mut snapshot, sst.sst_id());
+                println!(
+                    "flushed {}.sst with size={}",
+                    sst.sst_id(),
+                    sst.table_size()
+                );
+                snapshot.sstables.insert(sst.sst_id(), sst.clone());
             }
-            println!("flushed {}.sst with size={}", sst_id, sst.table_size());
-            snapshot.sstables.insert(sst_id, sst);
             // Update the snapshot.
             *guard = Arc::new(snapshot);
         }
 
-        if self.options.enable_wal {
-            std::fs::remove_file(self.path_of_wal(sst_id))?;
+        if let Some(sst) = sst {
+            self.manifest()
+                .add_record(state_lock, cf.flush_record(sst.sst_id()))?;
         }
 
-        self.manifest()
-            .add_record(&state_lock, ManifestRecord::Flush(sst_id))?;
+        Ok(())
+    }
 
-        self.sync_dir()?;
+    /// Garbage collect the oldest value log file. The values that are still the latest versions of
+    /// their keys are written again at a new commit ts, pointing to the active value log file, and
+    /// the ones that are no longer visible are dropped. The file is only removed once no snapshot
+    /// reads a version that points to it: it cannot be collected while some of its values are
+    /// visible to running transactions, and once its values are moved, it is removed by a later
+    /// call if the transactions older than the move are still running.
+    pub fn gc_value_log(&self) -> Result<bool> {
+        let Some(&file_id) = self.value_log.file_ids().first() else {
+            return Ok(false);
+        };
 
-        Ok(())
+        let (moved_at, sizes) = {
+            // Hold the write lock so that no value is appended to the file, and no newer version is
+            // written while moving the values.
+            let _lck = self.mvcc().write_lock.lock();
+            if matches!(self.value_log.active_file(), Some((id, _)) if id == file_id) {
+                self.value_log.seal()?;
+            }
+            let records = self.value_log.read_file(file_id)?;
+            let latest_ts = self.mvcc().latest_commit_ts();
+            let watermark = self.mvcc().watermark();
+            let column_families = self.column_families();
+            // The live records of each column family. A pointer is only stored in the column
+            // family that the value is written to.
+            let mut live_records = BTreeMap::<usize, Vec<_>>::new();
+            let points_to = |stored: Option<(ValueType, Bytes)>, record: &ValueLogRecord| {
+                stored.is_some_and(|(value_type, stored)| {
+                    matches!(vlog::decode_value(value_type, &stored), StoredValue::Pointer(pointer) if pointer == record.pointer)
+                })
+            };
+            for record in &records {
+                let key = record.key.key_ref();
+                for cf in &column_families {
+                    let stored = self.get_stored_with_ts(cf, key, latest_ts, None)?;
+                    if points_to(stored.clone(), record) {
+                        // The expiry time of a value with a TTL is kept when it is moved.
+                        let expires_at = stored
+                            .and_then(|(value_type, stored)| vlog::expires_at(value_type, &stored));
+                        live_records.entry(cf.id()).or_default().push((
+                            cf.clone(),
+                            record,
+                            expires_at,
+                        ));
+                        break;
+                    } else if points_to(
+                        self.get_stored_with_ts(cf, key, record.key.ts().max(watermark), None)?,
+                        record,
+                    ) {
+                        // An old version that is still visible to a snapshot.
+                        return Ok(false);
+                    }
+                }
+            }
+            if live_records.is_empty() {
+                (None, Vec::new())
+            } else {
+                // Write the latest values at a new commit ts, so that they shadow the versions
+                // pointing to the file in all SSTs, whatever order they are compacted in. The
+                // merges of a key are folded into the value they are merged into.
+                let ts = latest_ts + 1;
+                let mut entries = BTreeMap::<usize, Vec<_>>::new();
+                for (cf_id, records) in &live_records {
+                    let cf_entries = entries.entry(*cf_id).or_default();
+                    for (cf, record, expires_at) in records {
+                        let key = record.key.key_ref();
+                        let reader = Some(self.value_log.reader());
+                        let Some((_, value)) =
+                            self.get_stored_with_ts(cf, key, latest_ts, reader)?
+                        else {
+                            // It has expired since it is checked.
+                            continue;
+                        };
+                        let key = KeySlice::from_slice(key, ts);
+                        let pointer = self.append_value_log(key, &value)?.encode();
+                        cf_entries.push(match expires_at {
+                            Some(expires_at) => (
+                                key,
+                                ValueType::ValuePointerWithTtl,
+                                vlog::encode_expiring(*expires_at, &pointer),
+                            ),
+                            None => (key, ValueType::ValuePointer, pointer),
+                        });
+                    }
+                }
+                let data = entries
+                    .values()
+                    .map(|entries| {
+                        entries
+                            .iter()
+                            .map(|(key, value_type, value)| (*key, *value_type, value.as_slice()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                let batches = entries
+                    .keys()
+                    .zip(&data)
+                    .map(|(cf_id, data)| (*cf_id, data.as_slice(), &[][..]))
+                    .collect::<Vec<ColumnFamilyBatch>>();
+                let sizes = self.write_memtables(&batches)?;
+                self.mvcc().update_commit_ts(ts);
+                (Some(ts), sizes)
+            }
+        };
+        if !sizes.is_empty() {
+            for (cf, size) in sizes {
+                self.try_freeze(&cf, size)?;
+            }
+            // Persist the new versions before removing the old values.
+            if self.options.enable_wal {
+                self.sync()?;
+            } else {
+                self.flush_all_memtables()?;
+            }
+        }
+        if moved_at.is_some_and(|ts| self.mvcc().watermark() < ts) {
+            // A transaction older than the move still reads the values from the file.
+            return Ok(false);
+        }
+
+        {
+            let state_lock = self.state_lock.lock();
+            self.manifest()
+                .add_record(&state_lock, ManifestRecord::ValueLogGc(file_id))?;
+            // Remove the file under the state lock, so that a manifest snapshot never lists it.
+            self.value_log.remove_file(file_id);
+            std::fs::remove_file(self.path_of_vlog(file_id))?;
+        }
+        self.sync_dir()?;
+        Ok(true)
     }
 
     pub fn new_txn(self: &Arc<Self>) -> Result<Arc<Transaction>> {
         Ok(self.mvcc().new_txn(self.clone(), self.options.serializable))
     }
 
+    /// Get a key as of a past commit ts.
+    pub fn get_cf_at(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        key: &[u8],
+        ts: u64,
+    ) -> Result<Option<Bytes>> {
+        self.mvcc()
+            .new_read_only_txn(self.clone(), ts)?
+            .get_cf(cf, key)
+    }
+
+    /// Create an iterator over a range of keys as of a past commit ts.
+    pub fn scan_cf_at(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        ts: u64,
+    ) -> Result<TxnIterator> {
+        self.mvcc()
+            .new_read_only_txn(self.clone(), ts)?
+            .scan_cf(cf, lower, upper)
+    }
+
+    /// Create a named snapshot at the latest commit ts, which is kept across restarts until it is
+    /// deleted. Returns its read ts.
+    ///
+    /// Without the WAL, the memtables are flushed first while no txn commits, or a restart would
+    /// lose the versions at or below the read ts, and reuse their ts for new versions that the
+    /// snapshot would see.
+    pub fn create_snapshot(&self, name: &str) -> Result<u64> {
+        let _lck = if self.options.enable_wal {
+            None
+        } else {
+            let lck = self.mvcc().write_lock.lock();
+            self.flush_all_memtables()?;
+            Some(lck)
+        };
+        let state_lock = self.state_lock.lock();
+        if self.mvcc().named_snapshots.lock().contains_key(name) {
+            bail!("snapshot {} already exists", name);
+        }
+        // Pin the versions before the record is added, so that no compaction drops them.
+        let read_ts = self.mvcc().latest_commit_ts();
+        self.mvcc().add_named_snapshot(name.to_string(), read_ts);
+        if let Err(e) = self.manifest().add_record(
+            &state_lock,
+            ManifestRecord::NewNamedSnapshot(name.to_string(), read_ts),
+        ) {
+            self.mvcc().remove_named_snapshot(name);
+            return Err(e);
+        }
+        Ok(read_ts)
+    }
+
+    /// Open a named snapshot as a read-only txn.
+    pub fn open_snapshot(self: &Arc<Self>, name: &str) -> Result<Arc<Transaction>> {
+        self.mvcc().new_snapshot_txn(self.clone(), name)
+    }
+
+    /// Delete a named snapshot. The txns opened from it can still be used.
+    pub fn delete_snapshot(&self, name: &str) -> Result<()> {
+        let state_lock = self.state_lock.lock();
+        if !self.mvcc().named_snapshots.lock().contains_key(name) {
+            bail!("snapshot {} does not exist", name);
+        }
+        self.manifest().add_record(
+            &state_lock,
+            ManifestRecord::DeleteNamedSnapshot(name.to_string()),
+        )?;
+        self.mvcc().remove_named_snapshot(name);
+        Ok(())
+    }
+
     /// Create an iterator over a range of keys.
     pub fn scan<'a>(
         self: &'a Arc<Self>,
@@ -768,14 +1999,60 @@ impl LsmStorageInner {
         txn.scan(lower, upper)
     }
 
+    pub fn scan_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.scan_cf(cf, lower, upper)
+    }
+
+    /// Create an iterator over the keys that start with `prefix`.
+    pub fn prefix_scan(self: &Arc<Self>, prefix: &[u8]) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.prefix_scan(prefix)
+    }
+
+    pub fn prefix_scan_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        prefix: &[u8],
+    ) -> Result<TxnIterator> {
+        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+        txn.prefix_scan_cf(cf, prefix)
+    }
+
+    /// Whether an SST may have keys that start with `prefix`, which is only known if its bloom
+    /// filter has the prefixes of the current prefix extractor.
+    fn may_contain_prefix(&self, table: &SsTable, prefix: &[u8]) -> bool {
+        let (Some(extractor), Some(bloom)) = (&self.options.prefix_extractor, &table.bloom) else {
+            return true;
+        };
+        let Some(prefix) = extractor.prefix(prefix) else {
+            return true;
+        };
+        if bloom.prefix_extractor.as_deref() != Some(extractor.name().as_str()) {
+            return true;
+        }
+        bloom.may_contain(farmhash::fingerprint32(prefix))
+    }
+
+    /// Create an iterator over a range of keys at `read_ts`. If `prefix` is given, all keys in the
+    /// range start with it, and the SSTs without the prefix are skipped.
     pub(crate) fn scan_with_ts(
         &self,
+        cf: &ColumnFamily,
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
+        prefix: Option<&[u8]>,
         read_ts: u64,
     ) -> Result<FusedIterator<LsmIterator>> {
+        // Get the value log reader before the state, so that it can resolve all values in the state.
+        let value_log = self.value_log.reader();
         let snapshot = {
-            let guard = self.state.read();
+            let guard = cf.state.read();
             Arc::clone(&guard)
         }; // drop global lock here
 
@@ -800,7 +2077,8 @@ impl LsmStorageInner {
                 upper,
                 table.first_key().as_key_slice(),
                 table.last_key().as_key_slice(),
-            ) {
+            ) && prefix.is_none_or(|prefix| self.may_contain_prefix(&table, prefix))
+            {
                 let iter = match lower {
                     Bound::Included(key) => SsTableIterator::create_and_seek_to_key(
                         table,
@@ -834,7 +2112,8 @@ impl LsmStorageInner {
                     upper,
                     table.first_key().as_key_slice(),
                     table.last_key().as_key_slice(),
-                ) {
+                ) && prefix.is_none_or(|prefix| self.may_contain_prefix(&table, prefix))
+                {
                     level_ssts.push(table);
                 }
             }
@@ -864,8 +2143,12 @@ impl LsmStorageInner {
 
         Ok(FusedIterator::new(LsmIterator::new(
             iter,
+            map_bound(lower),
             map_bound(upper),
             read_ts,
+            snapshot.range_tombstones(read_ts, lower, upper),
+            Some(value_log),
+            self.options.merge_operator.clone(),
         )?))
     }
 }
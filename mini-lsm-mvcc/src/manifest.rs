@@ -1,6 +1,7 @@
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{bail, Context, Result};
@@ -8,10 +9,44 @@ use bytes::{Buf, BufMut};
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 
+use crate::column_family::ColumnFamilyOptions;
 use crate::compact::CompactionTask;
 
+/// The manifest file that a database starts with. Once the manifest is rotated, CURRENT holds the
+/// name of the manifest file in use.
+const INITIAL_MANIFEST: &str = "MANIFEST";
+const CURRENT: &str = "CURRENT";
+const CURRENT_TMP: &str = "CURRENT.tmp";
+const MANIFEST_TMP: &str = "MANIFEST.tmp";
+
 pub struct Manifest {
     file: Arc<Mutex<File>>,
+    /// The path of the manifest file in use.
+    path: Mutex<PathBuf>,
+}
+
+/// The SSTs of a column family in a manifest snapshot.
+#[derive(Serialize, Deserialize)]
+pub struct ColumnFamilySnapshot {
+    pub id: usize,
+    pub name: String,
+    pub options: ColumnFamilyOptions,
+    pub l0_sstables: Vec<usize>,
+    pub levels: Vec<(usize, Vec<usize>)>,
+}
+
+/// Everything that the records of a manifest file describe, so that the records before it do not
+/// need to be replayed.
+#[derive(Serialize, Deserialize)]
+pub struct ManifestSnapshot {
+    pub next_sst_id: usize,
+    /// The memtables whose WALs are not flushed yet, from earliest to latest.
+    pub memtables: Vec<usize>,
+    pub value_logs: Vec<usize>,
+    pub column_families: Vec<ColumnFamilySnapshot>,
+    /// The named snapshots and their read ts.
+    #[serde(default)]
+    pub named_snapshots: BTreeMap<String, u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -19,10 +54,63 @@ pub enum ManifestRecord {
     Flush(usize),
     NewMemtable(usize),
     Compaction(CompactionTask, Vec<usize>),
+    NewValueLog(usize),
+    ValueLogGc(usize),
+    /// A column family is created with its id, name and options.
+    NewColumnFamily(usize, String, ColumnFamilyOptions),
+    /// A memtable of a column family other than the default one is flushed to an SST.
+    ColumnFamilyFlush(usize, usize),
+    ColumnFamilyCompaction(usize, CompactionTask, Vec<usize>),
+    /// The first record of a rotated manifest file.
+    Snapshot(ManifestSnapshot),
+    /// A named snapshot is created with its read ts.
+    NewNamedSnapshot(String, u64),
+    DeleteNamedSnapshot(String),
 }
 
 impl Manifest {
+    /// The path of the manifest file in use in the database directory, which may not exist yet.
+    pub fn current_path(dir: impl AsRef<Path>) -> Result<PathBuf> {
+        let dir = dir.as_ref();
+        let current = dir.join(CURRENT);
+        if !current.exists() {
+            return Ok(dir.join(INITIAL_MANIFEST));
+        }
+        let name = std::fs::read_to_string(current).context("failed to read CURRENT")?;
+        Ok(dir.join(name.trim_end()))
+    }
+
+    /// Write a manifest that only has the snapshot to a database directory, e.g. a checkpoint,
+    /// replacing the manifest there.
+    pub fn write_snapshot(dir: impl AsRef<Path>, snapshot: ManifestSnapshot) -> Result<()> {
+        let dir = dir.as_ref();
+        let tmp = dir.join(MANIFEST_TMP);
+        if tmp.exists() {
+            std::fs::remove_file(&tmp)?;
+        }
+        Self::create(&tmp)?.add_record_when_init(ManifestRecord::Snapshot(snapshot))?;
+        std::fs::rename(&tmp, Self::current_path(dir)?)?;
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Remove the manifest files that are not in use, which are left by a crash during rotation.
+    pub fn remove_stale_files(dir: impl AsRef<Path>, current: impl AsRef<Path>) -> Result<()> {
+        let current = current.as_ref();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if (name.starts_with(INITIAL_MANIFEST) || name == CURRENT_TMP) && path != current {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
         Ok(Self {
             file: Arc::new(Mutex::new(
                 OpenOptions::new()
@@ -32,10 +120,12 @@ impl Manifest {
                     .open(path)
                     .context("failed to create manifest")?,
             )),
+            path: Mutex::new(path.to_path_buf()),
         })
     }
 
     pub fn recover(path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestRecord>)> {
+        let path = path.as_ref();
         let mut file = OpenOptions::new()
             .read(true)
             .append(true)
@@ -59,6 +149,7 @@ impl Manifest {
         Ok((
             Self {
                 file: Arc::new(Mutex::new(file)),
+                path: Mutex::new(path.to_path_buf()),
             },
             records,
         ))
@@ -82,4 +173,42 @@ impl Manifest {
         file.sync_all()?;
         Ok(())
     }
+
+    /// The size of the manifest file in use.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.lock().metadata()?.len())
+    }
+
+    /// Start a new manifest file with the snapshot as its first record, point CURRENT to it, and
+    /// remove the old manifest file. A crash at any point leaves either the old or the new file in
+    /// use.
+    pub fn rotate(
+        &self,
+        _state_lock_observer: &MutexGuard<()>,
+        id: usize,
+        snapshot: ManifestSnapshot,
+    ) -> Result<()> {
+        let mut file = self.file.lock();
+        let mut path = self.path.lock();
+        let dir = path
+            .parent()
+            .context("manifest has no parent")?
+            .to_path_buf();
+        let name = format!("{}-{:05}", INITIAL_MANIFEST, id);
+        let new_path = dir.join(&name);
+        let new_manifest = Self::create(&new_path)?;
+        new_manifest.add_record_when_init(ManifestRecord::Snapshot(snapshot))?;
+
+        let current_tmp = dir.join(CURRENT_TMP);
+        let mut current = File::create(&current_tmp)?;
+        current.write_all(name.as_bytes())?;
+        current.sync_all()?;
+        std::fs::rename(&current_tmp, dir.join(CURRENT))?;
+        File::open(&dir)?.sync_all()?;
+
+        let old_path = std::mem::replace(&mut *path, new_path);
+        *file = Arc::into_inner(new_manifest.file).unwrap().into_inner();
+        std::fs::remove_file(old_path)?;
+        Ok(())
+    }
 }
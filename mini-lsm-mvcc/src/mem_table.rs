@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::ops::Bound;
 use std::path::Path;
 use std::sync::atomic::AtomicUsize;
@@ -8,18 +9,23 @@ use bytes::Bytes;
 use crossbeam_skiplist::map::Entry;
 use crossbeam_skiplist::SkipMap;
 use ouroboros::self_referencing;
+use parking_lot::Mutex;
 
+use crate::column_family::DEFAULT_COLUMN_FAMILY_ID;
 use crate::iterators::StorageIterator;
 use crate::key::{KeyBytes, KeySlice, TS_DEFAULT};
+use crate::range_tombstone::RangeTombstone;
 use crate::table::SsTableBuilder;
-use crate::wal::Wal;
+use crate::value_type::ValueType;
+use crate::wal::{ColumnFamilyBatch, Wal};
 
 /// A basic mem-table based on crossbeam-skiplist.
 ///
 /// An initial implementation of memtable is part of week 1, day 1. It will be incrementally implemented in other
 /// chapters of week 1 and week 2.
 pub struct MemTable {
-    pub(crate) map: Arc<SkipMap<KeyBytes, Bytes>>,
+    pub(crate) map: Arc<SkipMap<KeyBytes, (ValueType, Bytes)>>,
+    range_tombstones: Mutex<Vec<RangeTombstone>>,
     wal: Option<Wal>,
     id: usize,
     approximate_size: Arc<AtomicUsize>,
@@ -64,6 +70,7 @@ impl MemTable {
         Self {
             id,
             map: Arc::new(SkipMap::new()),
+            range_tombstones: Mutex::new(Vec::new()),
             wal: None,
             approximate_size: Arc::new(AtomicUsize::new(0)),
         }
@@ -74,6 +81,7 @@ impl MemTable {
         Ok(Self {
             id,
             map: Arc::new(SkipMap::new()),
+            range_tombstones: Mutex::new(Vec::new()),
             wal: Some(Wal::create(path.as_ref())?),
             approximate_size: Arc::new(AtomicUsize::new(0)),
         })
@@ -82,21 +90,53 @@ impl MemTable {
     /// Create a memtable from WAL
     pub fn recover_from_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
         let map = Arc::new(SkipMap::new());
+        let mut range_tombstones = Vec::new();
+        let wal = Wal::recover(path.as_ref(), &map, &mut range_tombstones)?;
         Ok(Self {
             id,
-            wal: Some(Wal::recover(path.as_ref(), &map)?),
+            wal: Some(wal),
             map,
+            range_tombstones: Mutex::new(range_tombstones),
             approximate_size: Arc::new(AtomicUsize::new(0)),
         })
     }
 
-    /// Get a value by key. Should not be used in week 3.
+    /// Create the memtables of all column families from a WAL. The memtable of the default column
+    /// family owns the WAL, and the others are keyed by column family id.
+    pub fn recover_column_families_from_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, BTreeMap<usize, Self>)> {
+        let (wal, recovered) = Wal::recover_column_families(path.as_ref())?;
+        let mut memtable = Self::create(id);
+        memtable.wal = Some(wal);
+        let mut memtables = BTreeMap::new();
+        for (column_family, recovered) in recovered {
+            let target = if column_family == DEFAULT_COLUMN_FAMILY_ID {
+                &memtable
+            } else {
+                memtables
+                    .entry(column_family)
+                    .or_insert_with(|| Self::create(id))
+            };
+            for (key, value) in recovered.kv_pairs {
+                target.map.insert(key, value);
+            }
+            target
+                .range_tombstones
+                .lock()
+                .extend(recovered.range_tombstones);
+        }
+        Ok((memtable, memtables))
+    }
+
+    /// Get a value by key, which is empty for a delete tombstone. Should not be used in week 3.
     pub fn get(&self, key: KeySlice) -> Option<Bytes> {
         let key_bytes = KeyBytes::from_bytes_with_ts(
             Bytes::from_static(unsafe { std::mem::transmute(key.key_ref()) }),
             key.ts(),
         );
-        self.map.get(&key_bytes).map(|e| e.value().clone())
+        self.map.get(&key_bytes).map(|e| e.value().1.clone())
     }
 
     pub fn for_testing_put_slice(&self, key: &[u8], value: &[u8]) -> Result<()> {
@@ -123,15 +163,54 @@ impl MemTable {
     /// In week 1, day 1, simply put the key-value pair into the skipmap.
     /// In week 2, day 6, also flush the data to WAL.
     pub fn put(&self, key: KeySlice, value: &[u8]) -> Result<()> {
-        let estimated_size = key.raw_len() + value.len();
-        self.map.insert(
-            key.to_key_vec().into_key_bytes(),
-            Bytes::copy_from_slice(value),
-        );
+        self.put_batch(&[(key, value)], &[])
+    }
+
+    /// Put a batch of key-value pairs and range tombstones into the mem-table. The batch is written
+    /// to the WAL as a single record, so that it is either fully recovered or not recovered at all.
+    /// An empty value is put as a delete tombstone.
+    pub fn put_batch(
+        &self,
+        data: &[(KeySlice, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) -> Result<()> {
+        let data = data
+            .iter()
+            .map(|(key, value)| (*key, ValueType::of_untyped(value), *value))
+            .collect::<Vec<_>>();
+        self.insert_batch(&data, range_tombstones);
+        self.log_column_family_batches(&[(DEFAULT_COLUMN_FAMILY_ID, &data, range_tombstones)])
+    }
+
+    /// Put a batch into the skipmap without writing it to the WAL. Used for the column families
+    /// whose writes are logged by the WAL of the default column family.
+    pub(crate) fn insert_batch(
+        &self,
+        data: &[(KeySlice, ValueType, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) {
+        let mut estimated_size = 0;
+        if !range_tombstones.is_empty() {
+            estimated_size += range_tombstones.iter().map(|x| x.raw_len()).sum::<usize>();
+            self.range_tombstones
+                .lock()
+                .extend(range_tombstones.iter().cloned());
+        }
+        for (key, value_type, value) in data {
+            estimated_size += key.raw_len() + value.len();
+            self.map.insert(
+                key.to_key_vec().into_key_bytes(),
+                (*value_type, Bytes::copy_from_slice(value)),
+            );
+        }
         self.approximate_size
             .fetch_add(estimated_size, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Write the batches of several column families to the WAL of this mem-table as a single record.
+    pub(crate) fn log_column_family_batches(&self, batches: &[ColumnFamilyBatch]) -> Result<()> {
         if let Some(ref wal) = self.wal {
-            wal.put(key, value)?;
+            wal.put_column_family_batches(batches)?;
         }
         Ok(())
     }
@@ -146,10 +225,13 @@ impl MemTable {
     /// Get an iterator over a range of keys.
     pub fn scan(&self, lower: Bound<KeySlice>, upper: Bound<KeySlice>) -> MemTableIterator {
         let (lower, upper) = (map_key_bound(lower), map_key_bound(upper));
+        let range = (lower.clone(), upper.clone());
         let mut iter = MemTableIteratorBuilder {
             map: self.map.clone(),
-            iter_builder: |map| map.range((lower, upper)),
-            item: (KeyBytes::new(), Bytes::new()),
+            iter_builder: |map| map.range(range),
+            item: (KeyBytes::new(), (ValueType::Delete, Bytes::new())),
+            lower,
+            upper,
         }
         .build();
         iter.next().unwrap();
@@ -159,11 +241,36 @@ impl MemTable {
     /// Flush the mem-table to SSTable. Implement in week 1 day 6.
     pub fn flush(&self, builder: &mut SsTableBuilder) -> Result<()> {
         for entry in self.map.iter() {
-            builder.add(entry.key().as_key_slice(), &entry.value()[..]);
+            let (value_type, value) = entry.value();
+            builder.add_with_type(entry.key().as_key_slice(), *value_type, value);
+        }
+        for tombstone in self.range_tombstones.lock().iter() {
+            builder.add_range_tombstone(tombstone.clone());
         }
         Ok(())
     }
 
+    /// Get all range tombstones in the mem-table.
+    pub fn range_tombstones(&self) -> Vec<RangeTombstone> {
+        self.range_tombstones.lock().clone()
+    }
+
+    /// Get the range tombstones in the mem-table that are visible at `read_ts` and overlap the
+    /// key range.
+    pub fn overlapping_range_tombstones(
+        &self,
+        read_ts: u64,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Vec<RangeTombstone> {
+        self.range_tombstones
+            .lock()
+            .iter()
+            .filter(|tombstone| tombstone.ts <= read_ts && tombstone.overlaps_range(lower, upper))
+            .cloned()
+            .collect()
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -184,7 +291,7 @@ type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
     KeyBytes,
     (Bound<KeyBytes>, Bound<KeyBytes>),
     KeyBytes,
-    Bytes,
+    (ValueType, Bytes),
 >;
 
 /// An iterator over a range of `SkipMap`. This is a self-referential structure and please refer to week 1, day 2
@@ -194,20 +301,47 @@ type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
 #[self_referencing]
 pub struct MemTableIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<KeyBytes, Bytes>>,
+    map: Arc<SkipMap<KeyBytes, (ValueType, Bytes)>>,
     /// Stores a skipmap iterator that refers to the lifetime of `MemTableIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
-    /// Stores the current key-value pair.
-    item: (KeyBytes, Bytes),
+    /// Stores the current key with its value type and value.
+    item: (KeyBytes, (ValueType, Bytes)),
+    /// The range of the iterator, used when moving backward or seeking.
+    lower: Bound<KeyBytes>,
+    upper: Bound<KeyBytes>,
 }
 
 impl MemTableIterator {
-    fn entry_to_item(entry: Option<Entry<'_, KeyBytes, Bytes>>) -> (KeyBytes, Bytes) {
+    fn entry_to_item(
+        entry: Option<Entry<'_, KeyBytes, (ValueType, Bytes)>>,
+    ) -> (KeyBytes, (ValueType, Bytes)) {
         entry
             .map(|x| (x.key().clone(), x.value().clone()))
-            .unwrap_or_else(|| (KeyBytes::new(), Bytes::new()))
+            .unwrap_or_else(|| (KeyBytes::new(), (ValueType::Delete, Bytes::new())))
+    }
+
+    /// Move to the entry found by `find` from the current key and the upper bound, and continue forward iteration from there.
+    fn move_to_entry(
+        &mut self,
+        find: impl for<'a> FnOnce(
+            &'a SkipMap<KeyBytes, (ValueType, Bytes)>,
+            &KeyBytes,
+            &Bound<KeyBytes>,
+        ) -> Option<Entry<'a, KeyBytes, (ValueType, Bytes)>>,
+    ) {
+        self.with_mut(|x| {
+            let entry = find(x.map, &x.item.0, x.upper).filter(|entry| match x.lower {
+                Bound::Included(lower) => entry.key() >= lower,
+                Bound::Excluded(lower) => entry.key() > lower,
+                Bound::Unbounded => true,
+            });
+            *x.item = MemTableIterator::entry_to_item(entry);
+            *x.iter = x
+                .map
+                .range((Bound::Excluded(x.item.0.clone()), x.upper.clone()));
+        });
     }
 }
 
@@ -215,7 +349,11 @@ impl StorageIterator for MemTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
-        &self.borrow_item().1[..]
+        &self.borrow_item().1 .1[..]
+    }
+
+    fn value_type(&self) -> ValueType {
+        self.borrow_item().1 .0
     }
 
     fn key(&self) -> KeySlice {
@@ -231,4 +369,32 @@ impl StorageIterator for MemTableIterator {
         self.with_mut(|x| *x.item = entry);
         Ok(())
     }
+
+    fn prev(&mut self) -> Result<()> {
+        self.move_to_entry(|map, key, _| map.upper_bound(Bound::Excluded(key)));
+        Ok(())
+    }
+
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        let key = KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key.key_ref()), key.ts());
+        self.with_mut(|x| {
+            let within_lower = match x.lower {
+                Bound::Included(lower) => &key >= lower,
+                Bound::Excluded(lower) => &key > lower,
+                Bound::Unbounded => true,
+            };
+            let lower = if within_lower {
+                Bound::Included(key)
+            } else {
+                x.lower.clone()
+            };
+            *x.iter = x.map.range((lower, x.upper.clone()));
+        });
+        self.next()
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.move_to_entry(|map, _, upper| map.upper_bound(upper.as_ref()));
+        Ok(())
+    }
 }
@@ -0,0 +1,22 @@
+use std::fmt::Debug;
+
+use bytes::Bytes;
+
+/// Combines the operands written by `merge` with the value of a key, so that a read-modify-write,
+/// e.g. incrementing a counter or appending to a list, does not need to read the key.
+///
+/// The operands are stored as they are, and folded with the existing value when the key is read,
+/// and when compaction sees the existing value. Both methods must be deterministic, as the same
+/// operands may be folded many times.
+pub trait MergeOperator: Send + Sync + Debug {
+    /// Apply the operands, from the oldest to the latest, to the existing value of the key, which
+    /// is `None` if the key does not exist. The result is the new value, which may be empty.
+    fn full_merge(&self, key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Bytes;
+
+    /// Combine the operands, from the oldest to the latest, into one, when compaction does not
+    /// see the existing value of the key. Returns `None` if they cannot be combined, and they are
+    /// kept as they are.
+    fn partial_merge(&self, _key: &[u8], _operands: &[&[u8]]) -> Option<Bytes> {
+        None
+    }
+}
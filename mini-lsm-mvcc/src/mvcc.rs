@@ -1,43 +1,79 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
+mod conflict;
 pub mod txn;
 pub mod watermark;
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, VecDeque},
     sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
 };
 
+use anyhow::{bail, Result};
 use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
 
 use crate::lsm_storage::LsmStorageInner;
 
-use self::{txn::Transaction, watermark::Watermark};
+use self::{conflict::KeySet, txn::Transaction, watermark::Watermark};
 
 pub(crate) struct CommittedTxnData {
-    pub(crate) key_hashes: HashSet<u32>,
+    pub(crate) write_set: KeySet,
     #[allow(dead_code)]
     pub(crate) read_ts: u64,
     #[allow(dead_code)]
     pub(crate) commit_ts: u64,
 }
 
+/// How much history compaction keeps even if no txn reads it, so that it can be read with
+/// `get_at` and `scan_at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryRetention {
+    /// Keep the versions visible at the last `n` commit ts.
+    Timestamps(u64),
+    /// Keep the versions visible at any time within the duration. The commit times are only kept
+    /// in memory, so the history before the storage is opened is not kept by this.
+    Duration(Duration),
+}
+
+/// The commit times are sampled at most this often. A sample taken before a commit holds the
+/// latest commit ts at that time, so the retained history is at most this much longer.
+const COMMIT_TIME_SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
 pub(crate) struct LsmMvccInner {
     pub(crate) write_lock: Mutex<()>,
     pub(crate) commit_lock: Mutex<()>,
     pub(crate) ts: Arc<Mutex<(u64, Watermark)>>,
     pub(crate) committed_txns: Arc<Mutex<BTreeMap<u64, CommittedTxnData>>>,
+    /// The read ts of the running serializable txns, which are the only txns that check the
+    /// committed txns for conflicts. Unlike the watermark, it has no read-only txns and named
+    /// snapshots, so that they do not keep the committed txns. Always locked after `ts`.
+    serializable_txns: Mutex<Watermark>,
+    /// The named snapshots and their read ts. Each of them is a reader of the watermark, so that
+    /// compaction keeps the versions they see.
+    pub(crate) named_snapshots: Mutex<BTreeMap<String, u64>>,
+    history_retention: Option<HistoryRetention>,
+    /// The latest commit ts when the storage is opened.
+    initial_ts: u64,
+    /// Samples of the latest commit ts over time, for `HistoryRetention::Duration`. Only the last
+    /// sample before the retained duration is kept.
+    commit_times: Mutex<VecDeque<(Instant, u64)>>,
 }
 
 impl LsmMvccInner {
-    pub fn new(initial_ts: u64) -> Self {
+    pub fn new(initial_ts: u64, history_retention: Option<HistoryRetention>) -> Self {
         Self {
             write_lock: Mutex::new(()),
             commit_lock: Mutex::new(()),
             ts: Arc::new(Mutex::new((initial_ts, Watermark::new()))),
             committed_txns: Arc::new(Mutex::new(BTreeMap::new())),
+            serializable_txns: Mutex::new(Watermark::new()),
+            named_snapshots: Mutex::new(BTreeMap::new()),
+            history_retention,
+            initial_ts,
+            commit_times: Mutex::new(VecDeque::new()),
         }
     }
 
@@ -46,29 +82,168 @@ impl LsmMvccInner {
     }
 
     pub fn update_commit_ts(&self, ts: u64) {
-        self.ts.lock().0 = ts;
+        let mut guard = self.ts.lock();
+        if let Some(HistoryRetention::Duration(duration)) = self.history_retention {
+            let now = Instant::now();
+            let mut commit_times = self.commit_times.lock();
+            if commit_times
+                .back()
+                .is_none_or(|(time, _)| now.duration_since(*time) >= COMMIT_TIME_SAMPLE_INTERVAL)
+            {
+                commit_times.push_back((now, guard.0));
+            }
+            if let Some(cutoff) = now.checked_sub(duration) {
+                while commit_times.get(1).is_some_and(|(time, _)| *time <= cutoff) {
+                    commit_times.pop_front();
+                }
+            }
+        }
+        guard.0 = ts;
     }
 
-    /// All ts (strictly) below this ts can be garbage collected.
+    /// The oldest ts whose versions are kept for the history retention.
+    fn retention_horizon(&self, latest_ts: u64) -> Option<u64> {
+        match self.history_retention? {
+            HistoryRetention::Timestamps(n) => Some(latest_ts.saturating_sub(n)),
+            HistoryRetention::Duration(duration) => {
+                let Some(cutoff) = Instant::now().checked_sub(duration) else {
+                    return Some(self.initial_ts);
+                };
+                let commit_times = self.commit_times.lock();
+                Some(
+                    commit_times
+                        .iter()
+                        .rev()
+                        .find(|(time, _)| *time <= cutoff)
+                        .map_or(self.initial_ts, |(_, ts)| *ts),
+                )
+            }
+        }
+    }
+
+    fn watermark_locked(&self, ts: &(u64, Watermark)) -> u64 {
+        let watermark = ts.1.watermark().unwrap_or(ts.0);
+        self.retention_horizon(ts.0)
+            .map_or(watermark, |horizon| horizon.min(watermark))
+    }
+
+    /// All ts (strictly) below this ts can be garbage collected. It is the read ts of the oldest
+    /// reader, or the start of the retained history if it is older.
     pub fn watermark(&self) -> u64 {
-        let ts = self.ts.lock();
-        ts.1.watermark().unwrap_or(ts.0)
+        self.watermark_locked(&self.ts.lock())
+    }
+
+    /// Remove the committed txns that no running txn can conflict with. A serializable txn only
+    /// checks the txns committed after its read ts, and the txns started later read at least the
+    /// latest commit ts.
+    pub(crate) fn gc_committed_txns(&self) {
+        let watermark = {
+            let ts = self.ts.lock();
+            self.serializable_txns.lock().watermark().unwrap_or(ts.0)
+        };
+        let mut committed_txns = self.committed_txns.lock();
+        while let Some(entry) = committed_txns.first_entry() {
+            if *entry.key() <= watermark {
+                entry.remove();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// The number of committed txns kept for the serializable check.
+    pub fn num_committed_txns(&self) -> usize {
+        self.committed_txns.lock().len()
     }
 
     pub fn new_txn(&self, inner: Arc<LsmStorageInner>, serializable: bool) -> Arc<Transaction> {
         let mut ts = self.ts.lock();
         let read_ts = ts.0;
         ts.1.add_reader(read_ts);
+        if serializable {
+            self.serializable_txns.lock().add_reader(read_ts);
+        }
         Arc::new(Transaction {
             inner,
             read_ts,
-            local_storage: Arc::new(SkipMap::new()),
+            local_storage: SkipMap::new(),
             committed: Arc::new(AtomicBool::new(false)),
-            key_hashes: if serializable {
-                Some(Mutex::new((HashSet::new(), HashSet::new())))
+            key_sets: if serializable {
+                Some(Mutex::new((KeySet::default(), KeySet::default())))
             } else {
                 None
             },
+            read_only: false,
+        })
+    }
+
+    /// Remove the reader of a serializable txn that ends.
+    pub(crate) fn remove_serializable_txn(&self, read_ts: u64) {
+        let _ts = self.ts.lock();
+        self.serializable_txns.lock().remove_reader(read_ts);
+    }
+
+    /// Pin the versions visible at `read_ts` for a named snapshot.
+    pub(crate) fn add_named_snapshot(&self, name: String, read_ts: u64) {
+        let mut ts = self.ts.lock();
+        ts.1.add_reader(read_ts);
+        self.named_snapshots.lock().insert(name, read_ts);
+    }
+
+    /// Unpin the versions of a named snapshot. Returns its read ts, or `None` if it does not exist.
+    pub(crate) fn remove_named_snapshot(&self, name: &str) -> Option<u64> {
+        let mut ts = self.ts.lock();
+        let read_ts = self.named_snapshots.lock().remove(name)?;
+        ts.1.remove_reader(read_ts);
+        Some(read_ts)
+    }
+
+    /// Start a read-only txn at the read ts of a named snapshot.
+    pub fn new_snapshot_txn(
+        &self,
+        inner: Arc<LsmStorageInner>,
+        name: &str,
+    ) -> Result<Arc<Transaction>> {
+        let mut ts = self.ts.lock();
+        let Some(&read_ts) = self.named_snapshots.lock().get(name) else {
+            bail!("snapshot {} does not exist", name);
+        };
+        ts.1.add_reader(read_ts);
+        Ok(Self::read_only_txn(inner, read_ts))
+    }
+
+    /// Start a read-only txn at a past ts, which must not be below the watermark, as the versions
+    /// visible at it may already be garbage collected.
+    pub fn new_read_only_txn(
+        &self,
+        inner: Arc<LsmStorageInner>,
+        read_ts: u64,
+    ) -> Result<Arc<Transaction>> {
+        let mut ts = self.ts.lock();
+        if read_ts > ts.0 {
+            bail!("ts {} is newer than the latest commit ts {}", read_ts, ts.0);
+        }
+        let watermark = self.watermark_locked(&ts);
+        if read_ts < watermark {
+            bail!(
+                "ts {} is older than the retained history, which starts at ts {}",
+                read_ts,
+                watermark
+            );
+        }
+        ts.1.add_reader(read_ts);
+        Ok(Self::read_only_txn(inner, read_ts))
+    }
+
+    /// A read-only txn whose reader is added to the watermark.
+    fn read_only_txn(inner: Arc<LsmStorageInner>, read_ts: u64) -> Arc<Transaction> {
+        Arc::new(Transaction {
+            inner,
+            read_ts,
+            local_storage: SkipMap::new(),
+            committed: Arc::new(AtomicBool::new(false)),
+            key_sets: None,
+            read_only: true,
         })
     }
 }
@@ -0,0 +1,98 @@
+use std::{collections::BTreeSet, ops::Bound};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::mem_table::map_bound;
+
+/// The keys and the key ranges that a txn reads or writes, for the serializable check. Keys of
+/// different column families do not conflict, so each of them is kept with its column family id.
+#[derive(Default)]
+pub(crate) struct KeySet {
+    keys: BTreeSet<(usize, Bytes)>,
+    ranges: Vec<(usize, Bound<Bytes>, Bound<Bytes>)>,
+}
+
+/// The smallest key that is within the lower bound.
+fn first_key(lower: &Bound<Bytes>) -> Bytes {
+    match lower {
+        Bound::Included(key) => key.clone(),
+        Bound::Excluded(key) => {
+            let mut next = BytesMut::with_capacity(key.len() + 1);
+            next.put_slice(key);
+            next.put_u8(0);
+            next.freeze()
+        }
+        Bound::Unbounded => Bytes::new(),
+    }
+}
+
+fn is_below_upper(key: &[u8], upper: &Bound<Bytes>) -> bool {
+    match upper {
+        Bound::Included(upper) => key <= upper.as_ref(),
+        Bound::Excluded(upper) => key < upper.as_ref(),
+        Bound::Unbounded => true,
+    }
+}
+
+fn range_contains(lower: &Bound<Bytes>, upper: &Bound<Bytes>, key: &[u8]) -> bool {
+    first_key(lower).as_ref() <= key && is_below_upper(key, upper)
+}
+
+impl KeySet {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.ranges.is_empty()
+    }
+
+    pub(crate) fn insert_key(&mut self, cf_id: usize, key: &[u8]) {
+        self.keys.insert((cf_id, Bytes::copy_from_slice(key)));
+    }
+
+    pub(crate) fn insert_range(&mut self, cf_id: usize, lower: Bound<&[u8]>, upper: Bound<&[u8]>) {
+        self.ranges
+            .push((cf_id, map_bound(lower), map_bound(upper)));
+    }
+
+    /// Check if any of the keys in `read_set` is written by this write set. Returns the first
+    /// such key found.
+    pub(crate) fn find_conflict(&self, read_set: &KeySet) -> Option<Bytes> {
+        for (cf_id, key) in &self.keys {
+            if read_set.contains(*cf_id, key) {
+                return Some(key.clone());
+            }
+        }
+        for (cf_id, lower, upper) in &self.ranges {
+            let keys_lower = match lower {
+                Bound::Included(key) => Bound::Included((*cf_id, key.clone())),
+                Bound::Excluded(key) => Bound::Excluded((*cf_id, key.clone())),
+                Bound::Unbounded => Bound::Included((*cf_id, Bytes::new())),
+            };
+            if let Some((_, key)) = read_set
+                .keys
+                .range((keys_lower, Bound::Unbounded))
+                .next()
+                .filter(|(key_cf_id, key)| key_cf_id == cf_id && is_below_upper(key, upper))
+            {
+                return Some(key.clone());
+            }
+            for (read_cf_id, read_lower, read_upper) in &read_set.ranges {
+                if read_cf_id != cf_id {
+                    continue;
+                }
+                // The two ranges overlap iff the first key within both lower bounds is within
+                // both upper bounds.
+                let key = first_key(lower).max(first_key(read_lower));
+                if is_below_upper(&key, upper) && is_below_upper(&key, read_upper) {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    fn contains(&self, cf_id: usize, key: &Bytes) -> bool {
+        self.keys.contains(&(cf_id, key.clone()))
+            || self.ranges.iter().any(|(range_cf_id, lower, upper)| {
+                *range_cf_id == cf_id && range_contains(lower, upper, key)
+            })
+    }
+}
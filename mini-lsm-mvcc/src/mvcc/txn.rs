@@ -1,10 +1,12 @@
 use std::{
-    collections::HashSet,
+    collections::HashMap,
+    fmt,
     ops::Bound,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use anyhow::{bail, Result};
@@ -14,50 +16,197 @@ use ouroboros::self_referencing;
 use parking_lot::Mutex;
 
 use crate::{
+    column_family::ColumnFamily,
     iterators::{two_merge_iterator::TwoMergeIterator, StorageIterator},
     lsm_iterator::{FusedIterator, LsmIterator},
     lsm_storage::{LsmStorageInner, WriteBatchRecord},
     mem_table::map_bound,
-    mvcc::CommittedTxnData,
+    mvcc::{conflict::KeySet, CommittedTxnData},
+    prefix_extractor::prefix_upper_bound,
+    value_type::ValueType,
 };
 
+/// The writes of a txn to one column family.
+#[derive(Default)]
+pub(crate) struct TxnLocalStorage {
+    /// The values put by this txn, and the delete tombstones of the keys deleted by it.
+    pub(crate) map: Arc<SkipMap<Bytes, (ValueType, Bytes)>>,
+    /// Ranges deleted by `delete_range` in this txn, as `[lower, upper)`.
+    pub(crate) range_tombstones: Mutex<Vec<(Bytes, Bytes)>>,
+    /// The TTLs of the keys put by `put_with_ttl` in this txn. They start when the txn commits.
+    pub(crate) ttls: SkipMap<Bytes, Duration>,
+    /// The operands merged into the keys that this txn does not put or delete. They are written
+    /// as merges, so that the value is not read, while `map` has the value that this txn sees.
+    pub(crate) merge_operands: Mutex<HashMap<Bytes, Vec<Bytes>>>,
+}
+
+impl TxnLocalStorage {
+    /// Check if the key is deleted by a `delete_range` of this txn and not written again after it.
+    fn is_range_deleted(&self, key: &[u8]) -> bool {
+        let range_tombstones = self.range_tombstones.lock();
+        if range_tombstones.is_empty() || self.map.contains_key(key) {
+            return false;
+        }
+        range_tombstones
+            .iter()
+            .any(|(lower, upper)| lower.as_ref() <= key && key < upper.as_ref())
+    }
+
+    fn write_batch(&self) -> Vec<WriteBatchRecord<Bytes>> {
+        let merge_operands = self.merge_operands.lock();
+        // Range tombstones go first so that they do not override the keys put after them.
+        self.range_tombstones
+            .lock()
+            .iter()
+            .map(|(lower, upper)| WriteBatchRecord::DelRange(lower.clone(), upper.clone()))
+            .chain(self.map.iter().flat_map(|entry| {
+                if let Some(operands) = merge_operands.get(entry.key()) {
+                    return operands
+                        .iter()
+                        .map(|operand| {
+                            WriteBatchRecord::Merge(entry.key().clone(), operand.clone())
+                        })
+                        .collect();
+                }
+                let (value_type, value) = entry.value();
+                let record = if *value_type == ValueType::Delete {
+                    WriteBatchRecord::Del(entry.key().clone())
+                } else if let Some(ttl) = self.ttls.get(entry.key()) {
+                    WriteBatchRecord::PutWithTtl(entry.key().clone(), value.clone(), *ttl.value())
+                } else {
+                    WriteBatchRecord::Put(entry.key().clone(), value.clone())
+                };
+                vec![record]
+            }))
+            .collect()
+    }
+}
+
+/// The errors of a txn commit that the caller can handle, e.g. by retrying the txn. They are
+/// returned in an `anyhow::Error`, and can be told apart with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnError {
+    /// A serializable txn read a key, or scanned a range with a key, that another txn wrote and
+    /// committed after this txn started.
+    Conflict { key: Bytes },
+}
+
+impl fmt::Display for TxnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxnError::Conflict { key } => {
+                write!(f, "serializable check failed: conflict on key {:?}", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TxnError {}
+
 pub struct Transaction {
     pub(crate) read_ts: u64,
     pub(crate) inner: Arc<LsmStorageInner>,
-    pub(crate) local_storage: Arc<SkipMap<Bytes, Bytes>>,
+    /// The writes of this txn, keyed by column family id.
+    pub(crate) local_storage: SkipMap<usize, Arc<TxnLocalStorage>>,
     pub(crate) committed: Arc<AtomicBool>,
-    /// Write set and read set
-    pub(crate) key_hashes: Option<Mutex<(HashSet<u32>, HashSet<u32>)>>,
+    /// Write set and read set, of a serializable txn. A scan adds its whole range to the read
+    /// set, so that a key inserted into the range by another txn is a conflict.
+    pub(crate) key_sets: Option<Mutex<(KeySet, KeySet)>>,
+    /// A txn of a named snapshot or a past ts cannot write.
+    pub(crate) read_only: bool,
 }
 
 impl Transaction {
+    fn local_storage(&self, cf_id: usize) -> Arc<TxnLocalStorage> {
+        self.local_storage
+            .get_or_insert_with(cf_id, Default::default)
+            .value()
+            .clone()
+    }
+
     pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+        self.get_cf(&self.inner.default_column_family(), key)
+    }
+
+    pub fn get_cf(&self, cf: &ColumnFamily, key: &[u8]) -> Result<Option<Bytes>> {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
-        if let Some(guard) = &self.key_hashes {
+        if let Some(guard) = &self.key_sets {
             let mut guard = guard.lock();
             let (_, read_set) = &mut *guard;
-            read_set.insert(farmhash::hash32(key));
+            read_set.insert_key(cf.id(), key);
         }
-        if let Some(entry) = self.local_storage.get(key) {
-            if entry.value().is_empty() {
+        let local_storage = self.local_storage(cf.id());
+        if let Some(entry) = local_storage.map.get(key) {
+            let (value_type, value) = entry.value();
+            if *value_type == ValueType::Delete {
                 return Ok(None);
             } else {
-                return Ok(Some(entry.value().clone()));
+                return Ok(Some(value.clone()));
             }
         }
-        self.inner.get_with_ts(key, self.read_ts)
+        if local_storage.is_range_deleted(key) {
+            return Ok(None);
+        }
+        self.inner.get_with_ts(cf, key, self.read_ts)
     }
 
     pub fn scan(self: &Arc<Self>, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
+        self.scan_cf(&self.inner.default_column_family(), lower, upper)
+    }
+
+    pub fn scan_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> Result<TxnIterator> {
+        self.scan_cf_inner(cf, lower, upper, None)
+    }
+
+    /// Create an iterator over the keys that start with `prefix`.
+    pub fn prefix_scan(self: &Arc<Self>, prefix: &[u8]) -> Result<TxnIterator> {
+        self.prefix_scan_cf(&self.inner.default_column_family(), prefix)
+    }
+
+    pub fn prefix_scan_cf(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        prefix: &[u8],
+    ) -> Result<TxnIterator> {
+        let upper = prefix_upper_bound(prefix);
+        self.scan_cf_inner(
+            cf,
+            Bound::Included(prefix),
+            upper.as_ref().map(|x| x.as_ref()),
+            Some(prefix),
+        )
+    }
+
+    fn scan_cf_inner(
+        self: &Arc<Self>,
+        cf: &ColumnFamily,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        prefix: Option<&[u8]>,
+    ) -> Result<TxnIterator> {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
+        if let Some(guard) = &self.key_sets {
+            let mut guard = guard.lock();
+            let (_, read_set) = &mut *guard;
+            read_set.insert_range(cf.id(), lower, upper);
+        }
+        let local_storage = self.local_storage(cf.id());
+        let range = (map_bound(lower), map_bound(upper));
         let mut local_iter = TxnLocalIteratorBuilder {
-            map: self.local_storage.clone(),
-            iter_builder: |map| map.range((map_bound(lower), map_bound(upper))),
-            item: (Bytes::new(), Bytes::new()),
+            map: local_storage.map.clone(),
+            iter_builder: |map| map.range(range),
+            item: (Bytes::new(), (ValueType::Delete, Bytes::new())),
+            lower: map_bound(lower),
+            upper: map_bound(upper),
         }
         .build();
         let entry = local_iter.with_iter_mut(|iter| TxnLocalIterator::entry_to_item(iter.next()));
@@ -65,59 +214,186 @@ impl Transaction {
 
         TxnIterator::create(
             self.clone(),
+            cf.id(),
             TwoMergeIterator::create(
                 local_iter,
-                self.inner.scan_with_ts(lower, upper, self.read_ts)?,
+                self.inner
+                    .scan_with_ts(cf, lower, upper, prefix, self.read_ts)?,
             )?,
         )
     }
 
     pub fn put(&self, key: &[u8], value: &[u8]) {
+        self.put_cf(&self.inner.default_column_family(), key, value)
+    }
+
+    pub fn put_cf(&self, cf: &ColumnFamily, key: &[u8], value: &[u8]) {
+        self.put_inner(cf, key, value, None)
+    }
+
+    /// Put a key that expires after `ttl`, counted from when the txn commits. This txn sees the
+    /// key until it commits.
+    pub fn put_with_ttl(&self, key: &[u8], value: &[u8], ttl: Duration) {
+        self.put_with_ttl_cf(&self.inner.default_column_family(), key, value, ttl)
+    }
+
+    pub fn put_with_ttl_cf(&self, cf: &ColumnFamily, key: &[u8], value: &[u8], ttl: Duration) {
+        self.put_inner(cf, key, value, Some(ttl))
+    }
+
+    fn put_inner(&self, cf: &ColumnFamily, key: &[u8], value: &[u8], ttl: Option<Duration>) {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
-        self.local_storage
-            .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
-        if let Some(key_hashes) = &self.key_hashes {
-            let mut key_hashes = key_hashes.lock();
-            let (write_hashes, _) = &mut *key_hashes;
-            write_hashes.insert(farmhash::hash32(key));
+        if self.read_only {
+            panic!("cannot write to read-only txn!");
+        }
+        let local_storage = self.local_storage(cf.id());
+        let key_bytes = Bytes::copy_from_slice(key);
+        match ttl {
+            Some(ttl) => {
+                local_storage.ttls.insert(key_bytes.clone(), ttl);
+            }
+            None => {
+                local_storage.ttls.remove(key);
+            }
+        }
+        local_storage.merge_operands.lock().remove(key);
+        local_storage
+            .map
+            .insert(key_bytes, (ValueType::Put, Bytes::copy_from_slice(value)));
+        if let Some(key_sets) = &self.key_sets {
+            let mut key_sets = key_sets.lock();
+            let (write_set, _) = &mut *key_sets;
+            write_set.insert_key(cf.id(), key);
         }
     }
 
     pub fn delete(&self, key: &[u8]) {
+        self.delete_cf(&self.inner.default_column_family(), key)
+    }
+
+    pub fn delete_cf(&self, cf: &ColumnFamily, key: &[u8]) {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
-        self.local_storage
-            .insert(Bytes::copy_from_slice(key), Bytes::new());
-        if let Some(key_hashes) = &self.key_hashes {
-            let mut key_hashes = key_hashes.lock();
-            let (write_hashes, _) = &mut *key_hashes;
-            write_hashes.insert(farmhash::hash32(key));
+        if self.read_only {
+            panic!("cannot write to read-only txn!");
+        }
+        let local_storage = self.local_storage(cf.id());
+        local_storage.ttls.remove(key);
+        local_storage.merge_operands.lock().remove(key);
+        local_storage.map.insert(
+            Bytes::copy_from_slice(key),
+            (ValueType::Delete, Bytes::new()),
+        );
+        if let Some(key_sets) = &self.key_sets {
+            let mut key_sets = key_sets.lock();
+            let (write_set, _) = &mut *key_sets;
+            write_set.insert_key(cf.id(), key);
+        }
+    }
+
+    /// Delete all keys in `[lower, upper)`. Keys put after this call are not affected.
+    pub fn delete_range(&self, lower: &[u8], upper: &[u8]) {
+        self.delete_range_cf(&self.inner.default_column_family(), lower, upper)
+    }
+
+    pub fn delete_range_cf(&self, cf: &ColumnFamily, lower: &[u8], upper: &[u8]) {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        if self.read_only {
+            panic!("cannot write to read-only txn!");
+        }
+        if lower >= upper {
+            return;
         }
+        let local_storage = self.local_storage(cf.id());
+        let (lower, upper) = (Bytes::copy_from_slice(lower), Bytes::copy_from_slice(upper));
+        local_storage
+            .merge_operands
+            .lock()
+            .retain(|key, _| !(lower <= key && key < &upper));
+        for entry in local_storage.map.range((
+            Bound::Included(lower.clone()),
+            Bound::Excluded(upper.clone()),
+        )) {
+            local_storage.ttls.remove(entry.key());
+            entry.remove();
+        }
+        if let Some(key_sets) = &self.key_sets {
+            let mut key_sets = key_sets.lock();
+            let (write_set, _) = &mut *key_sets;
+            write_set.insert_range(cf.id(), Bound::Included(&lower), Bound::Excluded(&upper));
+        }
+        local_storage.range_tombstones.lock().push((lower, upper));
+    }
+
+    /// Merge an operand into the value of a key with the merge operator. Unless this txn has put
+    /// or deleted the key, the operand is written without reading the key, so the key is not added
+    /// to the read set of a serializable txn and other txns merging into it do not conflict.
+    pub fn merge(&self, key: &[u8], operand: &[u8]) -> Result<()> {
+        self.merge_cf(&self.inner.default_column_family(), key, operand)
     }
 
+    pub fn merge_cf(&self, cf: &ColumnFamily, key: &[u8], operand: &[u8]) -> Result<()> {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        if self.read_only {
+            panic!("cannot write to read-only txn!");
+        }
+        assert!(!key.is_empty(), "key cannot be empty");
+        let merge_operator = self.inner.merge_operator()?;
+        let local_storage = self.local_storage(cf.id());
+        let key_bytes = Bytes::copy_from_slice(key);
+        let mut merge_operands = local_storage.merge_operands.lock();
+        let existing = match local_storage.map.get(key) {
+            Some(entry) => match entry.value() {
+                (ValueType::Delete, _) => None,
+                (_, value) => Some(value.clone()),
+            },
+            None if local_storage.is_range_deleted(key) => None,
+            None => {
+                // The value this txn sees, which is not what the operand is merged into when
+                // the txn commits.
+                merge_operands.insert(key_bytes.clone(), Vec::new());
+                self.inner.get_with_ts(cf, key, self.read_ts)?
+            }
+        };
+        if let Some(operands) = merge_operands.get_mut(key) {
+            operands.push(Bytes::copy_from_slice(operand));
+        }
+        drop(merge_operands);
+        let value = merge_operator.full_merge(key, existing.as_deref(), &[operand]);
+        local_storage.map.insert(key_bytes, (ValueType::Put, value));
+        if let Some(key_sets) = &self.key_sets {
+            let mut key_sets = key_sets.lock();
+            let (write_set, _) = &mut *key_sets;
+            write_set.insert_key(cf.id(), key);
+        }
+        Ok(())
+    }
+
+    /// Commit the writes of all column families atomically.
     pub fn commit(&self) -> Result<()> {
         self.committed
             .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
             .expect("cannot operate on committed txn!");
+        if self.read_only {
+            return Ok(());
+        }
         let _commit_lock = self.inner.mvcc().commit_lock.lock();
         let serializability_check;
-        if let Some(guard) = &self.key_hashes {
+        if let Some(guard) = &self.key_sets {
             let guard = guard.lock();
             let (write_set, read_set) = &*guard;
-            println!(
-                "commit txn: write_set: {:?}, read_set: {:?}",
-                write_set, read_set
-            );
             if !write_set.is_empty() {
                 let committed_txns = self.inner.mvcc().committed_txns.lock();
                 for (_, txn_data) in committed_txns.range((self.read_ts + 1)..) {
-                    for key_hash in read_set {
-                        if txn_data.key_hashes.contains(key_hash) {
-                            bail!("serializable check failed");
-                        }
+                    if let Some(key) = txn_data.write_set.find_conflict(read_set) {
+                        bail!(TxnError::Conflict { key });
                     }
                 }
             }
@@ -125,42 +401,32 @@ impl Transaction {
         } else {
             serializability_check = false;
         }
-        let batch = self
+        let batches = self
             .local_storage
             .iter()
-            .map(|entry| {
-                if entry.value().is_empty() {
-                    WriteBatchRecord::Del(entry.key().clone())
-                } else {
-                    WriteBatchRecord::Put(entry.key().clone(), entry.value().clone())
-                }
-            })
+            .map(|entry| (*entry.key(), entry.value().write_batch()))
             .collect::<Vec<_>>();
-        let ts = self.inner.write_batch_inner(&batch)?;
+        let batches = batches
+            .iter()
+            .map(|(cf_id, batch)| (*cf_id, batch.as_slice()))
+            .collect::<Vec<_>>();
+        let ts = self.inner.write_column_family_batches_inner(&batches)?;
         if serializability_check {
             let mut committed_txns = self.inner.mvcc().committed_txns.lock();
-            let mut key_hashes = self.key_hashes.as_ref().unwrap().lock();
-            let (write_set, _) = &mut *key_hashes;
+            let mut key_sets = self.key_sets.as_ref().unwrap().lock();
+            let (write_set, _) = &mut *key_sets;
 
             let old_data = committed_txns.insert(
                 ts,
                 CommittedTxnData {
-                    key_hashes: std::mem::take(write_set),
+                    write_set: std::mem::take(write_set),
                     read_ts: self.read_ts,
                     commit_ts: ts,
                 },
             );
             assert!(old_data.is_none());
-
-            // remove unneeded txn data
-            let watermark = self.inner.mvcc().watermark();
-            while let Some(entry) = committed_txns.first_entry() {
-                if *entry.key() < watermark {
-                    entry.remove();
-                } else {
-                    break;
-                }
-            }
+            drop(committed_txns);
+            self.inner.mvcc().gc_committed_txns();
         }
         Ok(())
     }
@@ -168,30 +434,68 @@ impl Transaction {
 
 impl Drop for Transaction {
     fn drop(&mut self) {
-        self.inner.mvcc().ts.lock().1.remove_reader(self.read_ts)
+        self.inner.mvcc().ts.lock().1.remove_reader(self.read_ts);
+        // The txns committed while this one was running may no longer be needed.
+        if self.key_sets.is_some() {
+            self.inner.mvcc().remove_serializable_txn(self.read_ts);
+            self.inner.mvcc().gc_committed_txns();
+        }
     }
 }
 
-type SkipMapRangeIter<'a> =
-    crossbeam_skiplist::map::Range<'a, Bytes, (Bound<Bytes>, Bound<Bytes>), Bytes, Bytes>;
+type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
+    'a,
+    Bytes,
+    (Bound<Bytes>, Bound<Bytes>),
+    Bytes,
+    (ValueType, Bytes),
+>;
 
 #[self_referencing]
 pub struct TxnLocalIterator {
     /// Stores a reference to the skipmap.
-    map: Arc<SkipMap<Bytes, Bytes>>,
+    map: Arc<SkipMap<Bytes, (ValueType, Bytes)>>,
     /// Stores a skipmap iterator that refers to the lifetime of `TxnLocalIterator` itself.
     #[borrows(map)]
     #[not_covariant]
     iter: SkipMapRangeIter<'this>,
-    /// Stores the current key-value pair.
-    item: (Bytes, Bytes),
+    /// Stores the current key with its value type and value.
+    item: (Bytes, (ValueType, Bytes)),
+    /// The range of the iterator, used when moving backward or seeking.
+    lower: Bound<Bytes>,
+    upper: Bound<Bytes>,
 }
 
 impl TxnLocalIterator {
-    fn entry_to_item(entry: Option<Entry<'_, Bytes, Bytes>>) -> (Bytes, Bytes) {
+    fn entry_to_item(
+        entry: Option<Entry<'_, Bytes, (ValueType, Bytes)>>,
+    ) -> (Bytes, (ValueType, Bytes)) {
         entry
             .map(|x| (x.key().clone(), x.value().clone()))
-            .unwrap_or_else(|| (Bytes::new(), Bytes::new()))
+            .unwrap_or_else(|| (Bytes::new(), (ValueType::Delete, Bytes::new())))
+    }
+
+    /// Move to the entry found by `find` from the current key and the upper bound, and continue
+    /// forward iteration from there.
+    fn move_to_entry(
+        &mut self,
+        find: impl for<'a> FnOnce(
+            &'a SkipMap<Bytes, (ValueType, Bytes)>,
+            &Bytes,
+            &Bound<Bytes>,
+        ) -> Option<Entry<'a, Bytes, (ValueType, Bytes)>>,
+    ) {
+        self.with_mut(|x| {
+            let entry = find(x.map, &x.item.0, x.upper).filter(|entry| match x.lower {
+                Bound::Included(lower) => entry.key() >= lower,
+                Bound::Excluded(lower) => entry.key() > lower,
+                Bound::Unbounded => true,
+            });
+            *x.item = TxnLocalIterator::entry_to_item(entry);
+            *x.iter = x
+                .map
+                .range((Bound::Excluded(x.item.0.clone()), x.upper.clone()));
+        });
     }
 }
 
@@ -199,7 +503,11 @@ impl StorageIterator for TxnLocalIterator {
     type KeyType<'a> = &'a [u8];
 
     fn value(&self) -> &[u8] {
-        &self.borrow_item().1[..]
+        &self.borrow_item().1 .1[..]
+    }
+
+    fn value_type(&self) -> ValueType {
+        self.borrow_item().1 .0
     }
 
     fn key(&self) -> &[u8] {
@@ -215,40 +523,72 @@ impl StorageIterator for TxnLocalIterator {
         self.with_mut(|x| *x.item = entry);
         Ok(())
     }
+
+    fn prev(&mut self) -> Result<()> {
+        self.move_to_entry(|map, key, _| map.upper_bound(Bound::Excluded(key)));
+        Ok(())
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        let key = Bytes::copy_from_slice(key);
+        self.with_mut(|x| {
+            let within_lower = match x.lower {
+                Bound::Included(lower) => &key >= lower,
+                Bound::Excluded(lower) => &key > lower,
+                Bound::Unbounded => true,
+            };
+            let lower = if within_lower {
+                Bound::Included(key)
+            } else {
+                x.lower.clone()
+            };
+            *x.iter = x.map.range((lower, x.upper.clone()));
+        });
+        self.next()
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.move_to_entry(|map, _, upper| map.upper_bound(upper.as_ref()));
+        Ok(())
+    }
 }
 
 pub struct TxnIterator {
     txn: Arc<Transaction>,
+    local_storage: Arc<TxnLocalStorage>,
     iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
 }
 
 impl TxnIterator {
     pub fn create(
         txn: Arc<Transaction>,
+        cf_id: usize,
         iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
     ) -> Result<Self> {
-        let mut iter = Self { txn, iter };
-        iter.skip_deletes()?;
-        if iter.is_valid() {
-            iter.add_to_read_set(iter.key());
-        }
+        let local_storage = txn.local_storage(cf_id);
+        let mut iter = Self {
+            txn,
+            local_storage,
+            iter,
+        };
+        iter.skip_deletes(false)?;
         Ok(iter)
     }
 
-    fn skip_deletes(&mut self) -> Result<()> {
-        while self.iter.is_valid() && self.iter.value().is_empty() {
-            self.iter.next()?;
+    /// Skip the deleted keys in the direction of iteration.
+    fn skip_deletes(&mut self, backward: bool) -> Result<()> {
+        while self.iter.is_valid()
+            && (self.iter.value_type() == ValueType::Delete
+                || self.local_storage.is_range_deleted(self.iter.key()))
+        {
+            if backward {
+                self.iter.prev()?;
+            } else {
+                self.iter.next()?;
+            }
         }
         Ok(())
     }
-
-    fn add_to_read_set(&self, key: &[u8]) {
-        if let Some(guard) = &self.txn.key_hashes {
-            let mut guard = guard.lock();
-            let (_, read_set) = &mut *guard;
-            read_set.insert(farmhash::hash32(key));
-        }
-    }
 }
 
 impl StorageIterator for TxnIterator {
@@ -258,6 +598,10 @@ impl StorageIterator for TxnIterator {
         self.iter.value()
     }
 
+    fn value_type(&self) -> ValueType {
+        self.iter.value_type()
+    }
+
     fn key(&self) -> Self::KeyType<'_> {
         self.iter.key()
     }
@@ -268,11 +612,22 @@ impl StorageIterator for TxnIterator {
 
     fn next(&mut self) -> Result<()> {
         self.iter.next()?;
-        self.skip_deletes()?;
-        if self.is_valid() {
-            self.add_to_read_set(self.key());
-        }
-        Ok(())
+        self.skip_deletes(false)
+    }
+
+    fn prev(&mut self) -> Result<()> {
+        self.iter.prev()?;
+        self.skip_deletes(true)
+    }
+
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        self.iter.seek(key)?;
+        self.skip_deletes(false)
+    }
+
+    fn seek_to_last(&mut self) -> Result<()> {
+        self.iter.seek_to_last()?;
+        self.skip_deletes(true)
     }
 
     fn num_active_iterators(&self) -> usize {
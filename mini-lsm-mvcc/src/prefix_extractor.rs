@@ -0,0 +1,54 @@
+use std::fmt::Debug;
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+/// Extracts the prefix of a key, whose hash is added to the bloom filter of an SST along with the
+/// key, so that a prefix scan can skip the SSTs without the prefix.
+///
+/// If the prefix of a key `a` is `p`, the prefix of every key that starts with `a` must also be
+/// `p`. Otherwise a prefix scan may skip an SST that has keys with the scanned prefix.
+pub trait PrefixExtractor: Send + Sync + Debug {
+    /// The name of the extractor, which is stored in the SSTs. The bloom filters of an SST are only
+    /// used for prefix scans if it was built with an extractor of the same name.
+    fn name(&self) -> String;
+
+    /// The prefix of a key, or `None` if the key has no prefix, e.g. it is too short.
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]>;
+}
+
+/// Uses the first `len` bytes of a key as its prefix. Shorter keys have no prefix.
+#[derive(Debug, Clone)]
+pub struct FixedPrefixExtractor {
+    len: usize,
+}
+
+impl FixedPrefixExtractor {
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0, "prefix length must be positive");
+        Self { len }
+    }
+}
+
+impl PrefixExtractor for FixedPrefixExtractor {
+    fn name(&self) -> String {
+        format!("fixed:{}", self.len)
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        key.get(..self.len)
+    }
+}
+
+/// The upper bound of the keys that start with `prefix`, which is the prefix with its last
+/// non-0xff byte incremented, or unbounded if there is no such byte.
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Bound<Bytes> {
+    match prefix.iter().rposition(|&byte| byte != 0xff) {
+        Some(idx) => {
+            let mut upper = prefix[..=idx].to_vec();
+            upper[idx] += 1;
+            Bound::Excluded(Bytes::from(upper))
+        }
+        None => Bound::Unbounded,
+    }
+}
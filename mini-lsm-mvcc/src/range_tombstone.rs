@@ -0,0 +1,105 @@
+use std::ops::Bound;
+
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes};
+
+use crate::key::KeySlice;
+use crate::varint::LengthEncoding;
+
+/// A range tombstone deletes all versions of the keys in `[start, end)` that are older than `ts`.
+///
+/// Every range tombstone is written together with a point delete of `start` at the same ts. The
+/// point delete (the sentinel) makes sure the SST holding the tombstone always contains at least
+/// one key, and compaction attaches the tombstone to the output SST where its sentinel ends up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeTombstone {
+    pub start: Bytes,
+    pub end: Bytes,
+    pub ts: u64,
+}
+
+impl RangeTombstone {
+    pub fn new(start: Bytes, end: Bytes, ts: u64) -> Self {
+        Self { start, end, ts }
+    }
+
+    /// Check if the version of `key` at `ts` is deleted by this tombstone. Versions written in the
+    /// same batch as the tombstone (with the same ts) are not deleted.
+    pub fn covers(&self, key: &[u8], ts: u64) -> bool {
+        self.contains_key(key) && ts < self.ts
+    }
+
+    /// Check if the version `key` is deleted by any of the tombstones.
+    pub fn any_covers(range_tombstones: &[RangeTombstone], key: KeySlice) -> bool {
+        range_tombstones
+            .iter()
+            .any(|tombstone| tombstone.covers(key.key_ref(), key.ts()))
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.start.as_ref() <= key && key < self.end.as_ref()
+    }
+
+    /// Check if this tombstone overlaps with the key range `[first_key, last_key]`.
+    pub fn overlaps(&self, first_key: &[u8], last_key: &[u8]) -> bool {
+        self.start.as_ref() <= last_key && first_key < self.end.as_ref()
+    }
+
+    /// Check if this tombstone may cover a key in the range, e.g. of a get or a scan.
+    pub fn overlaps_range(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> bool {
+        let after_lower = match lower {
+            Bound::Included(lower) | Bound::Excluded(lower) => lower < self.end.as_ref(),
+            Bound::Unbounded => true,
+        };
+        let before_upper = match upper {
+            Bound::Included(upper) => self.start.as_ref() <= upper,
+            Bound::Excluded(upper) => self.start.as_ref() < upper,
+            Bound::Unbounded => true,
+        };
+        after_lower && before_upper
+    }
+
+    pub fn raw_len(&self) -> usize {
+        self.start.len() + self.end.len() + std::mem::size_of::<u64>()
+    }
+
+    /// Encode range tombstones to a buffer.
+    pub fn encode_range_tombstones(
+        range_tombstones: &[RangeTombstone],
+        lengths: LengthEncoding,
+        buf: &mut Vec<u8>,
+    ) {
+        let original_len = buf.len();
+        buf.put_u32(range_tombstones.len() as u32);
+        for tombstone in range_tombstones {
+            lengths.put(buf, tombstone.start.len());
+            buf.put_slice(&tombstone.start);
+            lengths.put(buf, tombstone.end.len());
+            buf.put_slice(&tombstone.end);
+            buf.put_u64(tombstone.ts);
+        }
+        buf.put_u32(crc32fast::hash(&buf[original_len + 4..]));
+    }
+
+    /// Decode range tombstones from a buffer.
+    pub fn decode_range_tombstones(
+        mut buf: &[u8],
+        lengths: LengthEncoding,
+    ) -> Result<Vec<RangeTombstone>> {
+        let num = buf.get_u32() as usize;
+        let checksum = crc32fast::hash(&buf[..buf.remaining() - 4]);
+        let mut range_tombstones = Vec::with_capacity(num);
+        for _ in 0..num {
+            let start_len = lengths.get(&mut buf)?;
+            let start = buf.copy_to_bytes(start_len);
+            let end_len = lengths.get(&mut buf)?;
+            let end = buf.copy_to_bytes(end_len);
+            let ts = buf.get_u64();
+            range_tombstones.push(RangeTombstone { start, end, ts });
+        }
+        if buf.get_u32() != checksum {
+            bail!("range tombstone checksum mismatched");
+        }
+        Ok(range_tombstones)
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
+
+/// The priority of a background write. Flushes go before compactions, so that a compaction does
+/// not hold up the flushes, and in turn the writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Memtable flushes
+    High,
+    /// Compactions
+    Low,
+}
+
+/// A token bucket that limits the write throughput of flushes and compactions. It can be shared
+/// by several storages to limit their total throughput.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    state: Mutex<RateLimiterState>,
+    cvar: Condvar,
+}
+
+#[derive(Debug)]
+struct RateLimiterState {
+    /// The tokens in the bucket. A request may take more tokens than available, which makes this
+    /// negative until the later requests pay them back.
+    available: i64,
+    last_refill: Instant,
+    /// The number of high priority requests that are waiting for tokens.
+    high_priority_waiters: usize,
+    total_bytes: u64,
+}
+
+impl RateLimiter {
+    /// The bucket holds at most this long of tokens, which limits the bursts after idle periods.
+    const MAX_BURST: Duration = Duration::from_millis(100);
+
+    pub fn new(bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "rate limit must be positive");
+        Self {
+            bytes_per_sec,
+            state: Mutex::new(RateLimiterState {
+                available: 0,
+                last_refill: Instant::now(),
+                high_priority_waiters: 0,
+                total_bytes: 0,
+            }),
+            cvar: Condvar::new(),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// The number of bytes that went through the limiter so far.
+    pub fn total_bytes(&self) -> u64 {
+        self.state.lock().total_bytes
+    }
+
+    fn refill(&self, state: &mut RateLimiterState) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.last_refill);
+        let max_burst = (self.bytes_per_sec as f64 * Self::MAX_BURST.as_secs_f64()).max(1.0);
+        let tokens = self.bytes_per_sec as f64 * elapsed.as_secs_f64();
+        state.available = (state.available as f64 + tokens).min(max_burst) as i64;
+        state.last_refill = now;
+    }
+
+    /// Block until `bytes` can be written. A low priority request waits while a high priority
+    /// request is waiting.
+    pub fn request(&self, bytes: usize, priority: IoPriority) {
+        let mut state = self.state.lock();
+        if priority == IoPriority::High {
+            state.high_priority_waiters += 1;
+        }
+        loop {
+            self.refill(&mut state);
+            let blocked_by_high_priority =
+                priority == IoPriority::Low && state.high_priority_waiters > 0;
+            if state.available > 0 && !blocked_by_high_priority {
+                break;
+            }
+            let deficit = (1 - state.available.min(0)) as f64;
+            let wait = Duration::from_secs_f64(deficit / self.bytes_per_sec as f64)
+                .clamp(Duration::from_millis(1), Self::MAX_BURST);
+            self.cvar.wait_for(&mut state, wait);
+        }
+        state.available -= bytes as i64;
+        state.total_bytes += bytes as u64;
+        if priority == IoPriority::High {
+            state.high_priority_waiters -= 1;
+            // wake up the low priority requests that were waiting for this one
+            self.cvar.notify_all();
+        }
+    }
+}
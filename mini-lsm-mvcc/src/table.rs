@@ -1,19 +1,25 @@
 pub(crate) mod bloom;
 mod builder;
 mod iterator;
+mod xor_filter;
 
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::sync::Arc;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 pub use builder::SsTableBuilder;
 use bytes::{Buf, BufMut};
 pub use iterator::SsTableIterator;
 
-use crate::block::Block;
+use crate::block::{Block, BlockFormat};
+use crate::block_cache::BlockCache;
+use crate::compression::CompressionType;
 use crate::key::{KeyBytes, KeySlice};
-use crate::lsm_storage::BlockCache;
+use crate::range_tombstone::RangeTombstone;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::varint::LengthEncoding;
 
 use self::bloom::Bloom;
 
@@ -29,17 +35,22 @@ pub struct BlockMeta {
 
 impl BlockMeta {
     /// Encode block meta to a buffer.
-    pub fn encode_block_meta(block_meta: &[BlockMeta], max_ts: u64, buf: &mut Vec<u8>) {
+    pub fn encode_block_meta(
+        block_meta: &[BlockMeta],
+        max_ts: u64,
+        lengths: LengthEncoding,
+        buf: &mut Vec<u8>,
+    ) {
         let mut estimated_size = std::mem::size_of::<u32>(); // number of blocks
         for meta in block_meta {
             // The size of offset
             estimated_size += std::mem::size_of::<u32>();
             // The size of key length
-            estimated_size += std::mem::size_of::<u16>();
+            estimated_size += lengths.encoded_len(meta.first_key.key_len());
             // The size of actual key
             estimated_size += meta.first_key.raw_len();
             // The size of key length
-            estimated_size += std::mem::size_of::<u16>();
+            estimated_size += lengths.encoded_len(meta.last_key.key_len());
             // The size of actual key
             estimated_size += meta.last_key.raw_len();
         }
@@ -53,12 +64,8 @@ impl BlockMeta {
         buf.put_u32(block_meta.len() as u32);
         for meta in block_meta {
             buf.put_u32(meta.offset as u32);
-            buf.put_u16(meta.first_key.key_len() as u16);
-            buf.put_slice(meta.first_key.key_ref());
-            buf.put_u64(meta.first_key.ts());
-            buf.put_u16(meta.last_key.key_len() as u16);
-            buf.put_slice(meta.last_key.key_ref());
-            buf.put_u64(meta.last_key.ts());
+            put_key(buf, &meta.first_key, lengths);
+            put_key(buf, &meta.last_key, lengths);
         }
         buf.put_u64(max_ts);
         buf.put_u32(crc32fast::hash(&buf[original_len + 4..]));
@@ -66,18 +73,17 @@ impl BlockMeta {
     }
 
     /// Decode block meta from a buffer.
-    pub fn decode_block_meta(mut buf: &[u8]) -> Result<(Vec<BlockMeta>, u64)> {
+    pub fn decode_block_meta(
+        mut buf: &[u8],
+        lengths: LengthEncoding,
+    ) -> Result<(Vec<BlockMeta>, u64)> {
         let mut block_meta = Vec::new();
         let num = buf.get_u32() as usize;
         let checksum = crc32fast::hash(&buf[..buf.remaining() - 4]);
         for _ in 0..num {
             let offset = buf.get_u32() as usize;
-            let first_key_len = buf.get_u16() as usize;
-            let first_key =
-                KeyBytes::from_bytes_with_ts(buf.copy_to_bytes(first_key_len), buf.get_u64());
-            let last_key_len: usize = buf.get_u16() as usize;
-            let last_key =
-                KeyBytes::from_bytes_with_ts(buf.copy_to_bytes(last_key_len), buf.get_u64());
+            let first_key = get_key(&mut buf, lengths)?;
+            let last_key = get_key(&mut buf, lengths)?;
             block_meta.push(BlockMeta {
                 offset,
                 first_key,
@@ -93,6 +99,181 @@ impl BlockMeta {
     }
 }
 
+/// The meta of the data blocks covered by an index partition, which is loaded lazily through the
+/// block cache.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexPartition {
+    pub block_meta: Vec<BlockMeta>,
+    /// The end offset of the last data block.
+    pub data_end: u64,
+}
+
+impl IndexPartition {
+    /// Encode an index partition to a buffer. Unlike `BlockMeta::encode_block_meta`, the offsets
+    /// are 64-bit, so that an SST with a partitioned index is not capped at 4 GiB.
+    pub fn encode_index_partition(
+        block_meta: &[BlockMeta],
+        data_end: u64,
+        lengths: LengthEncoding,
+        buf: &mut Vec<u8>,
+    ) {
+        let original_len = buf.len();
+        buf.put_u32(block_meta.len() as u32);
+        for meta in block_meta {
+            buf.put_u64(meta.offset as u64);
+            put_key(buf, &meta.first_key, lengths);
+            put_key(buf, &meta.last_key, lengths);
+        }
+        buf.put_u64(data_end);
+        buf.put_u32(crc32fast::hash(&buf[original_len + 4..]));
+    }
+
+    /// Decode an index partition from a buffer.
+    pub fn decode_index_partition(mut buf: &[u8], lengths: LengthEncoding) -> Result<Self> {
+        let num = buf.get_u32() as usize;
+        let checksum = crc32fast::hash(&buf[..buf.remaining() - 4]);
+        let mut block_meta = Vec::with_capacity(num);
+        for _ in 0..num {
+            let offset = buf.get_u64() as usize;
+            let first_key = get_key(&mut buf, lengths)?;
+            let last_key = get_key(&mut buf, lengths)?;
+            block_meta.push(BlockMeta {
+                offset,
+                first_key,
+                last_key,
+            });
+        }
+        let data_end = buf.get_u64();
+        if buf.get_u32() != checksum {
+            bail!("index partition checksum mismatched");
+        }
+        Ok(Self {
+            block_meta,
+            data_end,
+        })
+    }
+
+    /// The size of the index partition in memory.
+    pub fn size(&self) -> usize {
+        self.block_meta
+            .iter()
+            .map(|meta| {
+                std::mem::size_of::<BlockMeta>()
+                    + meta.first_key.raw_len()
+                    + meta.last_key.raw_len()
+            })
+            .sum()
+    }
+}
+
+/// An entry of the top-level index of a partitioned index, which is always held in memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexPartitionMeta {
+    /// Offset of this index partition.
+    pub offset: u64,
+    /// The index of the first data block covered by this partition.
+    pub first_block_idx: usize,
+    /// The number of data blocks covered by this partition.
+    pub num_blocks: usize,
+    /// The first key of the first data block.
+    pub first_key: KeyBytes,
+    /// The last key of the last data block.
+    pub last_key: KeyBytes,
+}
+
+impl IndexPartitionMeta {
+    /// Encode the top-level index to a buffer.
+    pub fn encode_index(
+        partitions: &[IndexPartitionMeta],
+        max_ts: u64,
+        lengths: LengthEncoding,
+        buf: &mut Vec<u8>,
+    ) {
+        let original_len = buf.len();
+        buf.put_u32(partitions.len() as u32);
+        for partition in partitions {
+            buf.put_u64(partition.offset);
+            buf.put_u32(partition.num_blocks as u32);
+            put_key(buf, &partition.first_key, lengths);
+            put_key(buf, &partition.last_key, lengths);
+        }
+        buf.put_u64(max_ts);
+        buf.put_u32(crc32fast::hash(&buf[original_len + 4..]));
+    }
+
+    /// Decode the top-level index from a buffer.
+    pub fn decode_index(
+        mut buf: &[u8],
+        lengths: LengthEncoding,
+    ) -> Result<(Vec<IndexPartitionMeta>, u64)> {
+        let num = buf.get_u32() as usize;
+        let checksum = crc32fast::hash(&buf[..buf.remaining() - 4]);
+        let mut partitions = Vec::with_capacity(num);
+        let mut first_block_idx = 0;
+        for _ in 0..num {
+            let offset = buf.get_u64();
+            let num_blocks = buf.get_u32() as usize;
+            let first_key = get_key(&mut buf, lengths)?;
+            let last_key = get_key(&mut buf, lengths)?;
+            partitions.push(IndexPartitionMeta {
+                offset,
+                first_block_idx,
+                num_blocks,
+                first_key,
+                last_key,
+            });
+            first_block_idx += num_blocks;
+        }
+        let max_ts = buf.get_u64();
+        if buf.get_u32() != checksum {
+            bail!("index checksum mismatched");
+        }
+        Ok((partitions, max_ts))
+    }
+}
+
+fn put_key(buf: &mut Vec<u8>, key: &KeyBytes, lengths: LengthEncoding) {
+    lengths.put(buf, key.key_len());
+    buf.put_slice(key.key_ref());
+    buf.put_u64(key.ts());
+}
+
+fn get_key(buf: &mut &[u8], lengths: LengthEncoding) -> Result<KeyBytes> {
+    let key_len = lengths.get(buf)?;
+    let key = buf.copy_to_bytes(key_len);
+    Ok(KeyBytes::from_bytes_with_ts(key, buf.get_u64()))
+}
+
+/// Marks an SST with a partitioned index, in place of the bloom filter offset of the other SSTs.
+/// The bloom filter offset of an SST is below 4 GiB - 4, so it is never `u32::MAX`.
+pub(crate) const PARTITIONED_INDEX_MAGIC: u32 = u32::MAX;
+
+/// Ends the footer of an SST that stores its format version right before it. The SSTs written
+/// before the format versions end with a bloom filter offset.
+pub(crate) const FORMAT_VERSION_MAGIC: u32 = u32::MAX - 1;
+
+/// The format of the SSTs without a format version, whose blocks do not store the value types or
+/// a compression type, and which have no range tombstones or partitioned index:
+///
+/// ```text
+/// | blocks | meta | meta_offset (u32) | bloom | bloom_offset (u32) |
+/// ```
+pub(crate) const SST_FORMAT_VERSION_UNTYPED: u32 = 1;
+
+/// The format of the SSTs written now, whose blocks store the value types, with varint lengths of
+/// the keys and values and `u32` entry offsets.
+pub(crate) const SST_FORMAT_VERSION: u32 = 2;
+
+/// The format of the blocks of an SST in the given format version, which is a supported one as
+/// `SsTable::open` rejects the others.
+pub(crate) fn block_format(format_version: u32) -> BlockFormat {
+    match format_version {
+        SST_FORMAT_VERSION_UNTYPED => BlockFormat::Untyped,
+        SST_FORMAT_VERSION => BlockFormat::Varint,
+        _ => unreachable!("unsupported SST format version {}", format_version),
+    }
+}
+
 /// A file object.
 pub struct FileObject(Option<File>, u64);
 
@@ -121,6 +302,35 @@ impl FileObject {
         ))
     }
 
+    /// Like `create`, but write the file in chunks at the pace of the rate limiter, and sync it
+    /// every `BYTES_PER_SYNC` bytes, so that the dirty pages are not written back in one burst.
+    pub fn create_rate_limited(
+        path: &Path,
+        data: Vec<u8>,
+        rate_limiter: &RateLimiter,
+        priority: IoPriority,
+    ) -> Result<Self> {
+        const CHUNK_SIZE: usize = 64 << 10;
+        const BYTES_PER_SYNC: usize = 1 << 20;
+        let mut file = File::create(path)?;
+        let mut unsynced = 0;
+        for chunk in data.chunks(CHUNK_SIZE) {
+            rate_limiter.request(chunk.len(), priority);
+            file.write_all(chunk)?;
+            unsynced += chunk.len();
+            if unsynced >= BYTES_PER_SYNC {
+                // there is no `sync_file_range` in std, so sync all data written so far
+                file.sync_data()?;
+                unsynced = 0;
+            }
+        }
+        file.sync_all()?;
+        Ok(FileObject(
+            Some(File::options().read(true).write(false).open(path)?),
+            data.len() as u64,
+        ))
+    }
+
     pub fn open(path: &Path) -> Result<Self> {
         let file = File::options().read(true).write(false).open(path)?;
         let size = file.metadata()?.len();
@@ -132,16 +342,23 @@ impl FileObject {
 pub struct SsTable {
     /// The actual storage unit of SsTable, the format is as above.
     pub(crate) file: FileObject,
-    /// The meta blocks that hold info for data blocks.
+    /// The meta blocks that hold info for data blocks, empty if the index is partitioned.
     pub(crate) block_meta: Vec<BlockMeta>,
-    /// The offset that indicates the start point of meta blocks in `file`.
+    /// The offset that indicates the start point of meta blocks in `file`, or of the top-level
+    /// index if the index is partitioned.
     pub(crate) block_meta_offset: usize,
+    /// The top-level index of a partitioned index, empty if the index is not partitioned.
+    pub(crate) index_partitions: Vec<IndexPartitionMeta>,
     id: usize,
     block_cache: Option<Arc<BlockCache>>,
     first_key: KeyBytes,
     last_key: KeyBytes,
     pub(crate) bloom: Option<Bloom>,
     max_ts: u64,
+    /// Range tombstones stored in this SST, which are not bounded by `first_key` and `last_key`.
+    pub(crate) range_tombstones: Vec<RangeTombstone>,
+    /// The format of the SST, which tells how to decode its blocks.
+    pub(crate) format_version: u32,
 }
 impl SsTable {
     #[cfg(test)]
@@ -151,25 +368,102 @@ impl SsTable {
 
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
-        let len = file.size();
+        let mut len = file.size();
+        let mut format_version = SST_FORMAT_VERSION_UNTYPED;
+        let raw_magic = file.read(len - 4, 4)?;
+        if (&raw_magic[..]).get_u32() == FORMAT_VERSION_MAGIC {
+            let raw_format_version = file.read(len - 8, 4)?;
+            format_version = (&raw_format_version[..]).get_u32();
+            if !(SST_FORMAT_VERSION_UNTYPED..=SST_FORMAT_VERSION).contains(&format_version) {
+                bail!("unsupported SST format version {}", format_version);
+            }
+            len -= 8;
+        }
         let raw_bloom_offset = file.read(len - 4, 4)?;
-        let bloom_offset = (&raw_bloom_offset[..]).get_u32() as u64;
+        let bloom_offset = (&raw_bloom_offset[..]).get_u32();
+        if bloom_offset == PARTITIONED_INDEX_MAGIC && format_version != SST_FORMAT_VERSION_UNTYPED {
+            return Self::open_partitioned(id, block_cache, file, len, format_version);
+        }
+        let bloom_offset = bloom_offset as u64;
         let raw_bloom = file.read(bloom_offset, len - 4 - bloom_offset)?;
         let bloom_filter = Bloom::decode(&raw_bloom)?;
-        let raw_meta_offset = file.read(bloom_offset - 4, 4)?;
+        let lengths = block_format(format_version).lengths();
+        // The meta ends at the range tombstones, which the SSTs without a format version do not
+        // have.
+        let (range_tombstones, meta_end) = if format_version == SST_FORMAT_VERSION_UNTYPED {
+            (Vec::new(), bloom_offset)
+        } else {
+            let raw_range_tombstone_offset = file.read(bloom_offset - 4, 4)?;
+            let range_tombstone_offset = (&raw_range_tombstone_offset[..]).get_u32() as u64;
+            let raw_range_tombstones = file.read(
+                range_tombstone_offset,
+                bloom_offset - 4 - range_tombstone_offset,
+            )?;
+            (
+                RangeTombstone::decode_range_tombstones(&raw_range_tombstones, lengths)?,
+                range_tombstone_offset,
+            )
+        };
+        let raw_meta_offset = file.read(meta_end - 4, 4)?;
         let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
-        let raw_meta = file.read(block_meta_offset, bloom_offset - 4 - block_meta_offset)?;
-        let (block_meta, max_ts) = BlockMeta::decode_block_meta(&raw_meta[..])?;
+        let raw_meta = file.read(block_meta_offset, meta_end - 4 - block_meta_offset)?;
+        let (block_meta, max_ts) = BlockMeta::decode_block_meta(&raw_meta[..], lengths)?;
         Ok(Self {
             file,
             first_key: block_meta.first().unwrap().first_key.clone(),
             last_key: block_meta.last().unwrap().last_key.clone(),
             block_meta,
             block_meta_offset: block_meta_offset as usize,
+            index_partitions: Vec::new(),
+            id,
+            block_cache,
+            bloom: Some(bloom_filter),
+            max_ts,
+            range_tombstones,
+            format_version,
+        })
+    }
+
+    /// Open an SSTable with a partitioned index, whose footer holds the 64-bit offsets of the
+    /// top-level index, the range tombstones and the bloom filter. The footer ends at `len`,
+    /// before the format version.
+    fn open_partitioned(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        len: u64,
+        format_version: u32,
+    ) -> Result<Self> {
+        let footer_offset = len - 4 - 3 * 8;
+        let raw_footer = file.read(footer_offset, 3 * 8)?;
+        let mut footer = &raw_footer[..];
+        let index_offset = footer.get_u64();
+        let range_tombstone_offset = footer.get_u64();
+        let bloom_offset = footer.get_u64();
+        let raw_bloom = file.read(bloom_offset, footer_offset - bloom_offset)?;
+        let bloom_filter = Bloom::decode(&raw_bloom)?;
+        let raw_range_tombstones = file.read(
+            range_tombstone_offset,
+            bloom_offset - range_tombstone_offset,
+        )?;
+        let lengths = block_format(format_version).lengths();
+        let range_tombstones =
+            RangeTombstone::decode_range_tombstones(&raw_range_tombstones, lengths)?;
+        let raw_index = file.read(index_offset, range_tombstone_offset - index_offset)?;
+        let (index_partitions, max_ts) = IndexPartitionMeta::decode_index(&raw_index, lengths)?;
+        Ok(Self {
+            file,
+            first_key: index_partitions.first().unwrap().first_key.clone(),
+            last_key: index_partitions.last().unwrap().last_key.clone(),
+            block_meta: Vec::new(),
+            block_meta_offset: index_offset as usize,
+            index_partitions,
             id,
             block_cache,
             bloom: Some(bloom_filter),
             max_ts,
+            range_tombstones,
+            format_version,
         })
     }
 
@@ -184,56 +478,152 @@ impl SsTable {
             file: FileObject(None, file_size),
             block_meta: vec![],
             block_meta_offset: 0,
+            index_partitions: Vec::new(),
             id,
             block_cache: None,
             first_key,
             last_key,
             bloom: None,
             max_ts: 0,
+            range_tombstones: Vec::new(),
+            format_version: SST_FORMAT_VERSION,
         }
     }
 
-    /// Read a block from the disk.
-    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self.block_meta[block_idx].offset;
+    /// Read an index partition from the disk.
+    pub fn read_index_partition(&self, partition_idx: usize) -> Result<Arc<IndexPartition>> {
+        let offset = self.index_partitions[partition_idx].offset;
         let offset_end = self
+            .index_partitions
+            .get(partition_idx + 1)
+            .map_or(self.block_meta_offset as u64, |x| x.offset);
+        let raw_partition = self.file.read(offset, offset_end - offset)?;
+        Ok(Arc::new(IndexPartition::decode_index_partition(
+            &raw_partition,
+            block_format(self.format_version).lengths(),
+        )?))
+    }
+
+    /// Read an index partition from disk, with block cache.
+    pub fn read_index_partition_cached(&self, partition_idx: usize) -> Result<Arc<IndexPartition>> {
+        if let Some(ref block_cache) = self.block_cache {
+            block_cache.try_get_index_partition_with(self.id, partition_idx, || {
+                self.read_index_partition(partition_idx)
+            })
+        } else {
+            self.read_index_partition(partition_idx)
+        }
+    }
+
+    /// The start and end offset of a data block.
+    fn block_range(&self, block_idx: usize) -> Result<(usize, usize)> {
+        if self.index_partitions.is_empty() {
+            let offset = self.block_meta[block_idx].offset;
+            let offset_end = self
+                .block_meta
+                .get(block_idx + 1)
+                .map_or(self.block_meta_offset, |x| x.offset);
+            return Ok((offset, offset_end));
+        }
+        let partition_idx = self
+            .index_partitions
+            .partition_point(|partition| partition.first_block_idx <= block_idx)
+            - 1;
+        let partition = self.read_index_partition_cached(partition_idx)?;
+        let idx = block_idx - self.index_partitions[partition_idx].first_block_idx;
+        let offset = partition.block_meta[idx].offset;
+        let offset_end = partition
             .block_meta
-            .get(block_idx + 1)
-            .map_or(self.block_meta_offset, |x| x.offset);
-        let block_len = offset_end - offset - 4;
+            .get(idx + 1)
+            .map_or(partition.data_end as usize, |x| x.offset);
+        Ok((offset, offset_end))
+    }
+
+    /// Read a block from the disk.
+    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
+        let (offset, offset_end) = self.block_range(block_idx)?;
+        let checksum_offset = offset_end - offset - 4;
         let block_data_with_chksum: Vec<u8> = self
             .file
             .read(offset as u64, (offset_end - offset) as u64)?;
-        let block_data = &block_data_with_chksum[..block_len];
-        let checksum = (&block_data_with_chksum[block_len..]).get_u32();
-        if checksum != crc32fast::hash(block_data) {
+        let checksum = (&block_data_with_chksum[checksum_offset..]).get_u32();
+        if checksum != crc32fast::hash(&block_data_with_chksum[..checksum_offset]) {
             bail!("block checksum mismatched");
         }
-        Ok(Arc::new(Block::decode(block_data)))
+        // The blocks of the SSTs without a format version have no compression type.
+        let (block_data, compression) = if self.format_version == SST_FORMAT_VERSION_UNTYPED {
+            (
+                &block_data_with_chksum[..checksum_offset],
+                CompressionType::None,
+            )
+        } else {
+            let block_len = checksum_offset - 1;
+            (
+                &block_data_with_chksum[..block_len],
+                CompressionType::from_byte(block_data_with_chksum[block_len])?,
+            )
+        };
+        let format = block_format(self.format_version);
+        if compression == CompressionType::None {
+            return Ok(Arc::new(Block::decode_with_format(block_data, format)?));
+        }
+        Ok(Arc::new(Block::decode_with_format(
+            &compression.decompress(block_data)?,
+            format,
+        )?))
     }
 
     /// Read a block from disk, with block cache.
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
         if let Some(ref block_cache) = self.block_cache {
-            let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
-            Ok(blk)
+            block_cache.try_get_with(self.id, block_idx, || self.read_block(block_idx))
         } else {
             self.read_block(block_idx)
         }
     }
 
-    /// Find the block that may contain `key`.
-    pub fn find_block_idx(&self, key: KeySlice) -> usize {
-        self.block_meta
+    /// Find the block that may contain `key`. This reads an index partition if the index is
+    /// partitioned.
+    pub fn find_block_idx(&self, key: KeySlice) -> Result<usize> {
+        if self.index_partitions.is_empty() {
+            return Ok(self
+                .block_meta
+                .partition_point(|meta| meta.first_key.as_key_slice() <= key)
+                .saturating_sub(1));
+        }
+        let partition_idx = self
+            .index_partitions
+            .partition_point(|partition| partition.first_key.as_key_slice() <= key)
+            .saturating_sub(1);
+        let partition = self.read_index_partition_cached(partition_idx)?;
+        let idx = partition
+            .block_meta
             .partition_point(|meta| meta.first_key.as_key_slice() <= key)
-            .saturating_sub(1)
+            .saturating_sub(1);
+        Ok(self.index_partitions[partition_idx].first_block_idx + idx)
     }
 
     /// Get number of data blocks.
     pub fn num_of_blocks(&self) -> usize {
-        self.block_meta.len()
+        match self.index_partitions.last() {
+            Some(partition) => partition.first_block_idx + partition.num_blocks,
+            None => self.block_meta.len(),
+        }
+    }
+
+    /// Get number of index partitions, 0 if the index is not partitioned.
+    pub fn num_of_index_partitions(&self) -> usize {
+        self.index_partitions.len()
+    }
+
+    /// The first keys of the data blocks, or of the index partitions if the index is partitioned,
+    /// which split the SST into ranges of about the same size without reading it.
+    pub(crate) fn index_first_keys(&self) -> impl Iterator<Item = &KeyBytes> {
+        self.block_meta.iter().map(|meta| &meta.first_key).chain(
+            self.index_partitions
+                .iter()
+                .map(|partition| &partition.first_key),
+        )
     }
 
     pub fn first_key(&self) -> &KeyBytes {
@@ -255,4 +645,8 @@ impl SsTable {
     pub fn max_ts(&self) -> u64 {
         self.max_ts
     }
+
+    pub fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
 }
@@ -3,12 +3,38 @@
 use anyhow::{bail, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-/// Implements a bloom filter
+use super::xor_filter;
+use crate::filter::FilterType;
+
+/// Implements a bloom filter, or one of the other filters in `FilterType`
 pub struct Bloom {
     /// data of filter in bits
     pub(crate) filter: Bytes,
     /// number of hash functions
     pub(crate) k: u8,
+    /// type of the filter, the bits per key are not known if decoded
+    pub(crate) filter_type: FilterType,
+    /// name of the prefix extractor whose prefixes are in the filter
+    pub(crate) prefix_extractor: Option<String>,
+}
+
+/// Set on the byte of `k` if the name of the prefix extractor is stored before it. Older readers
+/// take such a filter as one with more than 30 hash functions, and do not use it.
+const PREFIX_EXTRACTOR_FLAG: u8 = 0x80;
+/// Set on the byte of `k` if the filter type is stored before it, which is left out for the
+/// standard bloom filter.
+const FILTER_TYPE_FLAG: u8 = 0x40;
+/// The bits of a blocked bloom filter block, which is a cache line.
+const BLOCK_BITS: usize = 512;
+
+/// Mix the bits of a hash, see the finalizer of MurmurHash3.
+pub(crate) fn mix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^= h >> 33;
+    h
 }
 
 pub trait BitSlice {
@@ -51,11 +77,28 @@ impl Bloom {
         if checksum != crc32fast::hash(&buf[..buf.len() - 4]) {
             bail!("checksum mismatched for bloom filters");
         }
-        let filter = &buf[..buf.len() - 5];
-        let k = buf[buf.len() - 5];
+        let mut end = buf.len() - 5;
+        let k = buf[end];
+        let filter_type = if k & FILTER_TYPE_FLAG != 0 {
+            end -= 1;
+            FilterType::from_byte(buf[end])?
+        } else {
+            FilterType::Bloom(0)
+        };
+        let prefix_extractor = if k & PREFIX_EXTRACTOR_FLAG != 0 {
+            end -= 1;
+            let name_begin = end - buf[end] as usize;
+            let name = String::from_utf8(buf[name_begin..end].to_vec())?;
+            end = name_begin;
+            Some(name)
+        } else {
+            None
+        };
         Ok(Self {
-            filter: filter.to_vec().into(),
-            k,
+            filter: buf[..end].to_vec().into(),
+            k: k & !(PREFIX_EXTRACTOR_FLAG | FILTER_TYPE_FLAG),
+            filter_type,
+            prefix_extractor,
         })
     }
 
@@ -63,7 +106,17 @@ impl Bloom {
     pub fn encode(&self, buf: &mut Vec<u8>) {
         let offset = buf.len();
         buf.extend(&self.filter);
-        buf.put_u8(self.k);
+        let mut k = self.k;
+        if let Some(prefix_extractor) = &self.prefix_extractor {
+            buf.extend(prefix_extractor.as_bytes());
+            buf.put_u8(prefix_extractor.len() as u8);
+            k |= PREFIX_EXTRACTOR_FLAG;
+        }
+        if !matches!(self.filter_type, FilterType::Bloom(_)) {
+            buf.put_u8(self.filter_type.to_byte());
+            k |= FILTER_TYPE_FLAG;
+        }
+        buf.put_u8(k);
         let checksum = crc32fast::hash(&buf[offset..]);
         buf.put_u32(checksum);
     }
@@ -76,6 +129,20 @@ impl Bloom {
         locs as usize
     }
 
+    /// Build a filter of the given type from key hashes
+    pub fn build(keys: &[u32], filter_type: FilterType) -> Self {
+        match filter_type {
+            FilterType::Bloom(bits_per_key) => Self::build_from_key_hashes(keys, bits_per_key),
+            FilterType::BlockedBloom(bits_per_key) => Self::build_blocked(keys, bits_per_key),
+            FilterType::Xor8 => Self {
+                filter: xor_filter::build(keys).into(),
+                k: 3,
+                filter_type,
+                prefix_extractor: None,
+            },
+        }
+    }
+
     /// Build bloom filter from key hashes
     pub fn build_from_key_hashes(keys: &[u32], bits_per_key: usize) -> Self {
         let k = (bits_per_key as f64 * 0.69) as u32;
@@ -97,11 +164,62 @@ impl Bloom {
         Self {
             filter: filter.freeze(),
             k: k as u8,
+            filter_type: FilterType::Bloom(bits_per_key),
+            prefix_extractor: None,
+        }
+    }
+
+    /// The block of a key in a blocked bloom filter, and the first bit and the delta of its probes
+    /// in the block. The hash is mixed, so that the probes do not depend on the block.
+    fn blocked_probes(h: u32, num_blocks: usize) -> (usize, u32, u32) {
+        let h = mix64(h as u64);
+        let block = (((h >> 32) * num_blocks as u64) >> 32) as usize;
+        let h = h as u32;
+        (block, h, h.rotate_right(17))
+    }
+
+    /// Build a blocked bloom filter from key hashes
+    fn build_blocked(keys: &[u32], bits_per_key: usize) -> Self {
+        let k = (bits_per_key as f64 * 0.69) as u32;
+        let k = k.clamp(1, 30);
+        let num_blocks = (keys.len() * bits_per_key).div_ceil(BLOCK_BITS).max(1);
+        let nbytes = num_blocks * BLOCK_BITS / 8;
+        let mut filter = BytesMut::with_capacity(nbytes);
+        filter.resize(nbytes, 0);
+        for h in keys {
+            let (block, mut h, delta) = Self::blocked_probes(*h, num_blocks);
+            for _ in 0..k {
+                let bit_pos = block * BLOCK_BITS + (h as usize) % BLOCK_BITS;
+                filter.set_bit(bit_pos, true);
+                h = h.wrapping_add(delta);
+            }
+        }
+        Self {
+            filter: filter.freeze(),
+            k: k as u8,
+            filter_type: FilterType::BlockedBloom(bits_per_key),
+            prefix_extractor: None,
         }
     }
 
     /// Check if a bloom filter may contain some data
     pub fn may_contain(&self, mut h: u32) -> bool {
+        match self.filter_type {
+            FilterType::Bloom(_) => {}
+            FilterType::BlockedBloom(_) => {
+                let num_blocks = self.filter.bit_len() / BLOCK_BITS;
+                let (block, mut h, delta) = Self::blocked_probes(h, num_blocks);
+                for _ in 0..self.k {
+                    let bit_pos = block * BLOCK_BITS + (h as usize) % BLOCK_BITS;
+                    if !self.filter.get_bit(bit_pos) {
+                        return false;
+                    }
+                    h = h.wrapping_add(delta);
+                }
+                return true;
+            }
+            FilterType::Xor8 => return xor_filter::may_contain(&self.filter, h),
+        }
         if self.k > 30 {
             // potential new encoding for short bloom filters
             true
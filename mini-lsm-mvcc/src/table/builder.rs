@@ -5,10 +5,20 @@ use anyhow::Result;
 use bytes::BufMut;
 
 use super::bloom::Bloom;
-use super::{BlockMeta, FileObject, SsTable};
+use super::{
+    block_format, BlockMeta, FileObject, IndexPartition, IndexPartitionMeta, SsTable,
+    FORMAT_VERSION_MAGIC, PARTITIONED_INDEX_MAGIC, SST_FORMAT_VERSION, SST_FORMAT_VERSION_UNTYPED,
+};
 use crate::block::BlockBuilder;
+use crate::block_cache::BlockCache;
+use crate::compression::CompressionType;
+use crate::filter::FilterType;
 use crate::key::{KeySlice, KeyVec};
-use crate::lsm_storage::BlockCache;
+use crate::prefix_extractor::PrefixExtractor;
+use crate::range_tombstone::RangeTombstone;
+use crate::rate_limiter::{IoPriority, RateLimiter};
+use crate::value_type::ValueType;
+use crate::varint::LengthEncoding;
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
@@ -20,11 +30,24 @@ pub struct SsTableBuilder {
     block_size: usize,
     key_hashes: Vec<u32>,
     max_ts: u64,
+    range_tombstones: Vec<RangeTombstone>,
+    compression: CompressionType,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+    index_partition_size: Option<usize>,
+    prefix_extractor: Option<Arc<dyn PrefixExtractor>>,
+    last_prefix_hash: Option<u32>,
+    filter_type: FilterType,
+    format_version: u32,
 }
 
 impl SsTableBuilder {
     /// Create a builder based on target block size.
     pub fn new(block_size: usize) -> Self {
+        Self::new_with_compression(block_size, CompressionType::None)
+    }
+
+    /// Create a builder that compresses the data blocks.
+    pub fn new_with_compression(block_size: usize, compression: CompressionType) -> Self {
         Self {
             data: Vec::new(),
             meta: Vec::new(),
@@ -34,11 +57,60 @@ impl SsTableBuilder {
             builder: BlockBuilder::new(block_size),
             key_hashes: Vec::new(),
             max_ts: 0,
+            range_tombstones: Vec::new(),
+            compression,
+            rate_limiter: None,
+            index_partition_size: None,
+            prefix_extractor: None,
+            last_prefix_hash: None,
+            filter_type: FilterType::default(),
+            format_version: SST_FORMAT_VERSION,
         }
     }
 
-    /// Adds a key-value pair to SSTable
+    /// Write the SST in an older format version, to test that such SSTs are still readable.
+    #[cfg(test)]
+    pub(crate) fn for_testing_format_version(&mut self, format_version: u32) {
+        self.format_version = format_version;
+        self.builder = self.new_block_builder();
+    }
+
+    fn new_block_builder(&self) -> BlockBuilder {
+        BlockBuilder::new_with_format(self.block_size, block_format(self.format_version))
+    }
+
+    /// Write the SST file at the pace of the rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>, priority: IoPriority) {
+        self.rate_limiter = Some((rate_limiter, priority));
+    }
+
+    /// Split the index into partitions of about `partition_size` bytes, which are loaded lazily
+    /// instead of being held in memory.
+    pub fn set_index_partition_size(&mut self, partition_size: usize) {
+        self.index_partition_size = Some(partition_size);
+    }
+
+    /// Use another filter than the default bloom filter.
+    pub fn set_filter_type(&mut self, filter_type: FilterType) {
+        self.filter_type = filter_type;
+    }
+
+    /// Add the prefixes of the keys to the bloom filter, along with the keys.
+    pub fn set_prefix_extractor(&mut self, prefix_extractor: Arc<dyn PrefixExtractor>) {
+        assert!(
+            prefix_extractor.name().len() <= u8::MAX as usize,
+            "prefix extractor name too long"
+        );
+        self.prefix_extractor = Some(prefix_extractor);
+    }
+
+    /// Adds a key-value pair to SSTable. An empty value is added as a delete tombstone.
     pub fn add(&mut self, key: KeySlice, value: &[u8]) {
+        self.add_with_type(key, ValueType::of_untyped(value), value);
+    }
+
+    /// Adds a record of a key to SSTable.
+    pub fn add_with_type(&mut self, key: KeySlice, value_type: ValueType, value: &[u8]) {
         if self.first_key.is_empty() {
             self.first_key.set_from_slice(key);
         }
@@ -47,8 +119,33 @@ impl SsTableBuilder {
             self.max_ts = key.ts();
         }
         self.key_hashes.push(farmhash::fingerprint32(key.key_ref()));
+        if let Some(prefix) = self
+            .prefix_extractor
+            .as_ref()
+            .and_then(|extractor| extractor.prefix(key.key_ref()))
+        {
+            // the keys are sorted, so the keys with the same prefix are next to each other
+            let prefix_hash = farmhash::fingerprint32(prefix);
+            if self.last_prefix_hash != Some(prefix_hash) {
+                self.key_hashes.push(prefix_hash);
+                self.last_prefix_hash = Some(prefix_hash);
+            }
+        }
 
-        if self.builder.add(key, value) {
+        if key.raw_len() + value.len() > self.block_size {
+            // An entry larger than a block gets a block of its own, so that it does not grow the
+            // block of the entries around it.
+            if !self.builder.is_empty() {
+                self.finish_block();
+            }
+            self.first_key.set_from_slice(key);
+            self.last_key.set_from_slice(key);
+            assert!(self.builder.add_with_type(key, value_type, value));
+            self.finish_block();
+            return;
+        }
+
+        if self.builder.add_with_type(key, value_type, value) {
             self.last_key.set_from_slice(key);
             return;
         }
@@ -57,29 +154,89 @@ impl SsTableBuilder {
         self.finish_block();
 
         // add the key-value pair to the next block
-        assert!(self.builder.add(key, value));
+        assert!(self.builder.add_with_type(key, value_type, value));
         self.first_key.set_from_slice(key);
         self.last_key.set_from_slice(key);
     }
 
+    /// Adds a range tombstone to SSTable. Its sentinel key should be added to the same SSTable.
+    pub fn add_range_tombstone(&mut self, tombstone: RangeTombstone) {
+        if tombstone.ts > self.max_ts {
+            self.max_ts = tombstone.ts;
+        }
+        self.range_tombstones.push(tombstone);
+    }
+
     /// Get the estimated size of the SSTable.
     pub fn estimated_size(&self) -> usize {
         self.data.len()
     }
 
     fn finish_block(&mut self) {
-        let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
+        let new_builder = self.new_block_builder();
+        let builder = std::mem::replace(&mut self.builder, new_builder);
         let encoded_block = builder.build().encode();
         self.meta.push(BlockMeta {
             offset: self.data.len(),
             first_key: std::mem::take(&mut self.first_key).into_key_bytes(),
             last_key: std::mem::take(&mut self.last_key).into_key_bytes(),
         });
-        let checksum = crc32fast::hash(&encoded_block);
-        self.data.extend(encoded_block);
+        let block_offset = self.data.len();
+        if self.format_version == SST_FORMAT_VERSION_UNTYPED {
+            // The blocks of the SSTs without a format version have no compression type.
+            self.data.extend(encoded_block);
+            let checksum = crc32fast::hash(&self.data[block_offset..]);
+            self.data.put_u32(checksum);
+            return;
+        }
+        // Store the block uncompressed if compression does not make it smaller.
+        match self.compression.compress(&encoded_block) {
+            Ok(compressed) if compressed.len() < encoded_block.len() => {
+                self.data.extend(compressed);
+                self.data.put_u8(self.compression.to_byte());
+            }
+            _ => {
+                self.data.extend(encoded_block);
+                self.data.put_u8(CompressionType::None.to_byte());
+            }
+        }
+        let checksum = crc32fast::hash(&self.data[block_offset..]);
         self.data.put_u32(checksum);
     }
 
+    /// Write the index partitions after the data blocks, and return the top-level index.
+    fn build_index_partitions(
+        block_meta: &[BlockMeta],
+        partition_size: usize,
+        lengths: LengthEncoding,
+        buf: &mut Vec<u8>,
+    ) -> Vec<IndexPartitionMeta> {
+        let data_end = buf.len();
+        let mut partitions = Vec::new();
+        let mut partition_start = 0;
+        let mut estimated_size = 0;
+        for (idx, meta) in block_meta.iter().enumerate() {
+            // the keys with their timestamps, the key lengths and the offset
+            estimated_size += meta.first_key.raw_len() + meta.last_key.raw_len() + 12;
+            if estimated_size < partition_size && idx + 1 < block_meta.len() {
+                continue;
+            }
+            let metas = &block_meta[partition_start..=idx];
+            let data_end = block_meta.get(idx + 1).map_or(data_end, |x| x.offset);
+            partitions.push(IndexPartitionMeta {
+                offset: buf.len() as u64,
+                first_block_idx: partition_start,
+                num_blocks: metas.len(),
+                first_key: metas.first().unwrap().first_key.clone(),
+                last_key: metas.last().unwrap().last_key.clone(),
+            });
+            IndexPartition::encode_index_partition(metas, data_end as u64, lengths, buf);
+            partition_start = idx + 1;
+            estimated_size = 0;
+        }
+        partitions
+    }
+
     /// Builds the SSTable and writes it to the given path. Use the `FileObject` structure to manipulate the disk objects.
     pub fn build(
         mut self,
@@ -87,29 +244,81 @@ impl SsTableBuilder {
         block_cache: Option<Arc<BlockCache>>,
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
-        self.finish_block();
-        let mut buf = self.data;
-        let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, self.max_ts, &mut buf);
-        buf.put_u32(meta_offset as u32);
-        let bloom = Bloom::build_from_key_hashes(
-            &self.key_hashes,
-            Bloom::bloom_bits_per_key(self.key_hashes.len(), 0.01),
+        if !self.builder.is_empty() {
+            self.finish_block();
+        }
+        let untyped = self.format_version == SST_FORMAT_VERSION_UNTYPED;
+        assert!(
+            !untyped || (self.range_tombstones.is_empty() && self.index_partition_size.is_none()),
+            "the SSTs without a format version have no range tombstones or partitioned index"
         );
-        let bloom_offset = buf.len();
-        bloom.encode(&mut buf);
-        buf.put_u32(bloom_offset as u32);
-        let file = FileObject::create(path.as_ref(), buf)?;
+        let lengths = block_format(self.format_version).lengths();
+        let mut buf = self.data;
+        let mut bloom = Bloom::build(&self.key_hashes, self.filter_type);
+        bloom.prefix_extractor = self
+            .prefix_extractor
+            .as_ref()
+            .map(|extractor| extractor.name());
+        let first_key = self.meta.first().unwrap().first_key.clone();
+        let last_key = self.meta.last().unwrap().last_key.clone();
+        let (block_meta, meta_offset, index_partitions) = match self.index_partition_size {
+            Some(partition_size) => {
+                let partitions =
+                    Self::build_index_partitions(&self.meta, partition_size, lengths, &mut buf);
+                let index_offset = buf.len();
+                IndexPartitionMeta::encode_index(&partitions, self.max_ts, lengths, &mut buf);
+                let range_tombstone_offset = buf.len();
+                RangeTombstone::encode_range_tombstones(&self.range_tombstones, lengths, &mut buf);
+                let bloom_offset = buf.len();
+                bloom.encode(&mut buf);
+                buf.put_u64(index_offset as u64);
+                buf.put_u64(range_tombstone_offset as u64);
+                buf.put_u64(bloom_offset as u64);
+                buf.put_u32(PARTITIONED_INDEX_MAGIC);
+                (Vec::new(), index_offset, partitions)
+            }
+            None => {
+                let meta_offset = buf.len();
+                BlockMeta::encode_block_meta(&self.meta, self.max_ts, lengths, &mut buf);
+                buf.put_u32(meta_offset as u32);
+                if !untyped {
+                    let range_tombstone_offset = buf.len();
+                    RangeTombstone::encode_range_tombstones(
+                        &self.range_tombstones,
+                        lengths,
+                        &mut buf,
+                    );
+                    buf.put_u32(range_tombstone_offset as u32);
+                }
+                let bloom_offset = buf.len();
+                bloom.encode(&mut buf);
+                buf.put_u32(bloom_offset as u32);
+                (self.meta, meta_offset, Vec::new())
+            }
+        };
+        if !untyped {
+            buf.put_u32(self.format_version);
+            buf.put_u32(FORMAT_VERSION_MAGIC);
+        }
+        let file = match &self.rate_limiter {
+            Some((rate_limiter, priority)) => {
+                FileObject::create_rate_limited(path.as_ref(), buf, rate_limiter, *priority)?
+            }
+            None => FileObject::create(path.as_ref(), buf)?,
+        };
         Ok(SsTable {
             id,
             file,
-            first_key: self.meta.first().unwrap().first_key.clone(),
-            last_key: self.meta.last().unwrap().last_key.clone(),
-            block_meta: self.meta,
+            first_key,
+            last_key,
+            block_meta,
             block_meta_offset: meta_offset,
+            index_partitions,
             block_cache,
             bloom: Some(bloom),
             max_ts: self.max_ts,
+            range_tombstones: self.range_tombstones,
+            format_version: self.format_version,
         })
     }
 
@@ -0,0 +1,94 @@
+//! An xor filter with 8-bit fingerprints, see "Xor Filters: Faster and Smaller Than Bloom and
+//! Cuckoo Filters" by Graf and Lemire. A key is hashed to one slot in each of three segments, and
+//! the filter is built so that the xor of the three slots is the fingerprint of the key.
+
+use bytes::{Buf, BufMut};
+
+use super::bloom::mix64;
+
+fn hash(key_hash: u32, seed: u64) -> u64 {
+    mix64(key_hash as u64 ^ seed)
+}
+
+fn fingerprint(h: u64) -> u8 {
+    (h ^ (h >> 32)) as u8
+}
+
+/// The slots of a key in the three segments.
+fn slots(h: u64, segment_len: usize) -> [usize; 3] {
+    let reduce = |h: u64| ((h as u32 as u64 * segment_len as u64) >> 32) as usize;
+    [
+        reduce(h),
+        reduce(h.rotate_left(21)) + segment_len,
+        reduce(h.rotate_left(42)) + 2 * segment_len,
+    ]
+}
+
+/// Peel the keys off one at a time, each with a slot that no other remaining key uses. Returns the
+/// hashes of the keys with their slots in the order they were peeled, or `None` if some keys
+/// cannot be peeled, and another seed has to be tried.
+fn peel(keys: &[u32], seed: u64, segment_len: usize) -> Option<Vec<(u64, usize)>> {
+    let num_slots = 3 * segment_len;
+    let mut xor_masks = vec![0u64; num_slots];
+    let mut counts = vec![0u32; num_slots];
+    for key in keys {
+        let h = hash(*key, seed);
+        for slot in slots(h, segment_len) {
+            xor_masks[slot] ^= h;
+            counts[slot] += 1;
+        }
+    }
+    let mut queue = (0..num_slots)
+        .filter(|slot| counts[*slot] == 1)
+        .collect::<Vec<_>>();
+    let mut stack = Vec::with_capacity(keys.len());
+    while let Some(slot) = queue.pop() {
+        if counts[slot] != 1 {
+            continue;
+        }
+        // the only key left in the slot
+        let h = xor_masks[slot];
+        stack.push((h, slot));
+        for slot in slots(h, segment_len) {
+            xor_masks[slot] ^= h;
+            counts[slot] -= 1;
+            if counts[slot] == 1 {
+                queue.push(slot);
+            }
+        }
+    }
+    (stack.len() == keys.len()).then_some(stack)
+}
+
+/// Build the filter from key hashes, which is the seed followed by the fingerprints.
+pub(crate) fn build(key_hashes: &[u32]) -> Vec<u8> {
+    let mut keys = key_hashes.to_vec();
+    keys.sort_unstable();
+    keys.dedup();
+    let segment_len = (32 + (keys.len() as f64 * 1.23).ceil() as usize) / 3;
+    let mut seed = 0;
+    loop {
+        seed = mix64(seed ^ 0x9e37_79b9_7f4a_7c15);
+        let Some(stack) = peel(&keys, seed, segment_len) else {
+            continue;
+        };
+        let mut fingerprints = vec![0u8; 3 * segment_len];
+        // the slot of a key is free when it is assigned, as the keys peeled later do not use it
+        for (h, slot) in stack.into_iter().rev() {
+            let [a, b, c] = slots(h, segment_len);
+            fingerprints[slot] =
+                fingerprint(h) ^ fingerprints[a] ^ fingerprints[b] ^ fingerprints[c];
+        }
+        let mut filter = Vec::with_capacity(8 + fingerprints.len());
+        filter.put_u64(seed);
+        filter.extend(fingerprints);
+        return filter;
+    }
+}
+
+pub(crate) fn may_contain(filter: &[u8], key_hash: u32) -> bool {
+    let (mut seed, fingerprints) = filter.split_at(8);
+    let h = hash(key_hash, seed.get_u64());
+    let [a, b, c] = slots(h, fingerprints.len() / 3);
+    fingerprint(h) == fingerprints[a] ^ fingerprints[b] ^ fingerprints[c]
+}
@@ -1,4 +1,28 @@
+mod atomic_write_batch;
+mod block_cache;
+mod block_compression;
+mod checkpoint;
+mod column_family;
+mod compaction_filter;
 mod harness;
+mod harness_ext;
+mod large_entries;
+mod manifest_rotation;
+mod merge_operator;
+mod named_snapshot;
+mod partitioned_index;
+mod prefix_bloom;
+mod range_tombstone;
+mod rate_limiter;
+mod reverse_iteration;
+mod sst_filter;
+mod subcompaction;
+mod time_travel;
+mod trivial_move;
+mod ttl;
+mod txn_conflict;
+mod value_log;
+mod value_type;
 mod week1_day1;
 mod week1_day2;
 mod week1_day3;
@@ -19,3 +43,4 @@ mod week3_day4;
 mod week3_day5;
 mod week3_day6;
 mod week3_day7;
+mod write_stall;
@@ -0,0 +1,99 @@
+use std::fs::OpenOptions;
+
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    wal::Wal,
+};
+
+#[test]
+fn test_wal_drop_torn_batch() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.wal");
+    let wal = Wal::create(&path).unwrap();
+    wal.put_batch(
+        &[
+            (KeySlice::for_testing_from_slice_with_ts(b"a", 1), &b"1"[..]),
+            (KeySlice::for_testing_from_slice_with_ts(b"b", 1), &b"1"[..]),
+        ],
+        &[],
+    )
+    .unwrap();
+    wal.put_batch(
+        &[
+            (KeySlice::for_testing_from_slice_with_ts(b"a", 2), &b"2"[..]),
+            (KeySlice::for_testing_from_slice_with_ts(b"b", 2), &b"2"[..]),
+        ],
+        &[],
+    )
+    .unwrap();
+    wal.sync().unwrap();
+    drop(wal);
+
+    // simulate a crash in the middle of writing the second batch
+    let len = std::fs::metadata(&path).unwrap().len();
+    let file = OpenOptions::new().write(true).open(&path).unwrap();
+    file.set_len(len - 6).unwrap();
+    drop(file);
+
+    let map = SkipMap::new();
+    let wal = Wal::recover(&path, &map, &mut Vec::new()).unwrap();
+    assert_eq!(map.len(), 2);
+    for entry in map.iter() {
+        assert_eq!(entry.key().ts(), 1);
+    }
+
+    // the torn batch is truncated, so new batches can be recovered again
+    wal.put_batch(
+        &[(KeySlice::for_testing_from_slice_with_ts(b"c", 3), &b"3"[..])],
+        &[],
+    )
+    .unwrap();
+    wal.sync().unwrap();
+    drop(wal);
+    let map = SkipMap::new();
+    Wal::recover(&path, &map, &mut Vec::new()).unwrap();
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn test_write_batch_all_or_nothing() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put("key1", "v1"),
+            WriteBatchRecord::Put("key2", "v1"),
+        ])
+        .unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"key1", b"v2");
+    txn.put(b"key2", b"v2");
+    txn.put(b"key3", b"v2");
+    txn.commit().unwrap();
+    storage.close().unwrap();
+    drop(txn);
+    drop(storage);
+
+    let wal_path = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .find(|x| x.extension().map(|x| x == "wal").unwrap_or(false))
+        .unwrap();
+    let len = std::fs::metadata(&wal_path).unwrap().len();
+    let file = OpenOptions::new().write(true).open(&wal_path).unwrap();
+    file.set_len(len - 1).unwrap();
+    drop(file);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"key1").unwrap(), Some(Bytes::from("v1")));
+    assert_eq!(storage.get(b"key2").unwrap(), Some(Bytes::from("v1")));
+    assert_eq!(storage.get(b"key3").unwrap(), None);
+}
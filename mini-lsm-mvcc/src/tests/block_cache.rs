@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    block_cache::BlockCache,
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::key_of;
+
+fn put_and_flush(storage: &MiniLsm, value: &str) {
+    for idx in 0..1000 {
+        storage.put(&key_of(idx), value.as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+}
+
+#[test]
+fn test_block_cache_stats() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    put_and_flush(&storage, "value");
+    assert_eq!(storage.block_cache_stats().misses, 0);
+
+    storage.get(b"key_00500").unwrap();
+    let stats = storage.block_cache_stats();
+    assert_eq!(stats.hits, 0);
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.entry_count, 1);
+    assert!(stats.weighted_size > 0);
+
+    storage.get(b"key_00500").unwrap();
+    let stats = storage.block_cache_stats();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.evictions, 0);
+}
+
+#[test]
+fn test_block_cache_eviction() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    // room for about two blocks
+    options.block_cache_capacity_bytes = 2 * options.block_size as u64;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    put_and_flush(&storage, "value");
+    for idx in (0..1000).step_by(50) {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from("value"))
+        );
+    }
+    let stats = storage.block_cache_stats();
+    assert!(stats.evictions > 0);
+    assert!(stats.weighted_size <= 2 * 4096);
+}
+
+#[test]
+fn test_shared_block_cache() {
+    let block_cache = Arc::new(BlockCache::new(64 << 20));
+    let open = |dir: &tempfile::TempDir| {
+        let mut options =
+            LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+        options.block_cache = Some(block_cache.clone());
+        MiniLsm::open(dir, options).unwrap()
+    };
+    let (dir1, dir2) = (tempdir().unwrap(), tempdir().unwrap());
+    let (storage1, storage2) = (open(&dir1), open(&dir2));
+    // both storages number their SSTs the same way, the cache must not mix up their blocks
+    put_and_flush(&storage1, "value1");
+    put_and_flush(&storage2, "value2");
+    for _ in 0..2 {
+        assert_eq!(
+            storage1.get(b"key_00500").unwrap(),
+            Some(Bytes::from("value1"))
+        );
+        assert_eq!(
+            storage2.get(b"key_00500").unwrap(),
+            Some(Bytes::from("value2"))
+        );
+    }
+    let stats = block_cache.stats();
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.entry_count, 2);
+    assert_eq!(storage1.block_cache_stats(), stats);
+}
+
+#[test]
+fn test_block_cache_invalidated_by_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    put_and_flush(&storage, "value1");
+    put_and_flush(&storage, "value2");
+    for idx in 0..1000 {
+        storage.get(&key_of(idx)).unwrap();
+    }
+    assert!(storage.block_cache_stats().entry_count > 0);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.block_cache_stats().entry_count, 0);
+    assert_eq!(
+        storage.get(b"key_00500").unwrap(),
+        Some(Bytes::from("value2"))
+    );
+}
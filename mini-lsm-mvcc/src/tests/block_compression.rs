@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    compression::{CompressionOptions, CompressionType},
+    key::KeySlice,
+    lsm_storage::{BlockCache, LsmStorageOptions, MiniLsm},
+    table::{SsTable, SsTableBuilder, SsTableIterator},
+};
+
+use super::harness::{check_iter_result_by_key, key_of};
+
+fn value_of(idx: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}", idx).repeat(8))
+}
+
+fn build_sst(
+    dir: &tempfile::TempDir,
+    id: usize,
+    compression: CompressionType,
+    block_cache: Option<Arc<BlockCache>>,
+) -> SsTable {
+    let mut builder = SsTableBuilder::new_with_compression(1024, compression);
+    for idx in 0..500 {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx),
+        );
+    }
+    builder
+        .build(id, block_cache, dir.path().join(format!("{}.sst", id)))
+        .unwrap()
+}
+
+/// Get the compression type byte stored after the first data block.
+fn first_block_compression(sst: &SsTable) -> CompressionType {
+    let offset_end = sst.block_meta[1].offset;
+    let data = sst.file.read(offset_end as u64 - 5, 1).unwrap();
+    CompressionType::from_byte(data[0]).unwrap()
+}
+
+#[test]
+fn test_sst_compression_roundtrip() {
+    let dir = tempdir().unwrap();
+    let uncompressed = build_sst(&dir, 0, CompressionType::None, None);
+    assert_eq!(
+        first_block_compression(&uncompressed),
+        CompressionType::None
+    );
+    for (id, compression) in [
+        CompressionType::Lz4,
+        CompressionType::Snappy,
+        CompressionType::Zstd(3),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let sst = Arc::new(build_sst(&dir, id + 1, compression, None));
+        assert!(sst.table_size() < uncompressed.table_size());
+        assert_eq!(
+            first_block_compression(&sst).to_byte(),
+            compression.to_byte()
+        );
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst).unwrap();
+        check_iter_result_by_key(
+            &mut iter,
+            (0..500).map(|idx| (key_of(idx), value_of(idx))).collect(),
+        );
+    }
+}
+
+#[test]
+fn test_incompressible_block_stored_raw() {
+    let dir = tempdir().unwrap();
+    // pseudo-random bytes that lz4 cannot shrink
+    let mut state = 0x2545f491u32;
+    let value = (0..256)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect::<Vec<_>>();
+    let mut builder = SsTableBuilder::new_with_compression(4096, CompressionType::Lz4);
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"a"), &value);
+    let sst = builder.build(0, None, dir.path().join("0.sst")).unwrap();
+    let data = sst.file.read(sst.block_meta_offset as u64 - 5, 1).unwrap();
+    assert_eq!(data[0], CompressionType::None.to_byte());
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap();
+    check_iter_result_by_key(&mut iter, vec![(Bytes::from("a"), Bytes::from(value))]);
+}
+
+#[test]
+fn test_block_cache_holds_decompressed_blocks() {
+    let dir = tempdir().unwrap();
+    let block_cache = Arc::new(BlockCache::new(1 << 20));
+    let sst = build_sst(&dir, 0, CompressionType::Zstd(3), Some(block_cache.clone()));
+    let block = sst.read_block_cached(0).unwrap();
+    assert_eq!(block.data, sst.read_block(0).unwrap().data);
+    let cached = block_cache.get(0, 0).unwrap();
+    assert!(Arc::ptr_eq(&block, &cached));
+    assert_eq!(cached.data, sst.read_block(0).unwrap().data);
+}
+
+#[test]
+fn test_per_level_compression() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.compression = CompressionOptions {
+        per_level: vec![CompressionType::None, CompressionType::Lz4],
+    };
+    assert_eq!(options.compression.for_level(0), CompressionType::None);
+    assert_eq!(options.compression.for_level(5), CompressionType::Lz4);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..500 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let compression_of_level = |storage: &MiniLsm, level: usize| {
+        let state = storage.inner.state.read();
+        let sst_id = if level == 0 {
+            state.l0_sstables[0]
+        } else {
+            state.levels[level - 1].1[0]
+        };
+        first_block_compression(&state.sstables[&sst_id])
+    };
+    assert_eq!(compression_of_level(&storage, 0), CompressionType::None);
+
+    storage.force_full_compaction().unwrap();
+    assert_eq!(compression_of_level(&storage, 1), CompressionType::Lz4);
+    for idx in (0..500).step_by(7) {
+        assert_eq!(storage.get(&key_of(idx)).unwrap(), Some(value_of(idx)));
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    checkpoint::BackupMode,
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::key_of;
+use super::harness_ext::options_with_value_log;
+
+fn sst_files(dir: &Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.ends_with(".sst"))
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_checkpoint() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(dir.path().join("db"), options.clone()).unwrap();
+    let cf = storage
+        .create_column_family("users", options.column_family_options())
+        .unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"v1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    // left in the memtables
+    for idx in 50..150 {
+        storage.put(&key_of(idx), b"v2").unwrap();
+    }
+    storage.delete(&key_of(0)).unwrap();
+    storage.delete_range(&key_of(10), &key_of(20)).unwrap();
+    storage.put_cf(&cf, b"alice", b"1").unwrap();
+
+    let checkpoint_dir = dir.path().join("checkpoint");
+    let stats = storage.create_checkpoint(&checkpoint_dir).unwrap();
+    assert_eq!(stats.linked_files, 1);
+    assert_eq!(stats.copied_files, 0);
+    assert!(storage.create_checkpoint(&checkpoint_dir).is_err());
+
+    // changes after the checkpoint, and the flushed SST is removed from the database
+    for idx in 0..150 {
+        storage.put(&key_of(idx), b"v3").unwrap();
+    }
+    storage.put_cf(&cf, b"bob", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let checkpoint = MiniLsm::open(&checkpoint_dir, options).unwrap();
+    let cf = checkpoint.column_family("users").unwrap();
+    assert_eq!(checkpoint.get(&key_of(0)).unwrap(), None);
+    assert_eq!(checkpoint.get(&key_of(15)).unwrap(), None);
+    assert_eq!(
+        checkpoint.get(&key_of(30)).unwrap(),
+        Some(Bytes::from("v1"))
+    );
+    assert_eq!(
+        checkpoint.get(&key_of(120)).unwrap(),
+        Some(Bytes::from("v2"))
+    );
+    assert_eq!(
+        checkpoint.get_cf(&cf, b"alice").unwrap(),
+        Some(Bytes::from("1"))
+    );
+    assert_eq!(checkpoint.get_cf(&cf, b"bob").unwrap(), None);
+    assert_eq!(checkpoint.inner.mvcc().latest_commit_ts(), stats.commit_ts);
+}
+
+#[test]
+fn test_incremental_backup() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(dir.path().join("db"), options.clone()).unwrap();
+    let backup_dir = dir.path().join("backup");
+    for round in 0..3 {
+        for idx in (round * 100)..(round * 100 + 100) {
+            storage.put(&key_of(idx), b"v1").unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    let stats = storage
+        .create_backup(&backup_dir, BackupMode::Incremental)
+        .unwrap();
+    assert_eq!((stats.copied_files, stats.skipped_files), (3, 0));
+
+    for idx in 300..400 {
+        storage.put(&key_of(idx), b"v1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(&key_of(0), b"v2").unwrap();
+    let stats = storage
+        .create_backup(&backup_dir, BackupMode::Incremental)
+        .unwrap();
+    // the new SST is copied, and the memtable is written to another SST
+    assert_eq!((stats.copied_files, stats.skipped_files), (1, 3));
+    assert_eq!(sst_files(&backup_dir).len(), 5);
+    let stats = storage
+        .create_backup(&backup_dir, BackupMode::Full)
+        .unwrap();
+    assert_eq!((stats.copied_files, stats.skipped_files), (4, 0));
+    assert_eq!(sst_files(&backup_dir).len(), 5);
+
+    // the SSTs removed by compaction are removed from the backup
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage
+        .create_backup(&backup_dir, BackupMode::Incremental)
+        .unwrap();
+    assert_eq!(sst_files(&backup_dir), sst_files(&dir.path().join("db")));
+
+    let restore_dir = dir.path().join("restored");
+    MiniLsm::restore_backup(&backup_dir, &restore_dir).unwrap();
+    assert!(MiniLsm::restore_backup(&backup_dir, &restore_dir).is_err());
+    assert!(MiniLsm::restore_backup(dir.path().join("none"), dir.path().join("none2")).is_err());
+    let restored = MiniLsm::open(&restore_dir, options).unwrap();
+    assert_eq!(restored.get(&key_of(0)).unwrap(), Some(Bytes::from("v2")));
+    for idx in 1..400 {
+        assert_eq!(restored.get(&key_of(idx)).unwrap(), Some(Bytes::from("v1")));
+    }
+}
+
+#[test]
+fn test_backup_value_log() {
+    let dir = tempdir().unwrap();
+    let options = options_with_value_log();
+    let storage = MiniLsm::open(dir.path().join("db"), options.clone()).unwrap();
+    let large_value = |idx: usize| Bytes::from(format!("{}", idx).repeat(2000));
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &large_value(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(&key_of(10), &large_value(10)).unwrap();
+
+    let backup_dir = dir.path().join("backup");
+    storage
+        .create_backup(&backup_dir, BackupMode::Full)
+        .unwrap();
+    // overwrite all values, and collect the value log file that the backup has
+    for idx in 0..11 {
+        storage.put(&key_of(idx), b"small").unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert!(storage.gc_value_log().unwrap());
+
+    let restore_dir = dir.path().join("restored");
+    MiniLsm::restore_backup(&backup_dir, &restore_dir).unwrap();
+    let restored = MiniLsm::open(&restore_dir, options).unwrap();
+    for idx in 0..11 {
+        assert_eq!(restored.get(&key_of(idx)).unwrap(), Some(large_value(idx)));
+    }
+    // the restored database appends to a new value log file
+    restored.put(b"new", &large_value(11)).unwrap();
+    assert_eq!(restored.get(b"new").unwrap(), Some(large_value(11)));
+}
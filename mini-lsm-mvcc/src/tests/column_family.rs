@@ -0,0 +1,191 @@
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::check_lsm_iter_result_by_key;
+
+#[test]
+fn test_column_family_isolation() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let cf = storage
+        .create_column_family("users", options.column_family_options())
+        .unwrap();
+    assert!(storage
+        .create_column_family("users", options.column_family_options())
+        .is_err());
+    assert_eq!(storage.column_family("users").unwrap().id(), cf.id());
+    assert!(storage.column_family("orders").is_none());
+
+    for key in ["a", "b", "c"] {
+        storage.put(key.as_bytes(), b"default").unwrap();
+        storage.put_cf(&cf, key.as_bytes(), b"users").unwrap();
+    }
+    storage.delete_cf(&cf, b"a").unwrap();
+    storage.delete_range(b"b", b"c").unwrap();
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("default")));
+        assert_eq!(storage.get(b"b").unwrap(), None);
+        assert_eq!(storage.get_cf(&cf, b"a").unwrap(), None);
+        assert_eq!(
+            storage.get_cf(&cf, b"b").unwrap(),
+            Some(Bytes::from("users"))
+        );
+        check_lsm_iter_result_by_key(
+            &mut storage
+                .scan_cf(&cf, Bound::Unbounded, Bound::Unbounded)
+                .unwrap(),
+            vec![
+                (Bytes::from("b"), Bytes::from("users")),
+                (Bytes::from("c"), Bytes::from("users")),
+            ],
+        );
+        check_lsm_iter_result_by_key(
+            &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+            vec![
+                (Bytes::from("a"), Bytes::from("default")),
+                (Bytes::from("c"), Bytes::from("default")),
+            ],
+        );
+    };
+    check(&storage);
+    storage.force_flush().unwrap();
+    check(&storage);
+
+    // each column family flushes to its own SSTs
+    let default_state = storage.inner.state.read().clone();
+    let cf_state = cf.state.read().clone();
+    assert_eq!(default_state.l0_sstables.len(), 1);
+    assert_eq!(cf_state.l0_sstables.len(), 1);
+    assert_ne!(default_state.l0_sstables, cf_state.l0_sstables);
+}
+
+#[test]
+fn test_column_family_transaction() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let cf = storage
+        .create_column_family("index", options.column_family_options())
+        .unwrap();
+
+    let snapshot = storage.new_txn().unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"1", b"alice");
+    txn.put_cf(&cf, b"alice", b"1");
+    assert_eq!(txn.get(b"1").unwrap(), Some(Bytes::from("alice")));
+    assert_eq!(txn.get_cf(&cf, b"alice").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(txn.get_cf(&cf, b"1").unwrap(), None);
+    txn.commit().unwrap();
+
+    // both writes become visible at the same time
+    assert_eq!(snapshot.get(b"1").unwrap(), None);
+    assert_eq!(snapshot.get_cf(&cf, b"alice").unwrap(), None);
+    assert_eq!(storage.get(b"1").unwrap(), Some(Bytes::from("alice")));
+    assert_eq!(
+        storage.get_cf(&cf, b"alice").unwrap(),
+        Some(Bytes::from("1"))
+    );
+
+    // the same key in different column families does not conflict
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get(b"2").unwrap();
+    txn1.put(b"2", b"bob");
+    txn2.get_cf(&cf, b"2").unwrap();
+    txn2.put_cf(&cf, b"2", b"bob");
+    txn1.commit().unwrap();
+    txn2.commit().unwrap();
+
+    // but the same key in the same column family does
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get_cf(&cf, b"bob").unwrap();
+    txn1.put_cf(&cf, b"bob", b"2");
+    txn2.get_cf(&cf, b"bob").unwrap();
+    txn2.put_cf(&cf, b"bob", b"3");
+    txn1.commit().unwrap();
+    assert!(txn2.commit().is_err());
+}
+
+#[test]
+fn test_column_family_recover() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let mut cf_options = options.column_family_options();
+    cf_options.block_size = 1024;
+    let cf = storage.create_column_family("meta", cf_options).unwrap();
+
+    storage.put(b"a", b"1").unwrap();
+    storage.put_cf(&cf, b"a", b"2").unwrap();
+    storage.force_flush().unwrap();
+    // only the column family has data in this memtable
+    storage.put_cf(&cf, b"b", b"3").unwrap();
+    storage.force_flush().unwrap();
+    // and these are only in the WAL
+    storage.put(b"c", b"4").unwrap();
+    storage.put_cf(&cf, b"c", b"5").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let cf = storage.column_family("meta").unwrap();
+    assert_eq!(cf.options().block_size, 1024);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from("4")));
+    assert_eq!(storage.get_cf(&cf, b"a").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get_cf(&cf, b"b").unwrap(), Some(Bytes::from("3")));
+    assert_eq!(storage.get_cf(&cf, b"c").unwrap(), Some(Bytes::from("5")));
+    assert_eq!(cf.state.read().l0_sstables.len(), 2);
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 1);
+}
+
+#[test]
+fn test_column_family_compaction_options() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let mut cf_options = options.column_family_options();
+    cf_options.compaction_options = CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 3,
+    });
+    let cf = storage.create_column_family("logs", cf_options).unwrap();
+
+    for round in 0..4 {
+        for key in 0..100 {
+            let key = format!("key_{:03}", key);
+            let value = format!("value_{}", round);
+            storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+            storage
+                .put_cf(&cf, key.as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    std::thread::sleep(Duration::from_secs(1));
+
+    // the column family is compacted, while the default one is not
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 4);
+    let cf_state = cf.state.read().clone();
+    assert!(cf_state.l0_sstables.len() < 2);
+    assert!(cf_state.levels.iter().any(|(_, ssts)| !ssts.is_empty()));
+    assert_eq!(
+        storage.get_cf(&cf, b"key_042").unwrap(),
+        Some(Bytes::from("value_3"))
+    );
+}
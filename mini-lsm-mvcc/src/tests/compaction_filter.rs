@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    compaction_filter::{CompactionDecision, CompactionFilter, PrefixCompactionFilter},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness_ext::options_with_value_log;
+
+/// Removes the keys of the deleted tenants, and rewrites `v1:` values to `v2:`.
+#[derive(Default)]
+struct TenantFilter {
+    deleted_tenants: Mutex<Vec<String>>,
+    seen: Mutex<Vec<(Bytes, usize)>>,
+}
+
+impl CompactionFilter for TenantFilter {
+    fn filter(&self, key: &[u8], _ts: u64, value: &[u8], level: usize) -> CompactionDecision {
+        self.seen.lock().push((Bytes::copy_from_slice(key), level));
+        let tenant = String::from_utf8_lossy(key.split(|x| *x == b'/').next().unwrap());
+        if self.deleted_tenants.lock().iter().any(|x| *x == tenant) {
+            return CompactionDecision::Remove;
+        }
+        match value.strip_prefix(b"v1:") {
+            Some(rest) => CompactionDecision::ChangeValue(Bytes::from([b"v2:", rest].concat())),
+            None => CompactionDecision::Keep,
+        }
+    }
+}
+
+#[test]
+fn test_compaction_filter() {
+    let dir = tempdir().unwrap();
+    let options = options_with_value_log();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let large_value = format!("v1:{}", "x".repeat(2000));
+    storage.put(b"alice/a", b"v1:1").unwrap();
+    storage.put(b"alice/b", large_value.as_bytes()).unwrap();
+    storage.put(b"bob/a", b"v1:1").unwrap();
+    storage.put(b"bob/b", b"v2:1").unwrap();
+    storage.delete(b"carol/a").unwrap();
+    storage.force_flush().unwrap();
+
+    let filter = Arc::new(TenantFilter::default());
+    filter.deleted_tenants.lock().push("bob".to_string());
+    storage.add_compaction_filter(filter.clone());
+    // the version newer than the watermark is not filtered
+    let txn = storage.new_txn().unwrap();
+    storage.put(b"alice/a", b"v1:2").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(txn.get(b"alice/a").unwrap(), Some(Bytes::from("v2:1")));
+    assert_eq!(txn.get(b"bob/a").unwrap(), None);
+    assert_eq!(storage.get(b"alice/a").unwrap(), Some(Bytes::from("v1:2")));
+    drop(txn);
+
+    // tombstones are not passed, and the value in the value log is resolved
+    let mut seen = filter.seen.lock().clone();
+    seen.sort();
+    assert_eq!(
+        seen,
+        vec![
+            (Bytes::from("alice/a"), 1),
+            (Bytes::from("alice/b"), 1),
+            (Bytes::from("bob/a"), 1),
+            (Bytes::from("bob/b"), 1),
+        ]
+    );
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.get(b"alice/a").unwrap(), Some(Bytes::from("v2:2")));
+    assert_eq!(
+        storage.get(b"alice/b").unwrap(),
+        Some(Bytes::from(large_value.replacen("v1:", "v2:", 1)))
+    );
+    assert_eq!(storage.get(b"bob/a").unwrap(), None);
+    assert_eq!(storage.get(b"bob/b").unwrap(), None);
+}
+
+#[test]
+fn test_compaction_filter_chain() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"table1_a", b"v1:1").unwrap();
+    storage.put(b"table2_a", b"v1:1").unwrap();
+    storage.force_flush().unwrap();
+    // the value changed by the first filter is passed to the next one
+    storage.add_compaction_filter(Arc::new(TenantFilter::default()));
+    storage.add_compaction_filter(Arc::new(PrefixCompactionFilter::new("table2_")));
+    let seen = Arc::new(TenantFilter::default());
+    storage.add_compaction_filter(seen.clone());
+    storage.force_full_compaction().unwrap();
+    assert_eq!(seen.seen.lock().clone(), vec![(Bytes::from("table1_a"), 1)]);
+    assert_eq!(storage.get(b"table1_a").unwrap(), Some(Bytes::from("v2:1")));
+    assert_eq!(storage.get(b"table2_a").unwrap(), None);
+}
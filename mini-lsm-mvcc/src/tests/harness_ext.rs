@@ -0,0 +1,51 @@
+//! Helpers for the tests of the options that only this crate has, as `harness.rs` is shared with
+//! mini-lsm.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::{
+    compact::CompactionOptions, lsm_storage::LsmStorageOptions, merge_operator::MergeOperator,
+};
+
+/// Appends the operands to the value, separated by commas. An empty operand clears the value.
+#[derive(Debug)]
+pub struct AppendOperator;
+
+impl MergeOperator for AppendOperator {
+    fn full_merge(&self, _key: &[u8], existing: Option<&[u8]>, operands: &[&[u8]]) -> Bytes {
+        let mut value = existing.map(|x| x.to_vec()).unwrap_or_default();
+        for operand in operands {
+            if operand.is_empty() {
+                value.clear();
+                continue;
+            }
+            if !value.is_empty() {
+                value.push(b',');
+            }
+            value.extend_from_slice(operand);
+        }
+        value.into()
+    }
+
+    fn partial_merge(&self, _key: &[u8], operands: &[&[u8]]) -> Option<Bytes> {
+        if operands.iter().any(|operand| operand.is_empty()) {
+            return None;
+        }
+        Some(operands.join(&b","[..]).into())
+    }
+}
+
+/// Options that store the values larger than 1 KiB in the value log.
+pub fn options_with_value_log() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.value_log_threshold = Some(1024);
+    options
+}
+
+pub fn options_with_merge_operator() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.merge_operator = Some(Arc::new(AppendOperator));
+    options
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use bytes::{BufMut, Bytes};
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator},
+    varint::{get_varint, put_varint, varint_len},
+    vlog::{ValueLog, VALUE_LOG_FORMAT_VERSION},
+};
+
+use super::harness::scan_pairs;
+use super::harness_ext::options_with_value_log;
+
+/// A key and a value larger than 64 KiB, which do not fit in a `u16` length.
+fn large_key() -> Bytes {
+    Bytes::from("k".repeat(70000))
+}
+
+fn large_value() -> Bytes {
+    Bytes::from("v".repeat(100000))
+}
+
+fn sst_pairs(table: SsTable) -> Vec<(Bytes, Bytes)> {
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(table)).unwrap();
+    let mut pairs = Vec::new();
+    while iter.is_valid() {
+        pairs.push((
+            Bytes::copy_from_slice(iter.key().key_ref()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    pairs
+}
+
+#[test]
+fn test_large_entries() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", &large_value()).unwrap();
+    storage.put(&large_key(), b"2").unwrap();
+    storage.put(b"z", &large_value()).unwrap();
+    storage.delete(b"z").unwrap();
+
+    let expected = vec![
+        (Bytes::from("a"), Bytes::from("1")),
+        (Bytes::from("b"), large_value()),
+        (large_key(), Bytes::from("2")),
+    ];
+    assert_eq!(scan_pairs(&storage), expected);
+
+    // the WAL records are not truncated
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+
+    storage.force_flush().unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+    assert_eq!(storage.get(&large_key()).unwrap(), Some(Bytes::from("2")));
+    storage.force_full_compaction().unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+    assert_eq!(storage.get(b"b").unwrap(), Some(large_value()));
+}
+
+#[test]
+fn test_large_key_with_value_log() {
+    let dir = tempdir().unwrap();
+    let options = options_with_value_log();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(&large_key(), &large_value()).unwrap();
+    storage.put(b"a", &large_value()).unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(storage.get(&large_key()).unwrap(), Some(large_value()));
+    assert_eq!(storage.get(b"a").unwrap(), Some(large_value()));
+
+    // both values are in the value log
+    let vlog_id = storage.inner.value_log.file_ids()[0];
+    let records = storage.inner.value_log.read_file(vlog_id).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].key.key_ref(), large_key());
+    assert_eq!(records[0].value, large_value());
+}
+
+#[test]
+fn test_unsupported_value_log_format_version() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.vlog");
+    let mut buf = Vec::new();
+    buf.put_u32(VALUE_LOG_FORMAT_VERSION + 1);
+    std::fs::write(&path, &buf).unwrap();
+    let value_log = ValueLog::new();
+    assert!(value_log.open_file(1, &path).is_err());
+}
+
+#[test]
+fn test_oversized_entry_block() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut builder = SsTableBuilder::new(4096);
+    for key in [b"a", b"b"] {
+        builder.add(KeySlice::for_testing_from_slice_no_ts(key), b"1");
+    }
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"c"), &large_value());
+    for key in [b"d", b"e"] {
+        builder.add(KeySlice::for_testing_from_slice_no_ts(key), b"1");
+    }
+    let table = builder.build_for_test(&path).unwrap();
+
+    // the oversized entry gets a block of its own between the blocks of the small entries
+    assert_eq!(table.num_of_blocks(), 3);
+    let block_keys = table
+        .block_meta
+        .iter()
+        .map(|meta| {
+            (
+                Bytes::copy_from_slice(meta.first_key.key_ref()),
+                Bytes::copy_from_slice(meta.last_key.key_ref()),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        block_keys,
+        vec![
+            (Bytes::from("a"), Bytes::from("b")),
+            (Bytes::from("c"), Bytes::from("c")),
+            (Bytes::from("d"), Bytes::from("e")),
+        ]
+    );
+    let table = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    let pairs = sst_pairs(table);
+    assert_eq!(pairs.len(), 5);
+    assert_eq!(pairs[2], (Bytes::from("c"), large_value()));
+}
+
+#[test]
+fn test_varint() {
+    for value in [0, 1, 127, 128, 65535, 65536, u32::MAX as u64, u64::MAX] {
+        let mut buf = Vec::new();
+        put_varint(&mut buf, value);
+        assert_eq!(buf.len(), varint_len(value));
+        assert_eq!(get_varint(&mut &buf[..]).unwrap(), value);
+        // a truncated varint is an error
+        assert!(get_varint(&mut &buf[..buf.len() - 1]).is_err());
+    }
+    // more continuation bytes than a u64 has bits is an error, not an overflow
+    assert!(get_varint(&mut &[0xff; 11][..]).is_err());
+}
@@ -0,0 +1,108 @@
+use std::path::Path;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn manifest_files(path: &Path) -> Vec<String> {
+    let mut files = std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.starts_with("MANIFEST"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_manifest_not_rotated_below_limit() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    assert!(!dir.path().join("CURRENT").exists());
+    assert_eq!(manifest_files(dir.path()), vec!["MANIFEST".to_string()]);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+}
+
+#[test]
+fn test_manifest_rotation() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    options.enable_wal = true;
+    options.max_manifest_size = 256;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let cf = storage
+        .create_column_family("meta", options.column_family_options())
+        .unwrap();
+
+    for round in 0..5 {
+        for key in 0..50 {
+            let key = format!("key_{:03}", key);
+            let value = format!("value_{}", round);
+            storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+            storage
+                .put_cf(&cf, key.as_bytes(), value.as_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+        // the manifest is rotated on every flush and compaction, and only one file is left
+        assert_eq!(manifest_files(dir.path()).len(), 1);
+    }
+    // this one is only in the WAL
+    storage.put(b"key_000", b"value_wal").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let current = std::fs::read_to_string(dir.path().join("CURRENT")).unwrap();
+    assert_eq!(manifest_files(dir.path()), vec![current]);
+    assert!(!dir.path().join("MANIFEST").exists());
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let cf = storage.column_family("meta").unwrap();
+    assert_eq!(
+        storage.get(b"key_000").unwrap(),
+        Some(Bytes::from("value_wal"))
+    );
+    assert_eq!(
+        storage.get(b"key_042").unwrap(),
+        Some(Bytes::from("value_4"))
+    );
+    assert_eq!(
+        storage.get_cf(&cf, b"key_000").unwrap(),
+        Some(Bytes::from("value_4"))
+    );
+
+    // keep writing after recovering from a rotated manifest
+    storage.put_cf(&cf, b"key_100", b"value_5").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let cf = storage.column_family("meta").unwrap();
+    assert_eq!(
+        storage.get_cf(&cf, b"key_100").unwrap(),
+        Some(Bytes::from("value_5"))
+    );
+    assert_eq!(
+        storage.get(b"key_000").unwrap(),
+        Some(Bytes::from("value_wal"))
+    );
+    assert_eq!(manifest_files(dir.path()).len(), 1);
+}
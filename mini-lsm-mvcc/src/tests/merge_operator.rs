@@ -0,0 +1,218 @@
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+};
+
+use super::harness::{construct_merge_iterator_over_storage, scan_pairs};
+use super::harness_ext::options_with_merge_operator;
+
+fn scan_pairs_backward(storage: &MiniLsm) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    iter.seek_to_last().unwrap();
+    let mut pairs = Vec::new();
+    while iter.is_valid() {
+        pairs.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.prev().unwrap();
+    }
+    pairs
+}
+
+fn stored_versions(storage: &MiniLsm, key: &[u8]) -> Vec<Bytes> {
+    let mut iter = construct_merge_iterator_over_storage(&storage.inner.state.read());
+    let mut values = Vec::new();
+    while iter.is_valid() {
+        if iter.key().key_ref() == key {
+            values.push(Bytes::copy_from_slice(iter.value()));
+        }
+        iter.next().unwrap();
+    }
+    values
+}
+
+#[test]
+fn test_merge() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_merge_operator();
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.merge(b"a", b"2").unwrap();
+    storage.merge(b"a", b"3").unwrap();
+    storage.merge(b"b", b"1").unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.delete(b"c").unwrap();
+    storage.merge(b"c", b"2").unwrap();
+    storage.put(b"d", b"1").unwrap();
+    storage.merge(b"d", b"").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"e"[..], &b"1"[..]),
+            WriteBatchRecord::Merge(&b"e"[..], &b"2"[..]),
+            WriteBatchRecord::Merge(&b"f"[..], &b"1"[..]),
+            WriteBatchRecord::Merge(&b"f"[..], &b"2"[..]),
+        ])
+        .unwrap();
+    storage.merge(b"f", b"3").unwrap();
+
+    let expected = vec![
+        (Bytes::from("a"), Bytes::from("1,2,3")),
+        (Bytes::from("b"), Bytes::from("1")),
+        (Bytes::from("c"), Bytes::from("2")),
+        (Bytes::from("d"), Bytes::new()),
+        (Bytes::from("e"), Bytes::from("1,2")),
+        (Bytes::from("f"), Bytes::from("1,2,3")),
+    ];
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3")));
+    // merging an empty operand clears the value in this operator, which does not delete the key
+    assert_eq!(storage.get(b"d").unwrap(), Some(Bytes::new()));
+    assert_eq!(scan_pairs(&storage), expected);
+    let mut reversed = expected.clone();
+    reversed.reverse();
+    assert_eq!(scan_pairs_backward(&storage), reversed);
+
+    // the merges are kept in the WAL and folded when they are read from the SSTs
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+    storage.force_flush().unwrap();
+    storage.merge(b"a", b"4").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3,4")));
+    assert_eq!(storage.get(b"f").unwrap(), Some(Bytes::from("1,2,3")));
+}
+
+#[test]
+fn test_merge_without_operator() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(storage.merge(b"a", b"1").is_err());
+    assert_eq!(storage.get(b"a").unwrap(), None);
+}
+
+#[test]
+fn test_merge_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options_with_merge_operator()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.merge(b"b", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.merge(b"a", b"2").unwrap();
+    storage.merge(b"b", b"2").unwrap();
+    storage.merge(b"c", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.merge(b"a", b"3").unwrap();
+    storage.merge(b"c", b"2").unwrap();
+    storage.force_flush().unwrap();
+
+    // a snapshot keeps the versions it reads, until it is dropped
+    let txn = storage.new_txn().unwrap();
+    storage.merge(b"a", b"4").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from("1,2,3")));
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3,4")));
+    drop(txn);
+
+    // the merges are folded into a single value by a full compaction
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        stored_versions(&storage, b"a"),
+        vec![Bytes::from("1,2,3,4")]
+    );
+    assert_eq!(stored_versions(&storage, b"b"), vec![Bytes::from("1,2")]);
+    assert_eq!(stored_versions(&storage, b"c"), vec![Bytes::from("1,2")]);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3,4")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("1,2")));
+
+    // a merge that results in an empty value is folded into an empty value
+    storage.merge(b"b", b"").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(stored_versions(&storage, b"b"), vec![Bytes::new()]);
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::new()));
+}
+
+#[test]
+fn test_merge_compaction_range_tombstone_outside_inputs() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_merge_operator();
+    options.compaction_options = CompactionOptions::Leveled(LeveledCompactionOptions {
+        level_size_multiplier: 2,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 4,
+        base_level_size_mb: 1,
+    });
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    // the SST of the range tombstone does not overlap the one of the value it deletes, so both
+    // are moved to the bottom level
+    storage.delete_range(b"0", b"b").unwrap();
+    storage.force_flush().unwrap();
+    std::thread::sleep(Duration::from_secs(1));
+    let tombstone_sst_ids = storage
+        .inner
+        .state
+        .read()
+        .sstables
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+
+    // the merge is compacted with the deleted value but not with the range tombstone, and is
+    // not folded into the deleted value
+    storage.merge(b"a", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"x", b"1").unwrap();
+    storage.force_flush().unwrap();
+    std::thread::sleep(Duration::from_secs(1));
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    assert_eq!(
+        tombstone_sst_ids
+            .iter()
+            .filter(|id| state.sstables.contains_key(id))
+            .count(),
+        1
+    );
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_txn_merge() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_merge_operator();
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.merge(b"a", b"2").unwrap();
+    txn1.merge(b"a", b"3").unwrap();
+    // a txn sees its own merges
+    assert_eq!(txn1.get(b"a").unwrap(), Some(Bytes::from("1,2,3")));
+    txn2.merge(b"a", b"4").unwrap();
+    txn2.put(b"b", b"1");
+    txn2.merge(b"b", b"2").unwrap();
+    txn1.commit().unwrap();
+    // merges do not read the key, so they do not conflict
+    txn2.commit().unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3,4")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("1,2")));
+
+    // a merge through the storage goes through a txn in serializable mode
+    storage.merge(b"a", b"5").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1,2,3,4,5")));
+}
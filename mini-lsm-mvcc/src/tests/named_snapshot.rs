@@ -0,0 +1,120 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::key_of;
+
+#[test]
+fn test_named_snapshot() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), b"v1").unwrap();
+    }
+    let read_ts = storage.create_snapshot("nightly").unwrap();
+    assert!(storage.create_snapshot("nightly").is_err());
+    for idx in 0..100 {
+        if idx % 2 == 0 {
+            storage.put(&key_of(idx), b"v2").unwrap();
+        } else {
+            storage.delete(&key_of(idx)).unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(
+        storage.snapshots().into_iter().collect::<Vec<_>>(),
+        vec![("nightly".to_string(), read_ts)]
+    );
+
+    let check_snapshot = |storage: &MiniLsm| {
+        let txn = storage.open_snapshot("nightly").unwrap();
+        for idx in 0..100 {
+            assert_eq!(txn.get(&key_of(idx)).unwrap(), Some(Bytes::from("v1")));
+        }
+        let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+        let mut cnt = 0;
+        while iter.is_valid() {
+            assert_eq!(iter.value(), b"v1");
+            cnt += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(cnt, 100);
+    };
+    check_snapshot(&storage);
+    storage.close().unwrap();
+    drop(storage);
+
+    // the snapshot is kept across restarts, and compaction keeps the versions it sees
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.snapshots()["nightly"], read_ts);
+    check_snapshot(&storage);
+    storage.force_full_compaction().unwrap();
+    check_snapshot(&storage);
+    assert_eq!(storage.get(&key_of(1)).unwrap(), None);
+    assert_eq!(storage.get(&key_of(2)).unwrap(), Some(Bytes::from("v2")));
+
+    // a txn opened from the snapshot can be used after the snapshot is deleted
+    let txn = storage.open_snapshot("nightly").unwrap();
+    storage.delete_snapshot("nightly").unwrap();
+    assert!(storage.delete_snapshot("nightly").is_err());
+    assert!(storage.open_snapshot("nightly").is_err());
+    assert!(storage.snapshots().is_empty());
+    assert_eq!(txn.get(&key_of(1)).unwrap(), Some(Bytes::from("v1")));
+    txn.commit().unwrap();
+}
+
+#[test]
+fn test_named_snapshot_after_manifest_rotation() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.max_manifest_size = 256;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let first_ts = storage.create_snapshot("first").unwrap();
+    storage.put(b"a", b"2").unwrap();
+    storage.create_snapshot("second").unwrap();
+    storage.delete_snapshot("second").unwrap();
+    // the manifest is rotated, and its snapshot has the named snapshots
+    for idx in 0..10 {
+        storage.put(&key_of(idx), b"v").unwrap();
+        storage.force_flush().unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.snapshots().into_iter().collect::<Vec<_>>(),
+        vec![("first".to_string(), first_ts)]
+    );
+    let txn = storage.open_snapshot("first").unwrap();
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_named_snapshot_without_wal() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    assert!(!options.enable_wal);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let read_ts = storage.create_snapshot("nightly").unwrap();
+    // a crash, which loses the memtables without the WAL
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let txn = storage.open_snapshot("nightly").unwrap();
+    assert_eq!(txn.read_ts, read_ts);
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(txn.get(b"b").unwrap(), None);
+}
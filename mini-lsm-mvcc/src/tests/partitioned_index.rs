@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    block_cache::BlockCache,
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::{KeyBytes, KeySlice},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{BlockMeta, FileObject, IndexPartition, SsTable, SsTableBuilder, SsTableIterator},
+    varint::LengthEncoding,
+};
+
+use super::harness::{check_iter_result_by_key, key_of};
+
+fn value_of(idx: usize) -> Bytes {
+    Bytes::from(format!("value_{:05}", idx))
+}
+
+fn build_partitioned_sst(dir: &tempfile::TempDir, block_cache: Option<Arc<BlockCache>>) -> SsTable {
+    let mut builder = SsTableBuilder::new(128);
+    builder.set_index_partition_size(256);
+    for idx in 0..1000 {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+            &value_of(idx),
+        );
+    }
+    builder
+        .build(0, block_cache, dir.path().join("0.sst"))
+        .unwrap()
+}
+
+#[test]
+fn test_partitioned_index_roundtrip() {
+    let dir = tempdir().unwrap();
+    let sst = build_partitioned_sst(&dir, None);
+    assert!(sst.num_of_index_partitions() > 1);
+    assert!(sst.num_of_blocks() > sst.num_of_index_partitions());
+    assert!(sst.block_meta.is_empty());
+
+    let reopened = SsTable::open(
+        0,
+        None,
+        FileObject::open(&dir.path().join("0.sst")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(reopened.index_partitions, sst.index_partitions);
+    assert_eq!(reopened.num_of_blocks(), sst.num_of_blocks());
+    assert_eq!(reopened.first_key(), sst.first_key());
+    assert_eq!(reopened.last_key(), sst.last_key());
+
+    let sst = Arc::new(reopened);
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+    check_iter_result_by_key(
+        &mut iter,
+        (0..1000).map(|idx| (key_of(idx), value_of(idx))).collect(),
+    );
+    for idx in (0..1000).step_by(37) {
+        let iter = SsTableIterator::create_and_seek_to_key(
+            sst.clone(),
+            KeySlice::for_testing_from_slice_no_ts(&key_of(idx)),
+        )
+        .unwrap();
+        assert_eq!(iter.key().key_ref(), key_of(idx));
+        assert_eq!(iter.value(), value_of(idx));
+    }
+    let mut iter = SsTableIterator::create_and_seek_to_last(sst).unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(999));
+    iter.prev().unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(998));
+}
+
+#[test]
+fn test_index_partitions_in_block_cache() {
+    let dir = tempdir().unwrap();
+    let block_cache = Arc::new(BlockCache::new(1 << 20));
+    let sst = Arc::new(build_partitioned_sst(&dir, Some(block_cache.clone())));
+    assert_eq!(block_cache.stats().entry_count, 0);
+
+    let key = key_of(500);
+    let iter = SsTableIterator::create_and_seek_to_key(
+        sst.clone(),
+        KeySlice::for_testing_from_slice_no_ts(&key),
+    )
+    .unwrap();
+    assert_eq!(iter.key().key_ref(), key);
+    // the index partition is missed to find the block, then hit to find the offset of the missed
+    // data block
+    let stats = block_cache.stats();
+    assert_eq!(stats.misses, 2);
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.entry_count, 2);
+
+    block_cache.invalidate_sst(&sst);
+    assert_eq!(block_cache.stats().entry_count, 0);
+}
+
+#[test]
+fn test_index_partition_64bit_offsets() {
+    let block_meta = vec![
+        BlockMeta {
+            offset: 5 << 30,
+            first_key: KeyBytes::for_testing_from_bytes_no_ts(key_of(0)),
+            last_key: KeyBytes::for_testing_from_bytes_no_ts(key_of(1)),
+        },
+        BlockMeta {
+            offset: (5 << 30) + 4096,
+            first_key: KeyBytes::for_testing_from_bytes_no_ts(key_of(2)),
+            last_key: KeyBytes::for_testing_from_bytes_no_ts(key_of(3)),
+        },
+    ];
+    let mut buf = Vec::new();
+    IndexPartition::encode_index_partition(&block_meta, 6 << 30, LengthEncoding::Varint, &mut buf);
+    let partition = IndexPartition::decode_index_partition(&buf, LengthEncoding::Varint).unwrap();
+    assert_eq!(partition.block_meta, block_meta);
+    assert_eq!(partition.data_end, 6 << 30);
+
+    let last = buf.len() - 5;
+    buf[last] ^= 1;
+    assert!(IndexPartition::decode_index_partition(&buf, LengthEncoding::Varint).is_err());
+}
+
+#[test]
+fn test_storage_with_partitioned_index() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    // a few blocks per partition
+    options.index_partition_size = Some(128);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..2 {
+        for idx in 0..1000 {
+            let value = format!("value_{}_{}", idx, round);
+            storage.put(&key_of(idx), value.as_bytes()).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let check = |storage: &MiniLsm| {
+        for idx in (0..1000).step_by(7) {
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(Bytes::from(format!("value_{}_1", idx)))
+            );
+        }
+        let mut iter = storage
+            .scan(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+            .unwrap();
+        let mut count = 0;
+        while iter.is_valid() {
+            count += 1;
+            iter.next().unwrap();
+        }
+        assert_eq!(count, 1000);
+    };
+    check(&storage);
+    storage.force_full_compaction().unwrap();
+    {
+        let state = storage.inner.state.read();
+        assert!(!state.sstables.is_empty());
+        assert!(state
+            .sstables
+            .values()
+            .all(|sst| sst.num_of_index_partitions() > 1));
+    }
+    check(&storage);
+}
@@ -0,0 +1,156 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mvcc::txn::TxnIterator,
+    prefix_extractor::{prefix_upper_bound, FixedPrefixExtractor, PrefixExtractor},
+    table::bloom::Bloom,
+};
+
+fn options_with_prefix_extractor() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.prefix_extractor = Some(Arc::new(FixedPrefixExtractor::new(4)));
+    options
+}
+
+fn collect_keys(mut iter: TxnIterator) -> Vec<Bytes> {
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    keys
+}
+
+/// Flush one SST per prefix. Every SST also has the smallest and the largest key, so that their
+/// key ranges overlap and only the prefix bloom filters can tell them apart.
+fn put_prefixes(storage: &MiniLsm, prefixes: &[&str]) {
+    for prefix in prefixes {
+        storage.put(b"0000", b"min").unwrap();
+        for idx in 0..3 {
+            let key = format!("{}_{}", prefix, idx);
+            storage.put(key.as_bytes(), b"value").unwrap();
+        }
+        storage.put(b"zzzz", b"max").unwrap();
+        storage.force_flush().unwrap();
+    }
+}
+
+#[test]
+fn test_prefix_extractor() {
+    let extractor = FixedPrefixExtractor::new(4);
+    assert_eq!(extractor.prefix(b"user_1"), Some(&b"user"[..]));
+    assert_eq!(extractor.prefix(b"usr"), None);
+    assert_eq!(extractor.name(), "fixed:4");
+    assert_eq!(
+        prefix_upper_bound(b"ab\xff"),
+        Bound::Excluded(Bytes::from("ac"))
+    );
+    assert_eq!(prefix_upper_bound(b"\xff\xff"), Bound::Unbounded);
+}
+
+#[test]
+fn test_bloom_with_prefix_extractor_encoding() {
+    let hashes = [b"user".as_slice(), b"user_1"]
+        .map(farmhash::fingerprint32)
+        .to_vec();
+    let mut bloom = Bloom::build_from_key_hashes(&hashes, 10);
+    let mut plain = Vec::new();
+    bloom.encode(&mut plain);
+    assert_eq!(Bloom::decode(&plain).unwrap().prefix_extractor, None);
+
+    bloom.prefix_extractor = Some("fixed:4".to_string());
+    let mut buf = Vec::new();
+    bloom.encode(&mut buf);
+    let decoded = Bloom::decode(&buf).unwrap();
+    assert_eq!(decoded.prefix_extractor.as_deref(), Some("fixed:4"));
+    assert_eq!(decoded.k, bloom.k);
+    assert_eq!(decoded.filter, bloom.filter);
+    assert!(decoded.may_contain(farmhash::fingerprint32(b"user")));
+}
+
+#[test]
+fn test_prefix_scan() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options_with_prefix_extractor()).unwrap();
+    put_prefixes(&storage, &["aaaa", "bbbb", "cccc"]);
+    storage.put(b"bbbb_9", b"value").unwrap();
+    storage.delete(b"bbbb_1").unwrap();
+    storage.put(b"bbbc", b"value").unwrap();
+    assert_eq!(
+        collect_keys(storage.prefix_scan(b"bbbb").unwrap()),
+        vec![
+            Bytes::from("bbbb_0"),
+            Bytes::from("bbbb_2"),
+            Bytes::from("bbbb_9")
+        ]
+    );
+    // a prefix longer than the extracted one
+    assert_eq!(
+        collect_keys(storage.prefix_scan(b"aaaa_2").unwrap()),
+        vec![Bytes::from("aaaa_2")]
+    );
+    // a prefix shorter than the extracted one cannot use the filters
+    assert_eq!(collect_keys(storage.prefix_scan(b"cc").unwrap()).len(), 3);
+    assert!(collect_keys(storage.prefix_scan(b"dddd").unwrap()).is_empty());
+}
+
+#[test]
+fn test_prefix_scan_skips_ssts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options_with_prefix_extractor()).unwrap();
+    let prefixes = [
+        "aaaa", "bbbb", "cccc", "dddd", "eeee", "ffff", "gggg", "hhhh",
+    ];
+    put_prefixes(&storage, &prefixes);
+
+    let block_reads = |storage: &MiniLsm| {
+        let stats = storage.block_cache_stats();
+        stats.hits + stats.misses
+    };
+    let reads = block_reads(&storage);
+    assert_eq!(collect_keys(storage.prefix_scan(b"cccc").unwrap()).len(), 3);
+    assert_eq!(block_reads(&storage) - reads, 1);
+
+    // the same range without the prefix reads a block of every SST
+    let reads = block_reads(&storage);
+    let keys = collect_keys(
+        storage
+            .scan(Bound::Included(b"cccc"), Bound::Excluded(b"cccd"))
+            .unwrap(),
+    );
+    assert_eq!(keys.len(), 3);
+    assert_eq!(block_reads(&storage) - reads, prefixes.len() as u64);
+}
+
+#[test]
+fn test_prefix_scan_ssts_without_prefix_filter() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction),
+    )
+    .unwrap();
+    put_prefixes(&storage, &["aaaa", "bbbb"]);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options_with_prefix_extractor()).unwrap();
+    put_prefixes(&storage, &["cccc"]);
+    assert_eq!(collect_keys(storage.prefix_scan(b"aaaa").unwrap()).len(), 3);
+    assert_eq!(collect_keys(storage.prefix_scan(b"cccc").unwrap()).len(), 3);
+
+    // SSTs of another extractor are not skipped either
+    storage.close().unwrap();
+    drop(storage);
+    let mut options = options_with_prefix_extractor();
+    options.prefix_extractor = Some(Arc::new(FixedPrefixExtractor::new(2)));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(collect_keys(storage.prefix_scan(b"cccc").unwrap()).len(), 3);
+}
@@ -0,0 +1,189 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    range_tombstone::RangeTombstone,
+};
+
+use super::harness::{
+    check_iter_result_by_key, check_lsm_iter_result_by_key, construct_merge_iterator_over_storage,
+};
+
+#[test]
+fn test_delete_range() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for key in ["a", "b", "c", "d", "e"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    storage.delete_range(b"b", b"d").unwrap();
+    storage.put(b"c", b"2").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get(b"d").unwrap(), Some(Bytes::from("1")));
+    check_lsm_iter_result_by_key(
+        &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("c"), Bytes::from("2")),
+            (Bytes::from("d"), Bytes::from("1")),
+            (Bytes::from("e"), Bytes::from("1")),
+        ],
+    );
+
+    // the tombstone lives in an SST after flushing
+    storage.force_flush().unwrap();
+    storage.put(b"b0", b"1").unwrap();
+    storage.force_flush().unwrap();
+    check_lsm_iter_result_by_key(
+        &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b0"), Bytes::from("1")),
+            (Bytes::from("c"), Bytes::from("2")),
+            (Bytes::from("d"), Bytes::from("1")),
+            (Bytes::from("e"), Bytes::from("1")),
+        ],
+    );
+}
+
+#[test]
+fn test_range_tombstone_overlaps_range() {
+    let tombstone = RangeTombstone::new(Bytes::from("b"), Bytes::from("d"), 1);
+    let overlaps = |lower, upper| tombstone.overlaps_range(lower, upper);
+    assert!(overlaps(Bound::Unbounded, Bound::Unbounded));
+    assert!(overlaps(Bound::Included(b"c"), Bound::Included(b"c")));
+    assert!(overlaps(Bound::Included(b"a"), Bound::Included(b"b")));
+    assert!(!overlaps(Bound::Included(b"a"), Bound::Excluded(b"b")));
+    assert!(!overlaps(Bound::Included(b"d"), Bound::Unbounded));
+    assert!(!overlaps(Bound::Excluded(b"d"), Bound::Unbounded));
+    assert!(overlaps(Bound::Excluded(b"c"), Bound::Unbounded));
+    assert!(!overlaps(Bound::Unbounded, Bound::Included(b"a")));
+}
+
+#[test]
+fn test_delete_range_in_write_batch() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put("a", "1"),
+            WriteBatchRecord::Put("b", "1"),
+            WriteBatchRecord::DelRange("a", "c"),
+            WriteBatchRecord::Put("b", "2"),
+        ])
+        .unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_txn_delete_range() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    let snapshot = storage.new_txn().unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"2");
+    txn.delete_range(b"a", b"c");
+    txn.put(b"a", b"2");
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(txn.get(b"b").unwrap(), None);
+    check_lsm_iter_result_by_key(
+        &mut txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("2")),
+            (Bytes::from("c"), Bytes::from("1")),
+            (Bytes::from("d"), Bytes::from("1")),
+        ],
+    );
+    txn.commit().unwrap();
+
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    // the snapshot still sees the deleted keys
+    check_lsm_iter_result_by_key(
+        &mut snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("1")),
+            (Bytes::from("c"), Bytes::from("1")),
+            (Bytes::from("d"), Bytes::from("1")),
+        ],
+    );
+}
+
+#[test]
+fn test_delete_range_recover() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete_range(b"b", b"d").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check_lsm_iter_result_by_key(
+        &mut storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap(),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("d"), Bytes::from("1")),
+        ],
+    );
+}
+
+#[test]
+fn test_delete_range_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for key in ["a", "b", "c", "d"] {
+        storage.put(key.as_bytes(), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let snapshot = storage.new_txn().unwrap();
+    storage.delete_range(b"b", b"d").unwrap();
+    storage.force_flush().unwrap();
+
+    // the tombstone and the deleted keys are kept while the snapshot is alive
+    storage.force_full_compaction().unwrap();
+    assert_eq!(snapshot.get(b"b").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    let num_tombstones = |storage: &MiniLsm| {
+        let state = storage.inner.state.read();
+        state
+            .sstables
+            .values()
+            .map(|sst| sst.range_tombstones().len())
+            .sum::<usize>()
+    };
+    assert_eq!(num_tombstones(&storage), 1);
+
+    drop(snapshot);
+    storage.force_full_compaction().unwrap();
+    assert_eq!(num_tombstones(&storage), 0);
+    let mut iter = construct_merge_iterator_over_storage(&storage.inner.state.read());
+    check_iter_result_by_key(
+        &mut iter,
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("d"), Bytes::from("1")),
+        ],
+    );
+}
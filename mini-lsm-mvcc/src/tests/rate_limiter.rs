@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    rate_limiter::{IoPriority, RateLimiter},
+};
+
+#[test]
+fn test_rate_limiter_throughput() {
+    let rate_limiter = RateLimiter::new(10 << 20);
+    let start = Instant::now();
+    for _ in 0..32 {
+        rate_limiter.request(64 << 10, IoPriority::Low);
+    }
+    // 2MB at 10MB/s, of which the last request is paid back by later ones
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(rate_limiter.total_bytes(), 2 << 20);
+}
+
+#[test]
+fn test_rate_limiter_priority() {
+    let rate_limiter = Arc::new(RateLimiter::new(1 << 20));
+    // run into debt, so that the following requests have to wait
+    rate_limiter.request(512 << 10, IoPriority::Low);
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let spawn = |priority: IoPriority| {
+        let rate_limiter = rate_limiter.clone();
+        let order = order.clone();
+        std::thread::spawn(move || {
+            rate_limiter.request(64 << 10, priority);
+            order.lock().push(priority);
+        })
+    };
+    let low = spawn(IoPriority::Low);
+    std::thread::sleep(Duration::from_millis(50));
+    let high = spawn(IoPriority::High);
+    low.join().unwrap();
+    high.join().unwrap();
+    assert_eq!(*order.lock(), vec![IoPriority::High, IoPriority::Low]);
+}
+
+#[test]
+fn test_rate_limited_flush_and_compaction() {
+    let dir = tempdir().unwrap();
+    let rate_limiter = Arc::new(RateLimiter::new(64 << 20));
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.rate_limiter = Some(rate_limiter.clone());
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for round in 0..2 {
+        for key in 0..1000 {
+            let key = format!("key_{:05}", key);
+            let value = format!("value_{}", round);
+            storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    let flushed = rate_limiter.total_bytes();
+    assert!(flushed > 0);
+    storage.force_full_compaction().unwrap();
+    assert!(rate_limiter.total_bytes() > flushed);
+    assert_eq!(
+        storage.get(b"key_00500").unwrap(),
+        Some(Bytes::from("value_1"))
+    );
+}
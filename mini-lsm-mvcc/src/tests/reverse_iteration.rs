@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTableIterator,
+};
+
+use super::harness::{generate_sst_with_ts, key_of};
+
+fn collect_backward<I>(iter: &mut I) -> Vec<(Bytes, Bytes)>
+where
+    I: for<'a> StorageIterator<KeyType<'a> = &'a [u8]>,
+{
+    iter.seek_to_last().unwrap();
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.prev().unwrap();
+    }
+    result
+}
+
+fn expected_backward(
+    model: &BTreeMap<Bytes, Bytes>,
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> Vec<(Bytes, Bytes)> {
+    let lower = lower.map(Bytes::copy_from_slice);
+    let upper = upper.map(Bytes::copy_from_slice);
+    model
+        .range((lower, upper))
+        .rev()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect()
+}
+
+#[test]
+fn test_sst_iterator_prev() {
+    let dir = tempdir().unwrap();
+    let data = (0..100)
+        .map(|idx| ((key_of(idx), 1), Bytes::from(format!("value_{}", idx))))
+        .collect::<Vec<_>>();
+    let sst = Arc::new(generate_sst_with_ts(
+        1,
+        dir.path().join("1.sst"),
+        data.clone(),
+        None,
+    ));
+    assert!(sst.num_of_blocks() > 1);
+    let mut iter = SsTableIterator::create_and_seek_to_last(sst).unwrap();
+    for ((key, _), value) in data.iter().rev() {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().key_ref(), key);
+        assert_eq!(iter.value(), value);
+        iter.prev().unwrap();
+    }
+    assert!(!iter.is_valid());
+
+    iter.seek(KeySlice::for_testing_from_slice_with_ts(&key_of(50), 1))
+        .unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(50));
+    iter.prev().unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(49));
+    iter.next().unwrap();
+    assert_eq!(iter.key().key_ref(), key_of(50));
+}
+
+#[test]
+fn test_reverse_scan() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let mut model = BTreeMap::new();
+    // spread versions of the keys over the levels, L0 SSTs, and the memtable
+    for round in 0..4 {
+        for idx in 0..100 {
+            if (idx + round) % 3 == 0 {
+                storage
+                    .put(&key_of(idx), format!("{}@{}", idx, round).as_bytes())
+                    .unwrap();
+                model.insert(key_of(idx), Bytes::from(format!("{}@{}", idx, round)));
+            } else if (idx + round) % 7 == 0 {
+                storage.delete(&key_of(idx)).unwrap();
+                model.remove(&key_of(idx));
+            }
+        }
+        if round != 3 {
+            storage.force_flush().unwrap();
+        }
+        if round == 1 {
+            storage.force_full_compaction().unwrap();
+        }
+    }
+    storage.delete_range(&key_of(40), &key_of(45)).unwrap();
+    model.retain(|key, _| !(key_of(40) <= key && key < &key_of(45)));
+
+    for (lower, upper) in [
+        (Bound::Unbounded, Bound::Unbounded),
+        (
+            Bound::Included(&key_of(10)[..]),
+            Bound::Included(&key_of(60)[..]),
+        ),
+        (
+            Bound::Excluded(&key_of(10)[..]),
+            Bound::Excluded(&key_of(60)[..]),
+        ),
+        (
+            Bound::Included(&b"key_00050a"[..]),
+            Bound::Excluded(&b"key_0007"[..]),
+        ),
+    ] {
+        let mut iter = storage.scan(lower, upper).unwrap();
+        let expected = expected_backward(&model, lower, upper);
+        assert_eq!(collect_backward(&mut iter), expected);
+
+        // walk back and forth, changing direction at every step
+        let expected = expected.into_iter().rev().collect::<Vec<_>>();
+        let mut pos = expected.len() / 2;
+        iter.seek(&expected[pos].0).unwrap();
+        for step in [1, 1, -1, -1, -1, 1, -1, 1, 1, 1, -1] {
+            if step > 0 {
+                iter.next().unwrap();
+                pos += 1;
+            } else {
+                iter.prev().unwrap();
+                pos -= 1;
+            }
+            let (key, value) = &expected[pos];
+            assert_eq!(iter.key(), key);
+            assert_eq!(iter.value(), value);
+        }
+    }
+}
+
+#[test]
+fn test_seek_and_change_direction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in (0..50).step_by(2) {
+        storage.put(&key_of(idx), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in (1..50).step_by(2) {
+        storage.put(&key_of(idx), b"2").unwrap();
+    }
+    let snapshot = storage.new_txn().unwrap();
+    for idx in (0..50).step_by(5) {
+        storage.put(&key_of(idx), b"3").unwrap();
+    }
+    storage.delete(&key_of(21)).unwrap();
+
+    let mut iter = storage
+        .scan(Bound::Included(&key_of(10)), Bound::Excluded(&key_of(30)))
+        .unwrap();
+    iter.seek(&key_of(20)).unwrap();
+    assert_eq!(iter.key(), key_of(20));
+    assert_eq!(iter.value(), b"3");
+    iter.next().unwrap();
+    assert_eq!(iter.key(), key_of(22));
+    iter.prev().unwrap();
+    assert_eq!(iter.key(), key_of(20));
+    iter.prev().unwrap();
+    assert_eq!(iter.key(), key_of(19));
+    assert_eq!(iter.value(), b"2");
+    iter.next().unwrap();
+    assert_eq!(iter.key(), key_of(20));
+    // seeking outside of the range stays within the range
+    iter.seek(&key_of(0)).unwrap();
+    assert_eq!(iter.key(), key_of(10));
+    iter.prev().unwrap();
+    assert!(!iter.is_valid());
+    iter.seek(&key_of(30)).unwrap();
+    assert!(!iter.is_valid());
+    iter.seek_to_last().unwrap();
+    assert_eq!(iter.key(), key_of(29));
+
+    // a snapshot sees the old versions and the deleted key in both directions
+    let mut iter = snapshot
+        .scan(Bound::Included(&key_of(20)), Bound::Included(&key_of(25)))
+        .unwrap();
+    assert_eq!(
+        collect_backward(&mut iter),
+        vec![
+            (key_of(25), Bytes::from("2")),
+            (key_of(24), Bytes::from("1")),
+            (key_of(23), Bytes::from("2")),
+            (key_of(22), Bytes::from("1")),
+            (key_of(21), Bytes::from("2")),
+            (key_of(20), Bytes::from("1")),
+        ]
+    );
+}
+
+#[test]
+fn test_txn_reverse_scan() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.put(b"e", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"2");
+    txn.put(b"c", b"2");
+    txn.delete(b"e");
+    let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert_eq!(
+        collect_backward(&mut iter),
+        vec![
+            (Bytes::from("c"), Bytes::from("2")),
+            (Bytes::from("b"), Bytes::from("2")),
+            (Bytes::from("a"), Bytes::from("1")),
+        ]
+    );
+    iter.seek(b"b").unwrap();
+    assert_eq!(iter.key(), b"b");
+    iter.next().unwrap();
+    assert_eq!(iter.key(), b"c");
+    assert_eq!(iter.value(), b"2");
+    iter.prev().unwrap();
+    assert_eq!(iter.key(), b"b");
+    iter.prev().unwrap();
+    assert_eq!(iter.key(), b"a");
+}
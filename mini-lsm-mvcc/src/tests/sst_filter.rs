@@ -0,0 +1,179 @@
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    filter::{FilterOptions, FilterType},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::bloom::Bloom,
+};
+
+fn key_hashes(range: std::ops::Range<usize>) -> Vec<u32> {
+    range
+        .map(|idx| farmhash::fingerprint32(format!("key_{:08}", idx).as_bytes()))
+        .collect()
+}
+
+fn false_positive_rate(filter: &Bloom) -> f64 {
+    let absent = key_hashes(1_000_000..1_100_000);
+    let positives = absent.iter().filter(|h| filter.may_contain(**h)).count();
+    positives as f64 / absent.len() as f64
+}
+
+#[test]
+fn test_filter_types() {
+    let keys = key_hashes(0..10000);
+    for (filter_type, max_fp_rate, max_bits_per_key) in [
+        (FilterType::Bloom(10), 0.02, 10.1),
+        (FilterType::BlockedBloom(10), 0.03, 10.3),
+        (FilterType::Xor8, 0.01, 10.0),
+    ] {
+        let filter = Bloom::build(&keys, filter_type);
+        assert_eq!(filter.filter_type, filter_type);
+        for h in &keys {
+            assert!(
+                filter.may_contain(*h),
+                "{:?} has a false negative",
+                filter_type
+            );
+        }
+        let fp_rate = false_positive_rate(&filter);
+        assert!(
+            fp_rate < max_fp_rate,
+            "{:?} false positive rate {}",
+            filter_type,
+            fp_rate
+        );
+        let bits_per_key = (filter.filter.len() * 8) as f64 / keys.len() as f64;
+        assert!(
+            bits_per_key < max_bits_per_key,
+            "{:?} takes {} bits per key",
+            filter_type,
+            bits_per_key
+        );
+    }
+}
+
+#[test]
+fn test_filter_type_encoding() {
+    let keys = key_hashes(0..1000);
+    let bloom = Bloom::build(&keys, FilterType::Bloom(10));
+    let mut buf = Vec::new();
+    bloom.encode(&mut buf);
+    // the standard bloom filter is encoded without a tag, as before
+    assert_eq!(buf.len(), bloom.filter.len() + 5);
+    assert_eq!(buf[buf.len() - 5], bloom.k);
+
+    for filter_type in [FilterType::BlockedBloom(8), FilterType::Xor8] {
+        let mut filter = Bloom::build(&keys, filter_type);
+        filter.prefix_extractor = Some("fixed:4".to_string());
+        let mut buf = Vec::new();
+        filter.encode(&mut buf);
+        let decoded = Bloom::decode(&buf).unwrap();
+        assert_eq!(decoded.filter_type.to_byte(), filter_type.to_byte());
+        assert_eq!(decoded.k, filter.k);
+        assert_eq!(decoded.filter, filter.filter);
+        assert_eq!(decoded.prefix_extractor.as_deref(), Some("fixed:4"));
+        for h in &keys {
+            assert!(decoded.may_contain(*h));
+        }
+    }
+}
+
+#[test]
+fn test_filter_per_level() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.filter = FilterOptions {
+        per_level: vec![FilterType::BlockedBloom(10), FilterType::Xor8],
+    };
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..2 {
+        for idx in 0..1000 {
+            let key = format!("key_{:05}", idx);
+            let value = format!("value_{}", round);
+            storage.put(key.as_bytes(), value.as_bytes()).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    let filter_types = |storage: &MiniLsm| {
+        let state = storage.inner.state.read();
+        let l0 = state
+            .l0_sstables
+            .iter()
+            .map(|id| {
+                state.sstables[id]
+                    .bloom
+                    .as_ref()
+                    .unwrap()
+                    .filter_type
+                    .to_byte()
+            })
+            .collect::<Vec<_>>();
+        let l1 = state.levels[0]
+            .1
+            .iter()
+            .map(|id| {
+                state.sstables[id]
+                    .bloom
+                    .as_ref()
+                    .unwrap()
+                    .filter_type
+                    .to_byte()
+            })
+            .collect::<Vec<_>>();
+        (l0, l1)
+    };
+    assert_eq!(
+        filter_types(&storage),
+        (vec![FilterType::BlockedBloom(0).to_byte(); 2], vec![])
+    );
+    storage.force_full_compaction().unwrap();
+    let (l0, l1) = filter_types(&storage);
+    assert!(l0.is_empty());
+    assert!(!l1.is_empty());
+    assert!(l1.iter().all(|tag| *tag == FilterType::Xor8.to_byte()));
+    for idx in (0..1000).step_by(13) {
+        let key = format!("key_{:05}", idx);
+        assert_eq!(
+            storage.get(key.as_bytes()).unwrap(),
+            Some(Bytes::from("value_1"))
+        );
+    }
+}
+
+#[test]
+fn test_filter_stats() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    // a filter of one bit per key lets through many absent keys
+    options.filter = FilterOptions::all_levels(FilterType::Bloom(1));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in (0..2000).step_by(2) {
+        let key = format!("key_{:05}", idx);
+        storage.put(key.as_bytes(), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    for idx in (0..2000).step_by(2) {
+        let key = format!("key_{:05}", idx);
+        assert!(storage.get(key.as_bytes()).unwrap().is_some());
+    }
+    let stats = storage.filter_stats();
+    assert_eq!(stats.checks, 1000);
+    assert_eq!(stats.negatives, 0);
+    assert_eq!(stats.false_positives, 0);
+
+    // the keys within the range of the SST, so that the filter is checked
+    for idx in (1..1998).step_by(2) {
+        let key = format!("key_{:05}", idx);
+        assert!(storage.get(key.as_bytes()).unwrap().is_none());
+    }
+    let stats = storage.filter_stats();
+    assert_eq!(stats.checks, 1999);
+    assert_eq!(stats.negatives + stats.false_positives, 999);
+    assert!(stats.negatives > 0);
+    assert!(stats.false_positives > 0);
+    let fp_rate = stats.false_positive_rate();
+    assert!(fp_rate > 0.0 && fp_rate < 1.0);
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::{tempdir, TempDir};
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::{key_of, scan_pairs};
+
+/// Write overlapping SSTs with overwrites, deletes and range deletes, compact them, and return the
+/// storage with the key-value pairs it holds.
+fn compact_with_subcompactions(
+    max_subcompactions: usize,
+) -> (TempDir, Arc<MiniLsm>, Vec<(Bytes, Bytes)>) {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.block_size = 256;
+    options.target_sst_size = 4096;
+    options.max_subcompactions = max_subcompactions;
+    // flush the memtables here rather than in the flush thread, which may add SSTs to L0 after the
+    // compaction
+    options.num_memtable_limit = 50;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for round in 0..3 {
+        for key in (round..1000).step_by(3) {
+            let value = format!("value_{}_{}", key, round);
+            storage.put(&key_of(key), value.as_bytes()).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    for key in (0..1000).step_by(7) {
+        storage.delete(&key_of(key)).unwrap();
+    }
+    storage.delete_range(b"key_00100", b"key_00600").unwrap();
+    storage.force_flush().unwrap();
+    while !storage.inner.state.read().imm_memtables.is_empty() {
+        storage.inner.force_flush_next_imm_memtable().unwrap();
+    }
+    storage.force_full_compaction().unwrap();
+
+    let result = scan_pairs(&storage);
+    (dir, storage, result)
+}
+
+#[test]
+fn test_subcompactions() {
+    let (_, _, expected) = compact_with_subcompactions(1);
+    let (_dir, storage, result) = compact_with_subcompactions(4);
+    assert_eq!(result.len(), expected.len());
+    assert_eq!(result, expected);
+    assert!(result
+        .iter()
+        .all(|(key, _)| key.as_ref() < b"key_00100".as_slice()
+            || key.as_ref() >= b"key_00600".as_slice()));
+
+    // the outputs of the subcompactions form one sorted run
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    let ssts = state.levels[0]
+        .1
+        .iter()
+        .map(|id| state.sstables[id].clone())
+        .collect::<Vec<_>>();
+    assert!(ssts.len() >= 4);
+    for pair in ssts.windows(2) {
+        assert!(pair[0].last_key().key_ref() < pair[1].first_key().key_ref());
+    }
+    assert_eq!(
+        storage.get(b"key_00002").unwrap(),
+        Some(Bytes::from("value_2_2"))
+    );
+    assert_eq!(storage.get(b"key_00007").unwrap(), None);
+}
+
+#[test]
+fn test_subcompactions_retain_range_tombstone_once() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.block_size = 256;
+    options.max_subcompactions = 4;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..1000 {
+        storage.put(&key_of(idx), &key_of(idx)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let snapshot = storage.new_txn().unwrap();
+    storage.delete_range(b"key_00100", b"key_00900").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    let num_tombstones = storage
+        .inner
+        .state
+        .read()
+        .sstables
+        .values()
+        .map(|sst| sst.range_tombstones().len())
+        .sum::<usize>();
+    assert_eq!(num_tombstones, 1);
+    assert_eq!(storage.get(b"key_00500").unwrap(), None);
+    assert_eq!(
+        snapshot.get(b"key_00500").unwrap(),
+        Some(Bytes::from("key_00500"))
+    );
+}
@@ -0,0 +1,132 @@
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mvcc::HistoryRetention,
+};
+
+use super::harness_ext::options_with_value_log;
+
+fn scan_at(storage: &MiniLsm, ts: u64) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage
+        .scan_at(Bound::Unbounded, Bound::Unbounded, ts)
+        .unwrap();
+    let mut result = Vec::new();
+    while iter.is_valid() {
+        result.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    result
+}
+
+#[test]
+fn test_get_at() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.history_retention = Some(HistoryRetention::Timestamps(100));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let ts1 = storage.latest_commit_ts();
+    storage.put(b"a", b"2").unwrap();
+    let ts2 = storage.latest_commit_ts();
+    storage.delete(b"a").unwrap();
+    storage.delete_range(b"b", b"c").unwrap();
+    let ts3 = storage.latest_commit_ts();
+    storage.force_flush().unwrap();
+    // no txn reads the old versions, but they are in the retained history
+    storage.force_full_compaction().unwrap();
+
+    assert_eq!(storage.get_at(b"a", ts1).unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get_at(b"a", ts2).unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get_at(b"a", ts3).unwrap(), None);
+    assert_eq!(storage.get_at(b"b", ts2).unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get_at(b"b", ts3).unwrap(), None);
+    assert_eq!(
+        scan_at(&storage, ts1),
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("1"))
+        ]
+    );
+    assert!(scan_at(&storage, ts3).is_empty());
+    assert!(storage.get_at(b"a", ts3 + 1).is_err());
+}
+
+#[test]
+fn test_history_retention_horizon() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.history_retention = Some(HistoryRetention::Timestamps(5));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..20 {
+        storage.put(b"key", format!("{}", idx).as_bytes()).unwrap();
+    }
+    let latest_ts = storage.latest_commit_ts();
+    assert_eq!(storage.oldest_readable_ts(), latest_ts - 5);
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for ts in (latest_ts - 5)..=latest_ts {
+        assert_eq!(
+            storage.get_at(b"key", ts).unwrap(),
+            Some(Bytes::from(format!("{}", ts - 1)))
+        );
+    }
+    let err = storage.get_at(b"key", latest_ts - 6).unwrap_err();
+    assert!(err.to_string().contains("older than the retained history"));
+
+    // a running txn keeps older history readable
+    let txn = storage.new_txn().unwrap();
+    for idx in 20..30 {
+        storage.put(b"key", format!("{}", idx).as_bytes()).unwrap();
+    }
+    assert_eq!(storage.oldest_readable_ts(), latest_ts);
+    assert_eq!(
+        storage.get_at(b"key", latest_ts).unwrap(),
+        Some(Bytes::from("19"))
+    );
+    drop(txn);
+    assert_eq!(storage.oldest_readable_ts(), latest_ts + 5);
+}
+
+#[test]
+fn test_no_history_retention() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let ts1 = storage.latest_commit_ts();
+    storage.put(b"a", b"2").unwrap();
+    assert!(storage.get_at(b"a", ts1).is_err());
+    assert_eq!(
+        storage.get_at(b"a", storage.latest_commit_ts()).unwrap(),
+        Some(Bytes::from("2"))
+    );
+}
+
+#[test]
+fn test_history_retention_duration_value_log() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_value_log();
+    options.history_retention = Some(HistoryRetention::Duration(Duration::from_secs(3600)));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let large_value = Bytes::from("1".repeat(2000));
+    storage.put(b"a", &large_value).unwrap();
+    let ts1 = storage.latest_commit_ts();
+    storage.put(b"a", b"small").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    // the old value in the value log is still in the retained history
+    assert_eq!(storage.oldest_readable_ts(), 0);
+    assert!(!storage.gc_value_log().unwrap());
+    assert_eq!(storage.get_at(b"a", ts1).unwrap(), Some(large_value));
+}
@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions, SimpleLeveledCompactionOptions},
+    compression::{CompressionOptions, CompressionType},
+    filter::{FilterOptions, FilterType},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn simple_leveled_options() -> LsmStorageOptions {
+    LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ))
+}
+
+/// Flush one SST per range of keys.
+fn flush_ranges(storage: &MiniLsm, ranges: &[std::ops::Range<usize>]) {
+    for range in ranges {
+        for key in range.clone() {
+            let key = format!("key_{:05}", key);
+            storage.put(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+}
+
+fn all_sst_ids(storage: &MiniLsm) -> Vec<usize> {
+    let mut ids = storage
+        .inner
+        .state
+        .read()
+        .sstables
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn test_trivial_move_simple_leveled() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_leveled_options();
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    flush_ranges(&storage, &[0..100, 100..200]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    // the SSTs do not overlap, so they are moved down instead of being rewritten
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    assert_eq!(all_sst_ids(&storage), flushed);
+    let mut moved = state
+        .levels
+        .iter()
+        .flat_map(|(_, ssts)| ssts)
+        .copied()
+        .collect::<Vec<_>>();
+    moved.sort();
+    assert_eq!(moved, flushed);
+    for key in [0, 99, 100, 199] {
+        let key = format!("key_{:05}", key);
+        assert_eq!(storage.get(key.as_bytes()).unwrap(), Some(Bytes::from(key)));
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    // the moves are recovered from the manifest
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(all_sst_ids(&storage), flushed);
+    assert_eq!(
+        storage.get(b"key_00150").unwrap(),
+        Some(Bytes::from("key_00150"))
+    );
+}
+
+#[test]
+fn test_trivial_move_into_non_empty_level() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple_leveled_options()).unwrap();
+    flush_ranges(&storage, &[0..100, 100..200]);
+    std::thread::sleep(Duration::from_secs(1));
+    flush_ranges(&storage, &[200..300, 300..400]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    // the SSTs of the upper level do not overlap the ones in the lower level, so they are all
+    // moved to the bottom level without being rewritten
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    assert_eq!(all_sst_ids(&storage), flushed);
+    let mut bottom_level = state.levels.last().unwrap().1.clone();
+    bottom_level.sort();
+    assert_eq!(bottom_level, flushed);
+    for key in [0, 199, 200, 399] {
+        let key = format!("key_{:05}", key);
+        assert_eq!(storage.get(key.as_bytes()).unwrap(), Some(Bytes::from(key)));
+    }
+}
+
+#[test]
+fn test_no_trivial_move_with_overlap() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple_leveled_options()).unwrap();
+    flush_ranges(&storage, &[0..100, 50..150]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    assert!(all_sst_ids(&storage).iter().all(|id| !flushed.contains(id)));
+}
+
+#[test]
+fn test_no_trivial_move_with_shared_boundary_key() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple_leveled_options()).unwrap();
+    // the older SST starts with a version of the key the newer one ends with
+    flush_ranges(&storage, &[99..200, 0..100]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    assert!(storage.inner.state.read().l0_sstables.is_empty());
+    assert!(all_sst_ids(&storage).iter().all(|id| !flushed.contains(id)));
+    assert_eq!(
+        storage.get(b"key_00099").unwrap(),
+        Some(Bytes::from("key_00099"))
+    );
+}
+
+#[test]
+fn test_no_trivial_move_with_different_compression() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_leveled_options();
+    options.compression = CompressionOptions {
+        per_level: vec![CompressionType::None, CompressionType::Lz4],
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    flush_ranges(&storage, &[0..100, 100..200]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    assert!(storage.inner.state.read().l0_sstables.is_empty());
+    assert!(all_sst_ids(&storage).iter().all(|id| !flushed.contains(id)));
+}
+
+#[test]
+fn test_no_trivial_move_with_different_filter() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_leveled_options();
+    options.filter = FilterOptions {
+        per_level: vec![FilterType::Bloom(10), FilterType::Xor8],
+    };
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    flush_ranges(&storage, &[0..100, 100..200]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    let state = storage.inner.state.read().clone();
+    assert!(state.l0_sstables.is_empty());
+    assert!(all_sst_ids(&storage).iter().all(|id| !flushed.contains(id)));
+    assert!(state
+        .sstables
+        .values()
+        .all(|sst| sst.bloom.as_ref().unwrap().filter_type == FilterType::Xor8));
+}
+
+#[test]
+fn test_trivial_move_leveled() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 2,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 1,
+            },
+        )),
+    )
+    .unwrap();
+    flush_ranges(&storage, &[0..100, 100..200, 200..300, 300..400]);
+    let flushed = all_sst_ids(&storage);
+    std::thread::sleep(Duration::from_secs(1));
+
+    assert!(storage.inner.state.read().l0_sstables.is_empty());
+    assert_eq!(all_sst_ids(&storage), flushed);
+    assert_eq!(
+        storage.get(b"key_00250").unwrap(),
+        Some(Bytes::from("key_00250"))
+    );
+}
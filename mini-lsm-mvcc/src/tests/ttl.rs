@@ -0,0 +1,122 @@
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    value_type::ValueType,
+};
+
+use super::harness::construct_merge_iterator_over_storage;
+use super::harness_ext::options_with_value_log;
+
+fn scan_keys(storage: &MiniLsm) -> Vec<Bytes> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    keys
+}
+
+#[test]
+fn test_put_with_ttl() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .put_with_ttl(b"a", b"2", Duration::from_millis(200))
+        .unwrap();
+    storage
+        .put_with_ttl(b"b", b"2", Duration::from_secs(3600))
+        .unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::PutWithTtl(&b"c"[..], &b"2"[..], Duration::from_millis(200)),
+            WriteBatchRecord::Put(&b"d"[..], &b"2"[..]),
+        ])
+        .unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(scan_keys(&storage), vec!["a", "b", "c", "d"]);
+
+    // the expiry time is kept in the WAL
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    // an expired version deletes the key, and the older versions are not visible
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get(b"c").unwrap(), None);
+    assert_eq!(scan_keys(&storage), vec!["b", "d"]);
+
+    // compaction removes the expired versions
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let mut iter = construct_merge_iterator_over_storage(&storage.inner.state.read());
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key().key_ref()));
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, vec!["b", "d"]);
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_txn_put_with_ttl() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put_with_ttl(b"a", b"1", Duration::from_millis(200));
+    txn.put_with_ttl(b"b", b"1", Duration::from_millis(200));
+    txn.put(b"b", b"2");
+    txn.put_with_ttl(b"c", b"1", Duration::from_secs(3600));
+    // the TTL starts when the txn commits
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::from("1")));
+    txn.commit().unwrap();
+    assert_eq!(scan_keys(&storage), vec!["a", "b", "c"]);
+
+    std::thread::sleep(Duration::from_millis(300));
+    let txn = storage.new_txn().unwrap();
+    assert_eq!(txn.get(b"a").unwrap(), None);
+    assert_eq!(txn.get(b"b").unwrap(), Some(Bytes::from("2")));
+    let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(Bytes::copy_from_slice(iter.key()));
+        iter.next().unwrap();
+    }
+    assert_eq!(keys, vec!["b", "c"]);
+}
+
+#[test]
+fn test_ttl_value_log_gc() {
+    let dir = tempdir().unwrap();
+    let options = options_with_value_log();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let large_value = Bytes::from("1".repeat(2000));
+    storage
+        .put_with_ttl(b"a", &large_value, Duration::from_secs(3600))
+        .unwrap();
+    storage.put(b"b", &large_value).unwrap();
+    storage.put(b"b", b"small").unwrap();
+    storage.force_flush().unwrap();
+    // the value with a TTL is live, and is moved with its expiry time
+    assert!(storage.gc_value_log().unwrap());
+    assert_eq!(storage.get(b"a").unwrap(), Some(large_value));
+    storage.force_flush().unwrap();
+    let iter = construct_merge_iterator_over_storage(&storage.inner.state.read());
+    assert_eq!(iter.key().key_ref(), b"a");
+    assert_eq!(iter.value_type(), ValueType::ValuePointerWithTtl);
+}
@@ -0,0 +1,180 @@
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mvcc::txn::TxnError,
+};
+
+use super::harness::key_of;
+
+fn open_serializable(dir: &tempfile::TempDir) -> std::sync::Arc<MiniLsm> {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    MiniLsm::open(dir, options).unwrap()
+}
+
+fn conflict_key(err: anyhow::Error) -> Bytes {
+    match err.downcast_ref::<TxnError>() {
+        Some(TxnError::Conflict { key }) => key.clone(),
+        None => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn test_conflict_on_exact_key() {
+    let dir = tempdir().unwrap();
+    let storage = open_serializable(&dir);
+    for idx in 0..1000 {
+        storage.put(&key_of(idx), b"1").unwrap();
+    }
+    // reading many keys that another txn does not write is never a conflict
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    for idx in 0..500 {
+        txn1.get(&key_of(idx)).unwrap();
+    }
+    txn1.put(b"result1", b"1");
+    for idx in 500..1000 {
+        txn2.put(&key_of(idx), b"2");
+    }
+    txn2.commit().unwrap();
+    txn1.commit().unwrap();
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get(&key_of(100)).unwrap();
+    txn1.put(b"result1", b"2");
+    txn2.put(&key_of(100), b"3");
+    txn2.commit().unwrap();
+    let err = txn1.commit().unwrap_err();
+    assert_eq!(conflict_key(err), key_of(100));
+    assert_eq!(storage.get(b"result1").unwrap(), Some(Bytes::from("1")));
+}
+
+#[test]
+fn test_phantom_conflict() {
+    let dir = tempdir().unwrap();
+    let storage = open_serializable(&dir);
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"c", b"1").unwrap();
+
+    // a key inserted into the scanned range conflicts, even though the scan did not return it
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    let mut iter = txn1
+        .scan(Bound::Included(b"a"), Bound::Excluded(b"c"))
+        .unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(cnt, 1);
+    txn1.put(b"count", b"1");
+    txn2.put(b"b", b"1");
+    txn2.commit().unwrap();
+    let err = txn1.commit().unwrap_err();
+    assert_eq!(conflict_key(err), Bytes::from("b"));
+
+    // a key inserted outside of the scanned range does not
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.scan(Bound::Included(b"a"), Bound::Excluded(b"c"))
+        .unwrap();
+    txn1.put(b"count", b"2");
+    txn2.put(b"c", b"2");
+    txn2.commit().unwrap();
+    txn1.commit().unwrap();
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.prefix_scan(b"ke").unwrap();
+    txn1.put(b"count", b"3");
+    txn2.put(b"key", b"1");
+    txn2.commit().unwrap();
+    let err = txn1.commit().unwrap_err();
+    assert_eq!(conflict_key(err), Bytes::from("key"));
+}
+
+#[test]
+fn test_delete_range_conflict() {
+    let dir = tempdir().unwrap();
+    let storage = open_serializable(&dir);
+    storage.put(b"key2", b"1").unwrap();
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.get(b"key2").unwrap();
+    txn1.put(b"result", b"1");
+    txn2.delete_range(b"key1", b"key3");
+    txn2.commit().unwrap();
+    let err = txn1.commit().unwrap_err();
+    assert_eq!(conflict_key(err), Bytes::from("key2"));
+
+    // the deleted range overlaps with the scanned range
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.scan(Bound::Excluded(b"key5"), Bound::Unbounded)
+        .unwrap();
+    txn1.put(b"result", b"2");
+    txn2.delete_range(b"key0", b"key6");
+    txn2.commit().unwrap();
+    let err = txn1.commit().unwrap_err();
+    assert_eq!(conflict_key(err), Bytes::from("key5\0"));
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.scan(Bound::Included(b"key5"), Bound::Unbounded)
+        .unwrap();
+    txn1.put(b"result", b"3");
+    txn2.delete_range(b"key0", b"key5");
+    txn2.commit().unwrap();
+    txn1.commit().unwrap();
+}
+
+#[test]
+fn test_gc_committed_txns() {
+    let dir = tempdir().unwrap();
+    let storage = open_serializable(&dir);
+    let long_running = storage.new_txn().unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), b"1").unwrap();
+    }
+    // kept while a txn that started before them is running
+    assert_eq!(storage.num_committed_txns(), 10);
+    long_running.get(&key_of(0)).unwrap();
+    drop(long_running);
+    assert_eq!(storage.num_committed_txns(), 0);
+
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.put(&key_of(0), b"2");
+    txn1.commit().unwrap();
+    assert_eq!(storage.num_committed_txns(), 1);
+    txn2.get(&key_of(0)).unwrap();
+    txn2.put(&key_of(1), b"2");
+    assert!(txn2.commit().is_err());
+    drop(txn1);
+    drop(txn2);
+    assert_eq!(storage.num_committed_txns(), 0);
+}
+
+#[test]
+fn test_gc_committed_txns_with_readers() {
+    let dir = tempdir().unwrap();
+    let storage = open_serializable(&dir);
+    storage.put(&key_of(0), b"1").unwrap();
+    // a named snapshot and a read-only txn never commit, so they do not keep the committed txns
+    storage.create_snapshot("nightly").unwrap();
+    let snapshot = storage.open_snapshot("nightly").unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), b"2").unwrap();
+    }
+    assert_eq!(storage.num_committed_txns(), 0);
+    assert_eq!(snapshot.get(&key_of(0)).unwrap(), Some(Bytes::from("1")));
+}
@@ -0,0 +1,187 @@
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{iterators::StorageIterator, lsm_storage::MiniLsm};
+
+use super::harness::key_of;
+use super::harness_ext::{options_with_value_log, AppendOperator};
+
+/// A value large enough to be stored in the value log.
+fn large_value_of(idx: usize, version: usize) -> Bytes {
+    Bytes::from(format!("{}@{}", idx, version).repeat(20000))
+}
+
+fn vlog_files(dir: &tempfile::TempDir) -> Vec<PathBuf> {
+    std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "vlog"))
+        .collect()
+}
+
+#[test]
+fn test_large_values() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options_with_value_log()).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &large_value_of(idx, 0)).unwrap();
+    }
+    storage.put(b"small", b"1").unwrap();
+    // a small value that looks like a value log pointer is kept as is
+    storage.put(b"tricky", b"\xffvlog\x01tricky").unwrap();
+    storage.delete(&key_of(3)).unwrap();
+
+    let check = |storage: &MiniLsm| {
+        assert_eq!(storage.get(&key_of(1)).unwrap(), Some(large_value_of(1, 0)));
+        assert_eq!(storage.get(&key_of(3)).unwrap(), None);
+        assert_eq!(storage.get(b"small").unwrap(), Some(Bytes::from("1")));
+        assert_eq!(
+            storage.get(b"tricky").unwrap(),
+            Some(Bytes::from_static(b"\xffvlog\x01tricky"))
+        );
+        let mut iter = storage
+            .scan(Bound::Included(&key_of(2)), Bound::Included(&key_of(4)))
+            .unwrap();
+        assert_eq!(iter.key(), key_of(2));
+        assert_eq!(iter.value(), large_value_of(2, 0));
+        iter.next().unwrap();
+        assert_eq!(iter.key(), key_of(4));
+        assert_eq!(iter.value(), large_value_of(4, 0));
+        iter.prev().unwrap();
+        assert_eq!(iter.key(), key_of(2));
+        assert_eq!(iter.value(), large_value_of(2, 0));
+    };
+    check(&storage);
+    storage.force_flush().unwrap();
+    check(&storage);
+
+    // only the pointers are stored in the SST
+    let state = storage.inner.state.read();
+    let sst = &state.sstables[&state.l0_sstables[0]];
+    assert!(sst.table_size() < 4096);
+}
+
+#[test]
+fn test_value_log_recover() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_value_log();
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for idx in 0..5 {
+        storage.put(&key_of(idx), &large_value_of(idx, 0)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    for idx in 5..10 {
+        storage.put(&key_of(idx), &large_value_of(idx, 0)).unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..10 {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(large_value_of(idx, 0))
+        );
+    }
+}
+
+#[test]
+fn test_value_log_gc() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_value_log();
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &large_value_of(idx, 0)).unwrap();
+    }
+    let snapshot = storage.new_txn().unwrap();
+    for idx in 0..5 {
+        storage.put(&key_of(idx), &large_value_of(idx, 1)).unwrap();
+    }
+    storage.delete(&key_of(9)).unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(vlog_files(&dir).len(), 1);
+
+    // the snapshot still reads the overwritten values
+    assert!(!storage.gc_value_log().unwrap());
+    assert_eq!(
+        snapshot.get(&key_of(0)).unwrap(),
+        Some(large_value_of(0, 0))
+    );
+    drop(snapshot);
+
+    assert!(storage.gc_value_log().unwrap());
+    assert_eq!(vlog_files(&dir).len(), 1);
+    let check = |storage: &MiniLsm| {
+        for idx in 0..9 {
+            let version = if idx < 5 { 1 } else { 0 };
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(large_value_of(idx, version))
+            );
+        }
+        assert_eq!(storage.get(&key_of(9)).unwrap(), None);
+    };
+    check(&storage);
+    // only the live values are moved
+    let size = vlog_files(&dir)
+        .iter()
+        .map(|path| std::fs::metadata(path).unwrap().len() as usize)
+        .sum::<usize>();
+    assert!(size > 9 * large_value_of(0, 0).len());
+    assert!(size < 10 * large_value_of(0, 0).len());
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check(&storage);
+}
+
+#[test]
+fn test_value_log_gc_new_version() {
+    let dir = tempdir().unwrap();
+    let mut options = options_with_value_log();
+    options.merge_operator = Some(Arc::new(AppendOperator));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..3 {
+        storage.put(&key_of(idx), &large_value_of(idx, 0)).unwrap();
+    }
+    storage.merge(&key_of(2), b"merged").unwrap();
+    let snapshot = storage.new_txn().unwrap();
+    let ts = storage.latest_commit_ts();
+
+    // the live values are moved at a new commit ts, but the file is kept for the snapshot
+    assert!(!storage.gc_value_log().unwrap());
+    assert_eq!(storage.latest_commit_ts(), ts + 1);
+    assert_eq!(vlog_files(&dir).len(), 2);
+    let merged = Bytes::from([&large_value_of(2, 0)[..], b",merged"].concat());
+    let check = |storage: &MiniLsm| {
+        for idx in 0..2 {
+            assert_eq!(
+                storage.get(&key_of(idx)).unwrap(),
+                Some(large_value_of(idx, 0))
+            );
+        }
+        assert_eq!(storage.get(&key_of(2)).unwrap(), Some(merged.clone()));
+    };
+    check(&storage);
+    assert_eq!(
+        snapshot.get(&key_of(0)).unwrap(),
+        Some(large_value_of(0, 0))
+    );
+    assert_eq!(snapshot.get(&key_of(2)).unwrap(), Some(merged.clone()));
+    drop(snapshot);
+
+    // the file is removed once no snapshot reads it, and the old versions are compacted away
+    assert!(storage.gc_value_log().unwrap());
+    assert_eq!(vlog_files(&dir).len(), 1);
+    check(&storage);
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    check(&storage);
+}
@@ -0,0 +1,302 @@
+use std::hash::Hasher;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::{Buf, BufMut, Bytes};
+use tempfile::tempdir;
+
+use crate::{
+    block::{Block, BlockBuilder, BlockFormat},
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator, SST_FORMAT_VERSION_UNTYPED},
+    value_type::ValueType,
+    wal::{Wal, WAL_FORMAT_VERSION},
+};
+
+use super::harness::scan_pairs;
+
+fn sst_records(table: SsTable) -> Vec<(Bytes, ValueType, Bytes)> {
+    let mut iter = SsTableIterator::create_and_seek_to_first(Arc::new(table)).unwrap();
+    let mut records = Vec::new();
+    while iter.is_valid() {
+        records.push((
+            Bytes::copy_from_slice(iter.key().key_ref()),
+            iter.value_type(),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    records
+}
+
+#[test]
+fn test_empty_value() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"c"[..], &b""[..]),
+            WriteBatchRecord::Put(&b"d"[..], &b""[..]),
+            WriteBatchRecord::Del(&b"b"[..]),
+        ])
+        .unwrap();
+    storage.delete(b"d").unwrap();
+
+    let expected = vec![
+        (Bytes::from("a"), Bytes::new()),
+        (Bytes::from("c"), Bytes::new()),
+    ];
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::new()));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"d").unwrap(), None);
+    assert_eq!(scan_pairs(&storage), expected);
+
+    // the value types are kept in the WAL
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+
+    // and in the SSTs, where a full compaction removes the delete tombstones only
+    storage.force_flush().unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+    storage.force_full_compaction().unwrap();
+    assert_eq!(scan_pairs(&storage), expected);
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::new()));
+    assert_eq!(storage.get(b"d").unwrap(), None);
+}
+
+#[test]
+fn test_txn_empty_value() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"b", b"1").unwrap();
+
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"a", b"");
+    txn.delete(b"b");
+    txn.put(b"c", b"");
+    txn.delete(b"c");
+    assert_eq!(txn.get(b"a").unwrap(), Some(Bytes::new()));
+    assert_eq!(txn.get(b"b").unwrap(), None);
+    assert_eq!(txn.get(b"c").unwrap(), None);
+    let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    assert!(iter.is_valid());
+    assert_eq!(iter.key(), b"a");
+    assert_eq!(iter.value(), b"");
+    iter.next().unwrap();
+    assert!(!iter.is_valid());
+    txn.commit().unwrap();
+
+    assert_eq!(scan_pairs(&storage), vec![(Bytes::from("a"), Bytes::new())]);
+}
+
+#[test]
+fn test_sst_format_version() {
+    let dir = tempdir().unwrap();
+
+    // an empty value with the put type is not a delete tombstone
+    let mut builder = SsTableBuilder::new(128);
+    builder.add_with_type(
+        KeySlice::for_testing_from_slice_no_ts(b"a"),
+        ValueType::Put,
+        b"",
+    );
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"b"), b"");
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"c"), b"1");
+    builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let table = SsTable::open(
+        0,
+        None,
+        FileObject::open(&dir.path().join("1.sst")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        sst_records(table),
+        vec![
+            (Bytes::from("a"), ValueType::Put, Bytes::new()),
+            (Bytes::from("b"), ValueType::Delete, Bytes::new()),
+            (Bytes::from("c"), ValueType::Put, Bytes::from("1")),
+        ]
+    );
+
+    // the SSTs written before the value types were stored are still readable, with the empty
+    // values as delete tombstones
+    let mut builder = SsTableBuilder::new(128);
+    builder.for_testing_format_version(SST_FORMAT_VERSION_UNTYPED);
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"a"), b"");
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"b"), b"1");
+    builder.build_for_test(dir.path().join("2.sst")).unwrap();
+    let table = SsTable::open(
+        0,
+        None,
+        FileObject::open(&dir.path().join("2.sst")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        sst_records(table),
+        vec![
+            (Bytes::from("a"), ValueType::Delete, Bytes::new()),
+            (Bytes::from("b"), ValueType::Put, Bytes::from("1")),
+        ]
+    );
+}
+
+#[test]
+fn test_unsupported_sst_format_version() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let mut builder = SsTableBuilder::new(128);
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"a"), b"1");
+    builder.build_for_test(&path).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    // the format version is right before the magic at the end of the footer
+    let version_offset = data.len() - 8;
+    for format_version in [0u32, 100] {
+        let mut data = data.clone();
+        data[version_offset..version_offset + 4].copy_from_slice(&format_version.to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert!(SsTable::open(0, None, FileObject::open(&path).unwrap()).is_err());
+    }
+}
+
+#[test]
+fn test_corrupted_value_type() {
+    let mut builder = BlockBuilder::new(128);
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"a"), b"1"));
+    let mut data = builder.build().encode().to_vec();
+    assert!(Block::decode_with_format(&data, BlockFormat::Varint).is_ok());
+    // the value type follows the key overlap, the key length, the key and the ts
+    data[3 + 8] = 100;
+    assert!(Block::decode_with_format(&data, BlockFormat::Varint).is_err());
+    // so does a block cut short
+    assert!(Block::decode_with_format(&data[5..], BlockFormat::Varint).is_err());
+}
+
+#[test]
+fn test_baseline_sst() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    // an SST as written before the format versions: no value types, compression types, range
+    // tombstones or footer
+    let mut buf = Vec::new();
+    let mut block_offsets = Vec::new();
+    for (key, value) in [(b"a", &b""[..]), (b"b", &b"1"[..])] {
+        let block_offset = buf.len();
+        block_offsets.push(block_offset as u32);
+        buf.put_u16(0);
+        buf.put_u16(key.len() as u16);
+        buf.put_slice(key);
+        buf.put_u64(1);
+        buf.put_u16(value.len() as u16);
+        buf.put_slice(value);
+        buf.put_u16(0);
+        buf.put_u16(1);
+        buf.put_u32(crc32fast::hash(&buf[block_offset..]));
+    }
+    let meta_offset = buf.len();
+    buf.put_u32(2);
+    for (key, block_offset) in [b"a", b"b"].into_iter().zip(block_offsets) {
+        buf.put_u32(block_offset);
+        for _ in 0..2 {
+            buf.put_u16(key.len() as u16);
+            buf.put_slice(key);
+            buf.put_u64(1);
+        }
+    }
+    buf.put_u64(1);
+    buf.put_u32(crc32fast::hash(&buf[meta_offset + 4..]));
+    buf.put_u32(meta_offset as u32);
+    // a bloom filter with all bits set
+    let bloom_offset = buf.len();
+    buf.put_slice(&[0xff; 8]);
+    buf.put_u8(1);
+    buf.put_u32(crc32fast::hash(&buf[bloom_offset..]));
+    buf.put_u32(bloom_offset as u32);
+    std::fs::write(&path, &buf).unwrap();
+
+    let table = SsTable::open(0, None, FileObject::open(&path).unwrap()).unwrap();
+    assert_eq!(table.format_version, SST_FORMAT_VERSION_UNTYPED);
+    assert!(table.range_tombstones.is_empty());
+    assert_eq!(
+        sst_records(table),
+        vec![
+            (Bytes::from("a"), ValueType::Delete, Bytes::new()),
+            (Bytes::from("b"), ValueType::Put, Bytes::from("1")),
+        ]
+    );
+
+    // the builder writes the same layout in that format version
+    let mut builder = SsTableBuilder::new(1);
+    builder.for_testing_format_version(SST_FORMAT_VERSION_UNTYPED);
+    builder.add(KeySlice::for_testing_from_slice_with_ts(b"a", 1), b"");
+    builder.add(KeySlice::for_testing_from_slice_with_ts(b"b", 1), b"1");
+    builder.build_for_test(dir.path().join("2.sst")).unwrap();
+    let data = std::fs::read(dir.path().join("2.sst")).unwrap();
+    assert_eq!(data[..bloom_offset], buf[..bloom_offset]);
+}
+
+#[test]
+fn test_baseline_wal() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.wal");
+    // a WAL as written before the format versions: entries not framed in records, whose checksums
+    // hash the lengths and the ts in native endian
+    let mut buf = Vec::new();
+    for (key, value) in [(b"a", &b"1"[..]), (b"b", &b""[..])] {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.write_u16(key.len() as u16);
+        hasher.write(key);
+        hasher.write_u64(1);
+        hasher.write_u16(value.len() as u16);
+        hasher.write(value);
+        buf.put_u16(key.len() as u16);
+        buf.put_slice(key);
+        buf.put_u64(1);
+        buf.put_u16(value.len() as u16);
+        buf.put_slice(value);
+        buf.put_u32(hasher.finalize());
+    }
+    // and a torn entry at the end
+    buf.put_u16(1);
+    buf.put_slice(b"c");
+    std::fs::write(&path, &buf).unwrap();
+
+    let expected = vec![
+        (b"a".to_vec(), ValueType::Put, Bytes::from("1")),
+        (b"b".to_vec(), ValueType::Delete, Bytes::new()),
+    ];
+    let recovered_records = |path| {
+        let (wal, mut column_families) = Wal::recover_column_families(path).unwrap();
+        let records = column_families
+            .remove(&0)
+            .unwrap_or_default()
+            .kv_pairs
+            .into_iter()
+            .map(|(key, (value_type, value))| (key.key_ref().to_vec(), value_type, value))
+            .collect::<Vec<_>>();
+        (wal, records)
+    };
+    let (wal, records) = recovered_records(&path);
+    assert_eq!(records, expected);
+
+    // the WAL is rewritten in the current format, so records can be appended to it
+    let data = std::fs::read(&path).unwrap();
+    assert_eq!(data[..2], [0, 0]);
+    assert_eq!((&data[2..6]).get_u32(), WAL_FORMAT_VERSION);
+    wal.put(KeySlice::for_testing_from_slice_with_ts(b"c", 2), b"2")
+        .unwrap();
+    wal.sync().unwrap();
+    drop(wal);
+    let (_, records) = recovered_records(&path);
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[..2], expected[..]);
+}
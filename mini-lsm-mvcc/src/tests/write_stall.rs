@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionController, CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm},
+    write_stall::{WriteStallCondition, WriteStallOptions},
+};
+
+#[test]
+fn test_write_stall_condition() {
+    let options = WriteStallOptions {
+        imm_memtables_slowdown_trigger: Some(2),
+        imm_memtables_stop_trigger: Some(4),
+        l0_slowdown_trigger: Some(8),
+        l0_stop_trigger: Some(12),
+        pending_compaction_bytes_slowdown_limit: Some(1 << 20),
+        pending_compaction_bytes_stop_limit: None,
+        ..Default::default()
+    };
+    assert_eq!(options.condition(1, 7, 0), WriteStallCondition::Normal);
+    assert_eq!(options.condition(2, 0, 0), WriteStallCondition::Delayed);
+    assert_eq!(options.condition(0, 8, 0), WriteStallCondition::Delayed);
+    assert_eq!(
+        options.condition(0, 0, 1 << 20),
+        WriteStallCondition::Delayed
+    );
+    assert_eq!(options.condition(4, 0, 0), WriteStallCondition::Stopped);
+    assert_eq!(options.condition(2, 12, 0), WriteStallCondition::Stopped);
+    assert_eq!(
+        options.condition(0, 0, u64::MAX),
+        WriteStallCondition::Delayed
+    );
+    assert_eq!(
+        WriteStallOptions::default().condition(usize::MAX, usize::MAX, u64::MAX),
+        WriteStallCondition::Normal
+    );
+}
+
+#[test]
+fn test_write_slowdown() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.write_stall.imm_memtables_slowdown_trigger = Some(1);
+    options.write_stall.slowdown_delay = Duration::from_millis(50);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    assert_eq!(storage.write_stall_stats().delayed_writes, 0);
+
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    assert_eq!(
+        storage.write_stall_stats().condition,
+        WriteStallCondition::Delayed
+    );
+    storage.put(b"b", b"2").unwrap();
+    let stats = storage.write_stall_stats();
+    assert_eq!(stats.delayed_writes, 1);
+    assert_eq!(stats.stopped_writes, 0);
+    assert!(stats.stall_time >= Duration::from_millis(50));
+
+    storage.inner.force_flush_next_imm_memtable().unwrap();
+    assert_eq!(
+        storage.write_stall_stats().condition,
+        WriteStallCondition::Normal
+    );
+    storage.put(b"c", b"3").unwrap();
+    assert_eq!(storage.write_stall_stats().delayed_writes, 1);
+}
+
+#[test]
+fn test_write_stop() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.write_stall.imm_memtables_stop_trigger = Some(2);
+    // without the flush thread, only this test flushes the memtables
+    let storage = Arc::new(LsmStorageInner::open(&dir, options).unwrap());
+    for key in [b"a", b"b"] {
+        storage.put(key, b"1").unwrap();
+        storage
+            .force_freeze_memtable(&storage.state_lock.lock())
+            .unwrap();
+    }
+    assert_eq!(
+        storage.write_stall_condition(),
+        WriteStallCondition::Stopped
+    );
+
+    let writer = {
+        let storage = storage.clone();
+        std::thread::spawn(move || storage.put(b"c", b"1").unwrap())
+    };
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(!writer.is_finished());
+    assert_eq!(storage.get(b"c").unwrap(), None);
+
+    // the write resumes once a memtable is flushed
+    storage.force_flush_next_imm_memtable().unwrap();
+    writer.join().unwrap();
+    assert_eq!(storage.get(b"c").unwrap(), Some(Bytes::from("1")));
+    let stats = storage.write_stall.stats(storage.write_stall_condition());
+    assert_eq!(stats.stopped_writes, 1);
+    assert!(stats.stall_time >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_write_stop_trigger_below_memtable_limit() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.write_stall.imm_memtables_stop_trigger = Some(1);
+    assert!(MiniLsm::open(&dir, options).is_err());
+}
+
+#[test]
+fn test_pending_compaction_bytes() {
+    let dir = tempdir().unwrap();
+    let compaction_options = CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 3,
+    });
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options.clone());
+    options.write_stall.pending_compaction_bytes_slowdown_limit = Some(1);
+    // without the compaction thread, the debt is never paid
+    let storage = Arc::new(LsmStorageInner::open(&dir, options).unwrap());
+    let controller = CompactionController::new(&compaction_options);
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .force_freeze_memtable(&storage.state_lock.lock())
+        .unwrap();
+    storage.force_flush_next_imm_memtable().unwrap();
+    assert_eq!(
+        controller.pending_compaction_bytes(&storage.state.read()),
+        0
+    );
+    assert_eq!(storage.write_stall_condition(), WriteStallCondition::Normal);
+
+    // L0 reaches the compaction trigger
+    storage.put(b"b", b"1").unwrap();
+    storage
+        .force_freeze_memtable(&storage.state_lock.lock())
+        .unwrap();
+    storage.force_flush_next_imm_memtable().unwrap();
+    let mut snapshot = storage.state.read().as_ref().clone();
+    let l0_bytes = snapshot
+        .l0_sstables
+        .iter()
+        .map(|id| snapshot.sstables[id].table_size())
+        .sum::<u64>();
+    assert_eq!(controller.pending_compaction_bytes(&snapshot), l0_bytes);
+    assert_eq!(
+        storage.write_stall_condition(),
+        WriteStallCondition::Delayed
+    );
+
+    // L1 is over the size ratio of L2
+    snapshot.levels[0].1 = std::mem::take(&mut snapshot.l0_sstables);
+    assert_eq!(controller.pending_compaction_bytes(&snapshot), l0_bytes);
+
+    // there is no debt once the SSTs are in the bottom level
+    snapshot.levels[2].1 = std::mem::take(&mut snapshot.levels[0].1);
+    assert_eq!(controller.pending_compaction_bytes(&snapshot), 0);
+}
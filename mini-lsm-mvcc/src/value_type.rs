@@ -0,0 +1,85 @@
+use anyhow::{bail, Result};
+
+use crate::vlog::{self, EXPIRES_AT_LEN, POINTER_LEN};
+
+/// The kind of a record of a key, stored with it in the memtables, the WAL and the blocks, so that
+/// an empty value is a value rather than a delete tombstone, and the values in the value log, with
+/// a TTL or of `merge` are told apart from the values that happen to look like them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ValueType {
+    /// Deletes the key. Its value is empty.
+    Delete = 0,
+    /// A value of the key, which may be empty.
+    Put = 1,
+    /// The operands of `merge`, encoded by `vlog::encode_merge_operands`.
+    Merge = 2,
+    /// A value in the value log. Its value is the `vlog::ValuePointer` to it.
+    ValuePointer = 3,
+    /// A value with a TTL. Its value is the time it expires at, followed by the value.
+    PutWithTtl = 4,
+    /// A value in the value log with a TTL. Its value is the time it expires at, followed by the
+    /// `vlog::ValuePointer`.
+    ValuePointerWithTtl = 5,
+}
+
+impl ValueType {
+    pub fn from_u8(value_type: u8) -> Result<Self> {
+        Ok(match value_type {
+            0 => Self::Delete,
+            1 => Self::Put,
+            2 => Self::Merge,
+            3 => Self::ValuePointer,
+            4 => Self::PutWithTtl,
+            5 => Self::ValuePointerWithTtl,
+            _ => bail!("unknown value type {}", value_type),
+        })
+    }
+
+    /// The type of a value written without one, in the formats before the types were stored, or
+    /// through the APIs that take only a value. An empty value is a delete tombstone.
+    pub fn of_untyped(value: &[u8]) -> Self {
+        if value.is_empty() {
+            Self::Delete
+        } else {
+            Self::Put
+        }
+    }
+
+    /// Whether it is a value of the key, whether it is in the value log or has a TTL.
+    pub fn is_put(self) -> bool {
+        matches!(
+            self,
+            Self::Put | Self::ValuePointer | Self::PutWithTtl | Self::ValuePointerWithTtl
+        )
+    }
+
+    pub fn has_ttl(self) -> bool {
+        matches!(self, Self::PutWithTtl | Self::ValuePointerWithTtl)
+    }
+
+    /// The type of the same value with a TTL, whose value is prefixed with the expiry time.
+    pub fn with_ttl(self) -> Self {
+        match self {
+            Self::Put => Self::PutWithTtl,
+            Self::ValuePointer => Self::ValuePointerWithTtl,
+            _ => unreachable!("{:?} cannot have a TTL", self),
+        }
+    }
+
+    /// Check that a value has the length its type requires, so that a corrupted file is an error
+    /// when it is read instead of a panic when the value is decoded.
+    pub fn check_value(self, value: &[u8]) -> Result<()> {
+        let valid = match self {
+            Self::Delete | Self::Put => true,
+            Self::Merge => vlog::merge_operands(value).is_some(),
+            Self::ValuePointer => value.len() == POINTER_LEN,
+            Self::PutWithTtl => value.len() >= EXPIRES_AT_LEN,
+            Self::ValuePointerWithTtl => value.len() == EXPIRES_AT_LEN + POINTER_LEN,
+        };
+        if !valid {
+            bail!("corrupted value of type {:?}", self);
+        }
+        Ok(())
+    }
+}
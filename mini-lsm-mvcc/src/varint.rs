@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut};
+
+/// Encode an integer in LEB128: 7 bits per byte from the lowest ones, with the high bit set on all
+/// bytes but the last.
+pub fn put_varint(buf: &mut impl BufMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+/// Decode an integer encoded by `put_varint`, and advance the buffer past it.
+pub fn get_varint(buf: &mut impl Buf) -> Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        if shift >= 64 {
+            bail!("varint is too long");
+        }
+        if !buf.has_remaining() {
+            bail!("varint is truncated");
+        }
+        let byte = buf.get_u8();
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// The number of bytes of an integer encoded by `put_varint`.
+pub fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+/// How the lengths of keys and values are encoded, which depends on the format version of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthEncoding {
+    /// A `u16`, which limits keys and values to 64 KiB, in the formats before the varints.
+    U16,
+    Varint,
+}
+
+impl LengthEncoding {
+    pub fn put(self, buf: &mut impl BufMut, len: usize) {
+        match self {
+            Self::U16 => {
+                assert!(len <= u16::MAX as usize, "length {} exceeds u16", len);
+                buf.put_u16(len as u16)
+            }
+            Self::Varint => put_varint(buf, len as u64),
+        }
+    }
+
+    pub fn get(self, buf: &mut impl Buf) -> Result<usize> {
+        match self {
+            Self::U16 if buf.remaining() < std::mem::size_of::<u16>() => {
+                bail!("length is truncated")
+            }
+            Self::U16 => Ok(buf.get_u16() as usize),
+            Self::Varint => Ok(get_varint(buf)? as usize),
+        }
+    }
+
+    /// The number of bytes of an encoded length.
+    pub fn encoded_len(self, len: usize) -> usize {
+        match self {
+            Self::U16 => std::mem::size_of::<u16>(),
+            Self::Varint => varint_len(len as u64),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use bytes::{Buf, BufMut, Bytes};
+use parking_lot::{Mutex, RwLock};
+
+use crate::key::{KeyBytes, KeySlice};
+use crate::value_type::ValueType;
+use crate::varint::{get_varint, put_varint, varint_len};
+
+/// The format version of the value log files, which each file starts with.
+pub const VALUE_LOG_FORMAT_VERSION: u32 = 1;
+const HEADER_LEN: usize = 4;
+
+/// The length of an encoded `ValuePointer`.
+pub const POINTER_LEN: usize = 8 + 8 + 4;
+/// The length of the expiry time that the values with a TTL start with.
+pub const EXPIRES_AT_LEN: usize = 8;
+
+/// The location of a record in the value log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValuePointer {
+    pub file_id: usize,
+    pub offset: u64,
+    pub len: u32,
+}
+
+/// A value as it is stored in the LSM tree.
+pub enum StoredValue<'a> {
+    Inline(&'a [u8]),
+    Pointer(ValuePointer),
+}
+
+impl ValuePointer {
+    /// Encode the pointer so that it can be stored as the value of a key in the LSM tree, with
+    /// `ValueType::ValuePointer`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(POINTER_LEN);
+        buf.put_u64(self.file_id as u64);
+        buf.put_u64(self.offset);
+        buf.put_u32(self.len);
+        buf
+    }
+
+    fn decode(mut buf: &[u8]) -> Self {
+        Self {
+            file_id: buf.get_u64() as usize,
+            offset: buf.get_u64(),
+            len: buf.get_u32(),
+        }
+    }
+}
+
+/// Prefix a value, or an encoded `ValuePointer`, with the time it expires at, in milliseconds
+/// since the Unix epoch. It is stored with the type returned by `ValueType::with_ttl`.
+pub fn encode_expiring(expires_at: u64, value: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(EXPIRES_AT_LEN + value.len());
+    buf.put_u64(expires_at);
+    buf.put_slice(value);
+    buf
+}
+
+/// The time a value stored in the LSM tree expires at, if it is written with a TTL.
+pub fn expires_at(value_type: ValueType, mut value: &[u8]) -> Option<u64> {
+    value_type.has_ttl().then(|| value.get_u64())
+}
+
+/// Whether a value stored in the LSM tree has expired at `now`. An expired value is deleted.
+pub fn is_expired(value_type: ValueType, value: &[u8], now: u64) -> bool {
+    expires_at(value_type, value).is_some_and(|expires_at| expires_at <= now)
+}
+
+/// The current time in milliseconds since the Unix epoch, which the expiry times are based on.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
+
+/// Encode the operands of `merge` of a key at the same ts, from the oldest to the latest, each of
+/// them with its length.
+pub fn encode_merge_operands(operands: &[&[u8]]) -> Vec<u8> {
+    let len = operands
+        .iter()
+        .map(|operand| 4 + operand.len())
+        .sum::<usize>();
+    let mut buf = Vec::with_capacity(len);
+    for operand in operands {
+        buf.put_u32(operand.len() as u32);
+        buf.put_slice(operand);
+    }
+    buf
+}
+
+/// The operands of a value stored in the LSM tree by `merge`, from the oldest to the latest, or
+/// `None` if they are corrupted.
+pub fn merge_operands(mut value: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut operands = Vec::new();
+    while value.len() >= 4 {
+        let len = value.get_u32() as usize;
+        if value.len() < len {
+            return None;
+        }
+        operands.push(&value[..len]);
+        value = &value[len..];
+    }
+    value.is_empty().then_some(operands)
+}
+
+/// Decode a value stored in the LSM tree, whose length is checked by `ValueType::check_value`.
+/// Delete tombstones are empty inline values. The expiry time of a value with a TTL is skipped.
+pub fn decode_value(value_type: ValueType, value: &[u8]) -> StoredValue<'_> {
+    match value_type {
+        ValueType::ValuePointer => StoredValue::Pointer(ValuePointer::decode(value)),
+        ValueType::ValuePointerWithTtl => {
+            StoredValue::Pointer(ValuePointer::decode(&value[EXPIRES_AT_LEN..]))
+        }
+        ValueType::PutWithTtl => StoredValue::Inline(&value[EXPIRES_AT_LEN..]),
+        ValueType::Delete | ValueType::Put | ValueType::Merge => StoredValue::Inline(value),
+    }
+}
+
+/// A record of the value log.
+pub struct ValueLogRecord {
+    pub key: KeyBytes,
+    pub value: Bytes,
+    pub pointer: ValuePointer,
+}
+
+/// An open file of the value log.
+struct ValueLogFile {
+    file: File,
+    /// The offset of the first record.
+    start: u64,
+}
+
+impl ValueLogFile {
+    /// Open a file and read its header. A file too short for a header has no records, e.g. it is
+    /// created right before a crash.
+    fn open(file: File) -> Result<Self> {
+        let len = file.metadata()?.len();
+        if len < HEADER_LEN as u64 {
+            return Ok(Self { file, start: len });
+        }
+        let mut header = [0; HEADER_LEN];
+        file.read_exact_at(&mut header, 0)?;
+        match (&header[..]).get_u32() {
+            VALUE_LOG_FORMAT_VERSION => Ok(Self {
+                file,
+                start: HEADER_LEN as u64,
+            }),
+            version => bail!("unsupported value log format version {}", version),
+        }
+    }
+}
+
+type ValueLogFiles = Arc<BTreeMap<usize, Arc<ValueLogFile>>>;
+
+/// The value log stores large values out of the LSM tree, so that they are not rewritten by
+/// compaction. Each file starts with the format version, followed by a sequence of records:
+///
+/// ```text
+/// | key_len (varint) | key | ts (u64) | value_len (u32) | value | checksum (u32) |
+/// ```
+///
+/// Values are appended to the active file, and the other files are only read until they are
+/// garbage collected.
+pub struct ValueLog {
+    files: RwLock<ValueLogFiles>,
+    /// The id and the size of the active file.
+    active: Mutex<Option<(usize, u64)>>,
+}
+
+impl ValueLog {
+    pub fn new() -> Self {
+        Self {
+            files: RwLock::new(Arc::new(BTreeMap::new())),
+            active: Mutex::new(None),
+        }
+    }
+
+    fn add_file(&self, id: usize, file: ValueLogFile) {
+        let mut guard = self.files.write();
+        let mut files = guard.as_ref().clone();
+        files.insert(id, Arc::new(file));
+        *guard = Arc::new(files);
+    }
+
+    /// Open an existing file of the value log. No value is appended to it.
+    pub fn open_file(&self, id: usize, path: impl AsRef<Path>) -> Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(path)
+            .context("failed to open value log")?;
+        self.add_file(id, ValueLogFile::open(file)?);
+        Ok(())
+    }
+
+    /// Create a new file and append values to it from now on.
+    pub fn create_file(&self, id: usize, path: impl AsRef<Path>) -> Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .context("failed to create value log")?;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.put_u32(VALUE_LOG_FORMAT_VERSION);
+        file.write_all_at(&header, 0)?;
+        let mut active = self.active.lock();
+        self.add_file(
+            id,
+            ValueLogFile {
+                file,
+                start: HEADER_LEN as u64,
+            },
+        );
+        *active = Some((id, HEADER_LEN as u64));
+        Ok(())
+    }
+
+    /// Stop appending to the active file.
+    pub fn seal(&self) -> Result<()> {
+        let mut active = self.active.lock();
+        if let Some((id, _)) = active.take() {
+            self.files.read()[&id].file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    pub fn active_file(&self) -> Option<(usize, u64)> {
+        *self.active.lock()
+    }
+
+    pub fn file_ids(&self) -> Vec<usize> {
+        self.files.read().keys().copied().collect()
+    }
+
+    /// Remove a file from the value log. Readers that started before keep it open.
+    pub fn remove_file(&self, id: usize) {
+        let mut active = self.active.lock();
+        if matches!(*active, Some((active_id, _)) if active_id == id) {
+            *active = None;
+        }
+        let mut guard = self.files.write();
+        let mut files = guard.as_ref().clone();
+        files.remove(&id);
+        *guard = Arc::new(files);
+    }
+
+    /// Append a value to the active file.
+    pub fn append(&self, key: KeySlice, value: &[u8]) -> Result<ValuePointer> {
+        let mut active = self.active.lock();
+        let Some((id, size)) = active.as_mut() else {
+            bail!("no active value log");
+        };
+        let mut buf =
+            Vec::with_capacity(varint_len(key.key_len() as u64) + key.raw_len() + value.len() + 8);
+        put_varint(&mut buf, key.key_len() as u64);
+        buf.put_slice(key.key_ref());
+        buf.put_u64(key.ts());
+        buf.put_u32(value.len() as u32);
+        buf.put_slice(value);
+        buf.put_u32(crc32fast::hash(&buf));
+        let file = self.files.read()[id].clone();
+        file.file.write_all_at(&buf, *size)?;
+        let pointer = ValuePointer {
+            file_id: *id,
+            offset: *size,
+            len: buf.len() as u32,
+        };
+        *size += buf.len() as u64;
+        Ok(pointer)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        let active = self.active.lock();
+        if let Some((id, _)) = *active {
+            self.files.read()[&id].file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Get a reader that can resolve all values that are in the value log now, even if their files
+    /// are garbage collected later.
+    pub fn reader(self: &Arc<Self>) -> ValueLogReader {
+        ValueLogReader {
+            files: self.files.read().clone(),
+            value_log: self.clone(),
+        }
+    }
+
+    fn decode_record(buf: &[u8], pointer: ValuePointer) -> Result<ValueLogRecord> {
+        if buf.len() < 4 {
+            bail!("value log record too short");
+        }
+        let (mut body, mut checksum) = buf.split_at(buf.len() - 4);
+        if crc32fast::hash(body) != checksum.get_u32() {
+            bail!("value log checksum mismatched");
+        }
+        let key_len = get_varint(&mut body)? as usize;
+        if body.remaining() < 8 + 4 || key_len > body.remaining() - 8 - 4 {
+            bail!("value log record length mismatched");
+        }
+        let key = Bytes::copy_from_slice(&body[..key_len]);
+        body.advance(key_len);
+        let ts = body.get_u64();
+        let value_len = body.get_u32() as usize;
+        if body.remaining() != value_len {
+            bail!("value log record length mismatched");
+        }
+        Ok(ValueLogRecord {
+            key: KeyBytes::from_bytes_with_ts(key, ts),
+            value: Bytes::copy_from_slice(body),
+            pointer,
+        })
+    }
+
+    /// Read all records of a file. A record that was only partially written before a crash can
+    /// only be the last one in the file, and is ignored.
+    pub fn read_file(&self, id: usize) -> Result<Vec<ValueLogRecord>> {
+        let file = self.files.read()[&id].clone();
+        let mut buf = vec![0; file.file.metadata()?.len() as usize];
+        file.file.read_exact_at(&mut buf, 0)?;
+        let mut records = Vec::new();
+        let mut offset = file.start as usize;
+        while offset < buf.len() {
+            let mut rest = &buf[offset..];
+            let Ok(key_len) = get_varint(&mut rest).map(|len| len as usize) else {
+                break;
+            };
+            if rest.len() < 8 + 4 || key_len > rest.len() - 8 - 4 {
+                break;
+            }
+            let value_len_offset = buf.len() - rest.len() + key_len + 8;
+            let value_len = (&buf[value_len_offset..]).get_u32() as usize;
+            let end = value_len_offset + 4 + value_len + 4;
+            if end > buf.len() {
+                break;
+            }
+            let pointer = ValuePointer {
+                file_id: id,
+                offset: offset as u64,
+                len: (end - offset) as u32,
+            };
+            match Self::decode_record(&buf[offset..end], pointer) {
+                Ok(record) => records.push(record),
+                Err(_) if end == buf.len() => break,
+                Err(e) => return Err(e),
+            }
+            offset = end;
+        }
+        Ok(records)
+    }
+}
+
+impl Default for ValueLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves the values stored in the LSM tree, with the value log files at the time it is created.
+#[derive(Clone)]
+pub struct ValueLogReader {
+    files: ValueLogFiles,
+    value_log: Arc<ValueLog>,
+}
+
+impl ValueLogReader {
+    pub fn read(&self, pointer: ValuePointer) -> Result<Bytes> {
+        let file = match self.files.get(&pointer.file_id) {
+            Some(file) => file.clone(),
+            // The file is created after the reader, e.g., by the garbage collector.
+            None => match self.value_log.files.read().get(&pointer.file_id) {
+                Some(file) => file.clone(),
+                None => bail!("value log {} not found", pointer.file_id),
+            },
+        };
+        let mut buf = vec![0; pointer.len as usize];
+        file.file.read_exact_at(&mut buf, pointer.offset)?;
+        Ok(ValueLog::decode_record(&buf, pointer)?.value)
+    }
+
+    /// Get the user value of a value stored in the LSM tree, whether it is inline or in the value
+    /// log.
+    pub fn user_value(&self, value_type: ValueType, value: &[u8]) -> Result<Bytes> {
+        match decode_value(value_type, value) {
+            StoredValue::Inline(value) => Ok(Bytes::copy_from_slice(value)),
+            StoredValue::Pointer(pointer) => self.read(pointer),
+        }
+    }
+
+    /// Get the user value of a value stored in the LSM tree. Returns `None` if it is inline.
+    pub fn resolve(&self, value_type: ValueType, value: &[u8]) -> Result<Option<Bytes>> {
+        match decode_value(value_type, value) {
+            StoredValue::Inline(_) => Ok(None),
+            StoredValue::Pointer(pointer) => self.read(pointer).map(Some),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
 use std::hash::Hasher;
 use std::io::{BufWriter, Read, Write};
@@ -9,28 +10,106 @@ use bytes::{Buf, BufMut, Bytes};
 use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
 
+use crate::column_family::DEFAULT_COLUMN_FAMILY_ID;
 use crate::key::{KeyBytes, KeySlice};
+use crate::range_tombstone::RangeTombstone;
+use crate::value_type::ValueType;
+use crate::varint::{get_varint, put_varint, varint_len, LengthEncoding};
 
+const SIZEOF_U32: usize = std::mem::size_of::<u32>();
+
+pub const WAL_FORMAT_VERSION: u32 = 2;
+/// The WALs before the format version, which have no header and whose entries are not framed in
+/// records: `| key_len (u16) | key | ts (u64) | value_len (u16) | value | checksum (u32) |`, where
+/// an empty value is a delete tombstone.
+pub const WAL_FORMAT_VERSION_UNFRAMED: u32 = 1;
+/// The header of a WAL: a `u16` 0, which never starts a WAL without a header as keys are never
+/// empty, and the format version.
+const HEADER_LEN: usize = 2 + SIZEOF_U32;
+
+/// A range tombstone. The key is the start key + ts, and the value is the (excluded) end key.
+const WAL_ENTRY_RANGE_TOMBSTONE: u8 = 1;
+/// The following entries of the record belong to the column family whose id is the ts. Entries
+/// before the first marker belong to the default column family.
+const WAL_ENTRY_COLUMN_FAMILY: u8 = 2;
+/// Set on the kind of a key-value pair, whose value type is the rest of the kind. The key is a user
+/// key + ts.
+const WAL_ENTRY_KEY_VALUE: u8 = 0x40;
+
+/// The records and range tombstones of a write batch in one column family.
+pub type ColumnFamilyBatch<'a, 'b> = (
+    usize,
+    &'b [(KeySlice<'a>, ValueType, &'a [u8])],
+    &'b [RangeTombstone],
+);
+
+/// The entries of one column family recovered from the WAL.
+#[derive(Default)]
+pub struct RecoveredColumnFamily {
+    pub kv_pairs: Vec<(KeyBytes, (ValueType, Bytes))>,
+    pub range_tombstones: Vec<RangeTombstone>,
+}
+
+/// The write-ahead log. It starts with a header of a `u16` 0 and the format version, followed by the
+/// records. Every write batch is framed as a single record so that recovery either applies the
+/// whole batch or nothing of it:
+///
+/// ```text
+/// | body_len (u32) | kind (u8) | key_len (varint) | key | ts (u64) | value_len (varint) | value | ... | checksum (u32) |
+/// ```
+///
+/// The kind of an entry holds the value type of a key-value pair, so that an empty value is not a
+/// delete tombstone and a value is never mistaken for a value log pointer.
+///
+/// A WAL of `WAL_FORMAT_VERSION_UNFRAMED` is rewritten in the current format when it is recovered.
+///
+/// The WAL is shared by all column families. It belongs to the memtable of the default column family.
 pub struct Wal {
     file: Arc<Mutex<BufWriter<File>>>,
 }
 
 impl Wal {
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            file: Arc::new(Mutex::new(BufWriter::new(
-                OpenOptions::new()
-                    .read(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(path)
-                    .context("failed to create WAL")?,
-            ))),
-        })
+        let mut file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .context("failed to create WAL")?;
+        write_header(&mut file)?;
+        Ok(Self::from_file(file))
+    }
+
+    fn from_file(file: File) -> Self {
+        Self {
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+        }
+    }
+
+    pub fn recover(
+        path: impl AsRef<Path>,
+        skiplist: &SkipMap<KeyBytes, (ValueType, Bytes)>,
+        range_tombstones: &mut Vec<RangeTombstone>,
+    ) -> Result<Self> {
+        let (wal, mut column_families) = Self::recover_column_families(path)?;
+        if let Some(recovered) = column_families.remove(&DEFAULT_COLUMN_FAMILY_ID) {
+            for (key, value) in recovered.kv_pairs {
+                skiplist.insert(key, value);
+            }
+            range_tombstones.extend(recovered.range_tombstones);
+        }
+        if !column_families.is_empty() {
+            bail!("WAL has entries of other column families");
+        }
+        Ok(wal)
     }
 
-    pub fn recover(path: impl AsRef<Path>, skiplist: &SkipMap<KeyBytes, Bytes>) -> Result<Self> {
+    /// Recover the entries of all column families from the WAL, keyed by column family id.
+    pub fn recover_column_families(
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, BTreeMap<usize, RecoveredColumnFamily>)> {
         let path = path.as_ref();
+        let mut column_families = BTreeMap::new();
         let mut file = OpenOptions::new()
             .read(true)
             .append(true)
@@ -38,49 +117,266 @@ impl Wal {
             .context("failed to recover from WAL")?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
-        let mut rbuf: &[u8] = buf.as_slice();
+        if buf.len() < HEADER_LEN {
+            // A WAL too short for a header has no records, e.g. it is created right before a crash.
+            file.set_len(0)?;
+            write_header(&mut file)?;
+            file.sync_all()?;
+            return Ok((Self::from_file(file), column_families));
+        }
+        let mut header = &buf[..HEADER_LEN];
+        if header.get_u16() != 0 {
+            drop(file);
+            let kv_pairs = Self::decode_unframed(&buf, path)?;
+            let wal = Self::upgrade(path, &kv_pairs)?;
+            column_families.insert(
+                DEFAULT_COLUMN_FAMILY_ID,
+                RecoveredColumnFamily {
+                    kv_pairs,
+                    range_tombstones: Vec::new(),
+                },
+            );
+            return Ok((wal, column_families));
+        }
+        match header.get_u32() {
+            WAL_FORMAT_VERSION => {}
+            version => bail!("unsupported WAL format version {}", version),
+        }
+        let mut rbuf: &[u8] = &buf[HEADER_LEN..];
+        while rbuf.has_remaining() {
+            // A batch that was only partially written before a crash can only be the last one in
+            // the file. Drop it instead of applying part of it.
+            if rbuf.remaining() < SIZEOF_U32 {
+                break;
+            }
+            let body_len = (&rbuf[..SIZEOF_U32]).get_u32() as usize;
+            if rbuf.remaining() < SIZEOF_U32 + body_len + SIZEOF_U32 {
+                break;
+            }
+            rbuf.advance(SIZEOF_U32);
+            let body = &rbuf[..body_len];
+            rbuf.advance(body_len);
+            let checksum = rbuf.get_u32();
+            if crc32fast::hash(body) != checksum {
+                if !rbuf.has_remaining() {
+                    break;
+                }
+                bail!("checksum mismatch");
+            }
+            Self::decode_batch(body, &mut column_families)?;
+        }
+        let valid_len = buf.len() - rbuf.remaining();
+        if valid_len != buf.len() {
+            println!(
+                "dropping {} bytes of torn write batch from WAL {}",
+                buf.len() - valid_len,
+                path.display()
+            );
+            // Truncate the torn batch so that later appends are readable.
+            file.set_len(valid_len as u64)?;
+            file.sync_all()?;
+        }
+        Ok((Self::from_file(file), column_families))
+    }
+
+    /// Decode the entries of a WAL of `WAL_FORMAT_VERSION_UNFRAMED`. A torn entry at the end is
+    /// dropped.
+    fn decode_unframed(buf: &[u8], path: &Path) -> Result<Vec<(KeyBytes, (ValueType, Bytes))>> {
+        let mut kv_pairs = Vec::new();
+        let mut rbuf = buf;
         while rbuf.has_remaining() {
+            let mut entry = rbuf;
+            let Some((key, ts, value, checksum)) = Self::decode_unframed_entry(&mut entry) else {
+                break;
+            };
+            // The lengths and the ts are hashed in native endian, as they always were.
             let mut hasher = crc32fast::Hasher::new();
-            let key_len = rbuf.get_u16() as usize;
-            hasher.write_u16(key_len as u16);
-            let key = Bytes::copy_from_slice(&rbuf[..key_len]);
-            hasher.write(&key);
-            rbuf.advance(key_len);
-            let ts = rbuf.get_u64();
+            hasher.write_u16(key.len() as u16);
+            hasher.write(key);
             hasher.write_u64(ts);
-            let value_len = rbuf.get_u16() as usize;
-            hasher.write_u16(value_len as u16);
-            let value = Bytes::copy_from_slice(&rbuf[..value_len]);
-            hasher.write(&value);
-            rbuf.advance(value_len);
-            let checksum = rbuf.get_u32();
+            hasher.write_u16(value.len() as u16);
+            hasher.write(value);
             if hasher.finalize() != checksum {
+                if !entry.has_remaining() {
+                    break;
+                }
                 bail!("checksum mismatch");
             }
-            skiplist.insert(KeyBytes::from_bytes_with_ts(key, ts), value);
+            rbuf = entry;
+            kv_pairs.push((
+                KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(key), ts),
+                (ValueType::of_untyped(value), Bytes::copy_from_slice(value)),
+            ));
         }
-        Ok(Self {
-            file: Arc::new(Mutex::new(BufWriter::new(file))),
-        })
+        if rbuf.has_remaining() {
+            println!(
+                "dropping {} bytes of torn entry from WAL {}",
+                rbuf.remaining(),
+                path.display()
+            );
+        }
+        Ok(kv_pairs)
+    }
+
+    /// Decode an entry of a WAL of `WAL_FORMAT_VERSION_UNFRAMED` into its key, ts, value and
+    /// checksum, or `None` if it is truncated.
+    fn decode_unframed_entry<'a>(rbuf: &mut &'a [u8]) -> Option<(&'a [u8], u64, &'a [u8], u32)> {
+        let key_len = LengthEncoding::U16.get(rbuf).ok()?;
+        if rbuf.remaining() < key_len + std::mem::size_of::<u64>() {
+            return None;
+        }
+        let key = &rbuf[..key_len];
+        rbuf.advance(key_len);
+        let ts = rbuf.get_u64();
+        let value_len = LengthEncoding::U16.get(rbuf).ok()?;
+        if rbuf.remaining() < value_len + SIZEOF_U32 {
+            return None;
+        }
+        let value = &rbuf[..value_len];
+        rbuf.advance(value_len);
+        Some((key, ts, value, rbuf.get_u32()))
+    }
+
+    /// Rewrite the entries of a WAL of an older format in the current one, so that new records
+    /// can be appended to it. The new WAL replaces the old one by a rename, so that a crash leaves
+    /// either of them.
+    fn upgrade(path: &Path, kv_pairs: &[(KeyBytes, (ValueType, Bytes))]) -> Result<Self> {
+        let tmp_path = path.with_extension("wal.tmp");
+        if tmp_path.exists() {
+            std::fs::remove_file(&tmp_path)?;
+        }
+        let wal = Self::create(&tmp_path)?;
+        if !kv_pairs.is_empty() {
+            let data = kv_pairs
+                .iter()
+                .map(|(key, (value_type, value))| (key.as_key_slice(), *value_type, &value[..]))
+                .collect::<Vec<_>>();
+            wal.put_column_family_batches(&[(DEFAULT_COLUMN_FAMILY_ID, &data, &[])])?;
+        }
+        wal.sync()?;
+        std::fs::rename(&tmp_path, path)?;
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(wal)
+    }
+
+    fn decode_batch(
+        mut body: &[u8],
+        column_families: &mut BTreeMap<usize, RecoveredColumnFamily>,
+    ) -> Result<()> {
+        let mut batch = BTreeMap::<usize, RecoveredColumnFamily>::new();
+        let mut column_family = DEFAULT_COLUMN_FAMILY_ID;
+        while body.has_remaining() {
+            let kind = body.get_u8();
+            let key_len = get_varint(&mut body)? as usize;
+            let key = Bytes::copy_from_slice(&body[..key_len]);
+            body.advance(key_len);
+            let ts = body.get_u64();
+            let value_len = get_varint(&mut body)? as usize;
+            let value = Bytes::copy_from_slice(&body[..value_len]);
+            body.advance(value_len);
+            match kind {
+                _ if kind & WAL_ENTRY_KEY_VALUE != 0 => {
+                    let value_type = ValueType::from_u8(kind & !WAL_ENTRY_KEY_VALUE)?;
+                    value_type.check_value(&value)?;
+                    batch
+                        .entry(column_family)
+                        .or_default()
+                        .kv_pairs
+                        .push((KeyBytes::from_bytes_with_ts(key, ts), (value_type, value)));
+                }
+                WAL_ENTRY_RANGE_TOMBSTONE => batch
+                    .entry(column_family)
+                    .or_default()
+                    .range_tombstones
+                    .push(RangeTombstone::new(key, value, ts)),
+                WAL_ENTRY_COLUMN_FAMILY => column_family = ts as usize,
+                _ => bail!("unknown WAL entry kind {}", kind),
+            }
+        }
+        for (id, recovered) in batch {
+            let entry = column_families.entry(id).or_default();
+            entry.kv_pairs.extend(recovered.kv_pairs);
+            entry.range_tombstones.extend(recovered.range_tombstones);
+        }
+        Ok(())
     }
 
     pub fn put(&self, key: KeySlice, value: &[u8]) -> Result<()> {
+        self.put_batch(&[(key, value)], &[])
+    }
+
+    /// Write a batch of key-value pairs and range tombstones to the WAL as a single record. An
+    /// empty value is written as a delete tombstone.
+    pub fn put_batch(
+        &self,
+        data: &[(KeySlice, &[u8])],
+        range_tombstones: &[RangeTombstone],
+    ) -> Result<()> {
+        let data = data
+            .iter()
+            .map(|(key, value)| (*key, ValueType::of_untyped(value), *value))
+            .collect::<Vec<_>>();
+        self.put_column_family_batches(&[(DEFAULT_COLUMN_FAMILY_ID, &data, range_tombstones)])
+    }
+
+    /// Write the batches of several column families to the WAL as a single record. The entries of
+    /// a column family other than the default one are preceded by a marker.
+    pub fn put_column_family_batches(&self, batches: &[ColumnFamilyBatch]) -> Result<()> {
         let mut file = self.file.lock();
-        let mut buf: Vec<u8> =
-            Vec::with_capacity(key.raw_len() + value.len() + std::mem::size_of::<u16>());
-        let mut hasher = crc32fast::Hasher::new();
-        hasher.write_u16(key.key_len() as u16);
-        buf.put_u16(key.key_len() as u16);
-        hasher.write(key.key_ref());
-        buf.put_slice(key.key_ref());
-        hasher.write_u64(key.ts());
-        buf.put_u64(key.ts());
-        hasher.write_u16(value.len() as u16);
-        buf.put_u16(value.len() as u16);
-        buf.put_slice(value);
-        hasher.write(value);
+        let body_len =
+            batches
+                .iter()
+                .map(|(column_family, data, range_tombstones)| {
+                    let marker_len = if *column_family == DEFAULT_COLUMN_FAMILY_ID {
+                        0
+                    } else {
+                        entry_len(0, 0)
+                    };
+                    marker_len
+                        + data
+                            .iter()
+                            .map(|(key, _, value)| entry_len(key.key_len(), value.len()))
+                            .chain(range_tombstones.iter().map(|tombstone| {
+                                entry_len(tombstone.start.len(), tombstone.end.len())
+                            }))
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+        let mut buf: Vec<u8> = Vec::with_capacity(body_len + SIZEOF_U32 * 2);
+        buf.put_u32(body_len as u32);
+        let mut has_marker = false;
+        for (column_family, data, range_tombstones) in batches {
+            if *column_family != DEFAULT_COLUMN_FAMILY_ID {
+                put_entry(
+                    &mut buf,
+                    WAL_ENTRY_COLUMN_FAMILY,
+                    &[],
+                    *column_family as u64,
+                    &[],
+                );
+                has_marker = true;
+            } else {
+                assert!(!has_marker, "the default column family must go first");
+            }
+            for tombstone in range_tombstones.iter() {
+                put_entry(
+                    &mut buf,
+                    WAL_ENTRY_RANGE_TOMBSTONE,
+                    &tombstone.start,
+                    tombstone.ts,
+                    &tombstone.end,
+                );
+            }
+            for (key, value_type, value) in data.iter() {
+                let kind = WAL_ENTRY_KEY_VALUE | *value_type as u8;
+                put_entry(&mut buf, kind, key.key_ref(), key.ts(), value);
+            }
+        }
+        debug_assert_eq!(buf.len(), SIZEOF_U32 + body_len);
         // add checksum: week 2 day 7
-        buf.put_u32(hasher.finalize());
+        buf.put_u32(crc32fast::hash(&buf[SIZEOF_U32..]));
         file.write_all(&buf)?;
         Ok(())
     }
@@ -92,3 +388,30 @@ impl Wal {
         Ok(())
     }
 }
+
+fn write_header(file: &mut File) -> Result<()> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.put_u16(0);
+    header.put_u32(WAL_FORMAT_VERSION);
+    file.write_all(&header)?;
+    Ok(())
+}
+
+/// The size of an entry.
+fn entry_len(key_len: usize, value_len: usize) -> usize {
+    std::mem::size_of::<u8>()
+        + varint_len(key_len as u64)
+        + key_len
+        + std::mem::size_of::<u64>()
+        + varint_len(value_len as u64)
+        + value_len
+}
+
+fn put_entry(buf: &mut Vec<u8>, kind: u8, key: &[u8], ts: u64, value: &[u8]) {
+    buf.put_u8(kind);
+    put_varint(buf, key.len() as u64);
+    buf.put_slice(key);
+    buf.put_u64(ts);
+    put_varint(buf, value.len() as u64);
+    buf.put_slice(value);
+}
@@ -0,0 +1,147 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
+
+/// The triggers that slow down or stop writes when flush or compaction falls behind, like the
+/// write stalls of RocksDB. A trigger of `None` is disabled.
+#[derive(Debug, Clone)]
+pub struct WriteStallOptions {
+    // Delay writes when there are at least this many immutable memtables
+    pub imm_memtables_slowdown_trigger: Option<usize>,
+    // Stop writes until a flush when there are at least this many immutable memtables
+    pub imm_memtables_stop_trigger: Option<usize>,
+    // Delay writes when a column family has at least this many L0 SSTs
+    pub l0_slowdown_trigger: Option<usize>,
+    // Stop writes until a compaction when a column family has at least this many L0 SSTs
+    pub l0_stop_trigger: Option<usize>,
+    // Delay writes when compaction has to rewrite at least this many bytes of a column family to
+    // bring the levels back to their targets
+    pub pending_compaction_bytes_slowdown_limit: Option<u64>,
+    // Stop writes until a compaction when compaction has to rewrite at least this many bytes
+    pub pending_compaction_bytes_stop_limit: Option<u64>,
+    // How long each write is delayed when writes are slowed down
+    pub slowdown_delay: Duration,
+}
+
+impl Default for WriteStallOptions {
+    fn default() -> Self {
+        Self {
+            imm_memtables_slowdown_trigger: None,
+            imm_memtables_stop_trigger: None,
+            l0_slowdown_trigger: None,
+            l0_stop_trigger: None,
+            pending_compaction_bytes_slowdown_limit: None,
+            pending_compaction_bytes_stop_limit: None,
+            slowdown_delay: Duration::from_millis(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WriteStallCondition {
+    #[default]
+    Normal,
+    /// Each write is delayed by `slowdown_delay`.
+    Delayed,
+    /// Writes are blocked until flush or compaction catches up.
+    Stopped,
+}
+
+impl WriteStallOptions {
+    /// The write stall condition of a column family.
+    pub fn condition(
+        &self,
+        num_imm_memtables: usize,
+        num_l0_sstables: usize,
+        pending_compaction_bytes: u64,
+    ) -> WriteStallCondition {
+        fn reached<T: PartialOrd>(trigger: Option<T>, value: T) -> bool {
+            trigger.is_some_and(|trigger| value >= trigger)
+        }
+        if reached(self.imm_memtables_stop_trigger, num_imm_memtables)
+            || reached(self.l0_stop_trigger, num_l0_sstables)
+            || reached(
+                self.pending_compaction_bytes_stop_limit,
+                pending_compaction_bytes,
+            )
+        {
+            WriteStallCondition::Stopped
+        } else if reached(self.imm_memtables_slowdown_trigger, num_imm_memtables)
+            || reached(self.l0_slowdown_trigger, num_l0_sstables)
+            || reached(
+                self.pending_compaction_bytes_slowdown_limit,
+                pending_compaction_bytes,
+            )
+        {
+            WriteStallCondition::Delayed
+        } else {
+            WriteStallCondition::Normal
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WriteStallStats {
+    /// The current condition.
+    pub condition: WriteStallCondition,
+    /// The number of writes that were delayed.
+    pub delayed_writes: u64,
+    /// The number of writes that were stopped.
+    pub stopped_writes: u64,
+    /// The total time that writes were delayed or stopped.
+    pub stall_time: Duration,
+}
+
+/// Delays and blocks the writers, and wakes them up when a flush or compaction finishes.
+#[derive(Default)]
+pub(crate) struct WriteStall {
+    mutex: Mutex<()>,
+    cvar: Condvar,
+    delayed_writes: AtomicU64,
+    stopped_writes: AtomicU64,
+    stall_micros: AtomicU64,
+}
+
+impl WriteStall {
+    /// Delay or block the writer depending on `condition`, which is checked again every time a
+    /// flush or compaction finishes.
+    pub(crate) fn wait(
+        &self,
+        options: &WriteStallOptions,
+        condition: impl Fn() -> WriteStallCondition,
+    ) {
+        let start = Instant::now();
+        match condition() {
+            WriteStallCondition::Normal => return,
+            WriteStallCondition::Delayed => {
+                self.delayed_writes.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(options.slowdown_delay);
+            }
+            WriteStallCondition::Stopped => {
+                self.stopped_writes.fetch_add(1, Ordering::Relaxed);
+                let mut guard = self.mutex.lock();
+                while condition() == WriteStallCondition::Stopped {
+                    self.cvar.wait(&mut guard);
+                }
+            }
+        }
+        self.stall_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Wake up the stopped writers to check the condition again.
+    pub(crate) fn notify(&self) {
+        let _guard = self.mutex.lock();
+        self.cvar.notify_all();
+    }
+
+    pub(crate) fn stats(&self, condition: WriteStallCondition) -> WriteStallStats {
+        WriteStallStats {
+            condition,
+            delayed_writes: self.delayed_writes.load(Ordering::Relaxed),
+            stopped_writes: self.stopped_writes.load(Ordering::Relaxed),
+            stall_time: Duration::from_micros(self.stall_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
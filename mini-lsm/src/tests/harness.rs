@@ -89,6 +89,25 @@ pub fn as_bytes(x: &[u8]) -> Bytes {
     Bytes::copy_from_slice(x)
 }
 
+#[allow(dead_code)]
+pub fn key_of(idx: usize) -> Bytes {
+    Bytes::from(format!("key_{:05}", idx))
+}
+
+#[allow(dead_code)]
+pub fn scan_pairs(storage: &MiniLsm) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut pairs = Vec::new();
+    while iter.is_valid() {
+        pairs.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    pairs
+}
+
 pub fn check_iter_result_by_key<I>(iter: &mut I, expected: Vec<(Bytes, Bytes)>)
 where
     I: for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,